@@ -0,0 +1,64 @@
+import struct, zlib
+
+def png(width, height, pixels):
+    def chunk(tag, data):
+        return struct.pack(">I", len(data)) + tag + data + struct.pack(">I", zlib.crc32(tag + data))
+    rows = b"".join(b"\0" + bytes(c for p in pixels[y * width:(y + 1) * width] for c in p) for y in range(height))
+    return (b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", struct.pack(">IIBBBBB", width, height, 8, 6, 0, 0, 0))
+            + chunk(b"IDAT", zlib.compress(rows, 9)) + chunk(b"IEND", b""))
+
+# 4 by 4 pixels in quadrants: red, green over blue, white
+R, G, B, W = (255, 0, 0, 255), (0, 255, 0, 255), (0, 0, 255, 255), (255, 255, 255, 255)
+pixels = []
+for y in range(4):
+    for x in range(4):
+        pixels.append([[R, G], [B, W]][y // 2][x // 2])
+picture = png(4, 4, pixels)
+
+UPEM, PPEM = 1000, 20
+glyph = struct.pack(">hh4s", 2, -3, b"png ") + picture
+strike = struct.pack(">HH", PPEM, 72)
+offsets = [4 + 4 * 3, 4 + 4 * 3, 4 + 4 * 3 + len(glyph)]
+strike += b"".join(struct.pack(">I", o) for o in offsets) + glyph
+sbix = struct.pack(">HHII", 1, 1, 1, 12) + strike
+
+head = struct.pack(">IIIIHHqqhhhhHHhhh", 0x00010000, 0x00010000, 0, 0x5F0F3CF5, 0b1011, UPEM,
+                   0, 0, 0, -150, 1000, 850, 0, 8, 2, 0, 0)
+hhea = struct.pack(">IhhhHhhhhhhhhhhhH", 0x00010000, 850, -150, 0, 1000, 0, 0, 1000, 1, 0, 0, 0, 0, 0, 0, 0, 2)
+hmtx = struct.pack(">HhHh", 500, 0, 1000, 0)
+maxp = struct.pack(">IH", 0x00005000, 2)
+# U+1F600 to glyph 1, in a format 12 subtable
+cmap12 = struct.pack(">HHIII", 12, 0, 16 + 12, 0, 1) + struct.pack(">III", 0x1F600, 0x1F600, 1)
+cmap = struct.pack(">HHHHI", 0, 1, 3, 10, 12) + cmap12
+names = [(1, "Sbix Test"), (2, "Regular"), (4, "Sbix Test"), (6, "SbixTest")]
+strings = b""
+records = b""
+for name_id, text in names:
+    data = text.encode("utf-16-be")
+    records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(data), len(strings))
+    strings += data
+name = struct.pack(">HHH", 0, len(names), 6 + 12 * len(names)) + records + strings
+post = struct.pack(">IihhIIIII", 0x00030000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+tables = sorted({b"head": head, b"hhea": hhea, b"hmtx": hmtx, b"maxp": maxp, b"cmap": cmap,
+                 b"name": name, b"post": post, b"sbix": sbix}.items())
+
+def checksum(data):
+    data += b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+count = len(tables)
+power = 1 << (count.bit_length() - 1)
+directory = struct.pack(">IHHHH", 0x00010000, count, power * 16, power.bit_length() - 1, count * 16 - power * 16)
+offset = 12 + 16 * count
+body = b""
+for tag, data in tables:
+    directory += struct.pack(">4sIII", tag, checksum(data), offset + len(body), len(data))
+    body += data + b"\0" * (-len(data) % 4)
+font = bytearray(directory + body)
+# the whole font sums to the magic number
+adjust = (0xB1B0AFBA - checksum(bytes(font))) & 0xFFFFFFFF
+head_offset = offset + sum(len(d) + (-len(d) % 4) for t, d in tables if t < b"head")
+font[head_offset + 8:head_offset + 12] = struct.pack(">I", adjust)
+open("sbix.ttf", "wb").write(font)
+print(len(font))
@@ -0,0 +1,412 @@
+//! Recovering where a photo of the site was taken from, so the art can be laid
+//! out for exactly that spot.
+//!
+//! Points on the floor with measured positions are marked in the photo. The
+//! floor-to-photo homography comes from a linear fit; splitting it with the
+//! camera's focal length gives a first pose, which is then refined by
+//! minimising the reprojection error in pixels.
+//!
+//! Floor points are given as world `(x, z)` on the `y = 0` ground plane. The
+//! camera follows the usual photo convention: x right, y down, z forward.
+
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use glam::{DMat3, DQuat, DVec3, Mat3, Quat, Vec2, Vec3};
+
+use crate::linalg;
+use crate::projection::Homography;
+
+/// Where the focal length comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Focal {
+    /// Known, in pixels, e.g. from the photo's EXIF data.
+    Known(f32),
+    /// From the vanishing points of the floor's x and z axes, which must both
+    /// be visible in perspective. It is refined along with the pose.
+    FromFloor,
+    /// From the vanishing points of two perpendicular directions marked in
+    /// the photo, e.g. with [`vanishing_point`]. Refined along with the pose.
+    FromVanishingPoints(Vec2, Vec2),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationError {
+    /// Fewer than four points, or not as many image points as floor points.
+    TooFewPoints,
+    /// The points don't pin down a homography, e.g. three are collinear.
+    Degenerate,
+    /// The focal length can't be estimated from these vanishing points.
+    NoFocalEstimate,
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::TooFewPoints => write!(f, "need at least four matched points"),
+            CalibrationError::Degenerate => write!(f, "the points do not determine the floor's position"),
+            CalibrationError::NoFocalEstimate => write!(
+                f,
+                "cannot estimate the focal length from the vanishing points; give it explicitly"
+            ),
+        }
+    }
+}
+
+impl Error for CalibrationError {}
+
+/// The camera behind a photo, relative to the floor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    /// Focal length in pixels.
+    pub focal: f32,
+    /// Where the lens axis meets the photo, in pixels.
+    pub principal: Vec2,
+    /// World to camera rotation.
+    pub rotation: Mat3,
+    /// World to camera translation.
+    pub translation: Vec3,
+    /// Root mean square distance between the marked points and where the
+    /// solved camera puts them, in pixels.
+    pub rms_error: f32,
+}
+
+impl Calibration {
+    /// Solves the camera from `image` points (pixels) marking the `floor`
+    /// points. The principal point is taken to be the centre of a photo of
+    /// `image_size` pixels.
+    pub fn solve(
+        image: &[Vec2],
+        floor: &[Vec2],
+        image_size: Vec2,
+        focal: Focal,
+    ) -> Result<Self, CalibrationError> {
+        if image.len() < 4 || image.len() != floor.len() {
+            return Err(CalibrationError::TooFewPoints);
+        }
+        let principal = image_size / 2.0;
+        let homography = Homography::fit(floor, image).ok_or(CalibrationError::Degenerate)?;
+
+        let estimate = match focal {
+            Focal::Known(focal) => focal,
+            Focal::FromFloor => {
+                let vanishing = |direction: Vec3| {
+                    let p = homography.0 * direction;
+                    (p.z.abs() > 1e-9).then(|| p.truncate() / p.z)
+                };
+                let (Some(x), Some(z)) = (vanishing(Vec3::X), vanishing(Vec3::Y)) else {
+                    return Err(CalibrationError::NoFocalEstimate);
+                };
+                focal_from_vanishing_points(x, z, principal).ok_or(CalibrationError::NoFocalEstimate)?
+            }
+            Focal::FromVanishingPoints(a, b) => {
+                focal_from_vanishing_points(a, b, principal).ok_or(CalibrationError::NoFocalEstimate)?
+            }
+        };
+
+        let (rotation, translation) = pose_from_homography(&homography, estimate as f64, principal)
+            .ok_or(CalibrationError::Degenerate)?;
+
+        // refine rotation (as a scaled axis), translation and, unless it was
+        // given, the focal length
+        let refine_focal = !matches!(focal, Focal::Known(_));
+        let mut params: Vec<f64> = DQuat::from_mat3(&rotation).to_scaled_axis().to_array().to_vec();
+        params.extend(translation.to_array());
+        if refine_focal {
+            params.push(estimate as f64);
+        }
+        let unpack = |params: &[f64]| {
+            let rotation = DMat3::from_quat(DQuat::from_scaled_axis(DVec3::new(params[0], params[1], params[2])));
+            let translation = DVec3::new(params[3], params[4], params[5]);
+            let focal = if refine_focal { params[6] } else { estimate as f64 };
+            (rotation, translation, focal)
+        };
+        let residuals = |params: &[f64]| {
+            let (rotation, translation, focal) = unpack(params);
+            image
+                .iter()
+                .zip(floor)
+                .flat_map(|(marked, floor)| {
+                    let world = DVec3::new(floor.x as f64, 0.0, floor.y as f64);
+                    let p = project(rotation, translation, focal, principal, world);
+                    [p.x - marked.x as f64, p.y - marked.y as f64]
+                })
+                .collect::<Vec<f64>>()
+        };
+        let params = linalg::levenberg_marquardt(params, residuals, 100);
+        let r = residuals(&params);
+        let rms_error = (r.iter().map(|v| v * v).sum::<f64>() / image.len() as f64).sqrt() as f32;
+        let (rotation, translation, focal) = unpack(&params);
+
+        Ok(Self {
+            focal: focal as f32,
+            principal,
+            rotation: rotation.as_mat3(),
+            translation: translation.as_vec3(),
+            rms_error,
+        })
+    }
+
+    /// The camera's position in the world.
+    pub fn eye(&self) -> Vec3 {
+        -(self.rotation.transpose() * self.translation)
+    }
+
+    /// The camera's orientation as a Bevy transform rotation (looking down
+    /// local -Z with +Y up).
+    pub fn look_rotation(&self) -> Quat {
+        let to_world = self.rotation.transpose();
+        Quat::from_mat3(&Mat3::from_cols(
+            to_world.x_axis,
+            -to_world.y_axis,
+            -to_world.z_axis,
+        ))
+    }
+
+    /// Vertical field of view, in radians, of a photo `height` pixels high.
+    pub fn fov_y(&self, height: f32) -> f32 {
+        2.0 * (height / 2.0 / self.focal).atan()
+    }
+
+    /// Where `world` appears in the photo, or `None` behind the camera.
+    pub fn project(&self, world: Vec3) -> Option<Vec2> {
+        let camera = self.rotation * world + self.translation;
+        (camera.z > 0.0).then(|| camera.truncate() / camera.z * self.focal + self.principal)
+    }
+
+    /// How far, in pixels, each `image` point is from where the camera puts
+    /// the matching `floor` point. Infinite for points behind the camera.
+    pub fn reprojection_errors(&self, image: &[Vec2], floor: &[Vec2]) -> Vec<f32> {
+        image
+            .iter()
+            .zip(floor)
+            .map(|(marked, floor)| {
+                self.project(Vec3::new(floor.x, 0.0, floor.y))
+                    .map_or(f32::INFINITY, |p| p.distance(*marked))
+            })
+            .collect()
+    }
+
+    /// Writes the calibration as a small text file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let r = self.rotation.transpose().to_cols_array();
+        let t = self.translation;
+        fs::write(path, format!(
+            "# site calibration: camera x right, y down, z forward\n\
+             focal {}\n\
+             principal {} {}\n\
+             rotation {} {} {} {} {} {} {} {} {}\n\
+             translation {} {} {}\n\
+             rms_error {}\n",
+            self.focal,
+            self.principal.x, self.principal.y,
+            r[0], r[1], r[2], r[3], r[4], r[5], r[6], r[7], r[8],
+            t.x, t.y, t.z,
+            self.rms_error,
+        ))
+    }
+
+    /// Reads a calibration written by [`Calibration::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("calibration: {what}"));
+        let field = |name: &str, count: usize| -> io::Result<Vec<f32>> {
+            let line = text
+                .lines()
+                .find(|line| line.split_whitespace().next() == Some(name))
+                .ok_or_else(|| invalid(&format!("missing {name}")))?;
+            let values = line
+                .split_whitespace()
+                .skip(1)
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid(&format!("bad {name}")))?;
+            if values.len() != count {
+                return Err(invalid(&format!("{name} needs {count} values")));
+            }
+            Ok(values)
+        };
+        let principal = field("principal", 2)?;
+        let rotation = field("rotation", 9)?;
+        let translation = field("translation", 3)?;
+        Ok(Self {
+            focal: field("focal", 1)?[0],
+            principal: Vec2::new(principal[0], principal[1]),
+            rotation: Mat3::from_cols_slice(&rotation).transpose(),
+            translation: Vec3::from_slice(&translation),
+            rms_error: field("rms_error", 1)?[0],
+        })
+    }
+}
+
+fn project(rotation: DMat3, translation: DVec3, focal: f64, principal: Vec2, world: DVec3) -> DVec3 {
+    let camera = rotation * world + translation;
+    DVec3::new(
+        camera.x / camera.z * focal + principal.x as f64,
+        camera.y / camera.z * focal + principal.y as f64,
+        camera.z,
+    )
+}
+
+/// Splits a floor-to-photo homography into a camera rotation and translation,
+/// given the focal length.
+fn pose_from_homography(homography: &Homography, focal: f64, principal: Vec2) -> Option<(DMat3, DVec3)> {
+    let h = homography.0.as_dmat3();
+    let to_camera = |column: DVec3| {
+        DVec3::new(
+            (column.x - principal.x as f64 * column.z) / focal,
+            (column.y - principal.y as f64 * column.z) / focal,
+            column.z,
+        )
+    };
+    // columns map the floor's x axis, its z axis and its origin
+    let (mut x, mut z, mut t) = (to_camera(h.x_axis), to_camera(h.y_axis), to_camera(h.z_axis));
+    let scale = 2.0 / (x.length() + z.length());
+    if !scale.is_finite() {
+        return None;
+    }
+    x *= scale;
+    z *= scale;
+    t *= scale;
+    // the floor has to be in front of the camera
+    if t.z < 0.0 {
+        x = -x;
+        z = -z;
+        t = -t;
+    }
+    let x = x.try_normalize()?;
+    let z = (z - x * z.dot(x)).try_normalize()?;
+    let y = z.cross(x);
+    Some((DMat3::from_cols(x, y, z), t))
+}
+
+/// Focal length, in pixels, of a camera that sees two perpendicular
+/// directions vanish at `a` and `b`. `None` if they can't be perpendicular
+/// for any focal length.
+pub fn focal_from_vanishing_points(a: Vec2, b: Vec2, principal: Vec2) -> Option<f32> {
+    let squared = -(a - principal).dot(b - principal);
+    (squared > 0.0).then(|| squared.sqrt())
+}
+
+/// The least squares meeting point of lines that are parallel in the world,
+/// each given by two points in the photo. `None` for fewer than two lines or
+/// lines that are parallel in the photo too.
+pub fn vanishing_point(lines: &[(Vec2, Vec2)]) -> Option<Vec2> {
+    if lines.len() < 2 {
+        return None;
+    }
+    // each line as n . p = c, with unit normal n
+    let (a, b): (Vec<Vec<f64>>, Vec<f64>) = lines
+        .iter()
+        .filter_map(|(p, q)| {
+            let direction = (*q - *p).try_normalize()?;
+            let normal = direction.perp();
+            Some((vec![normal.x as f64, normal.y as f64], normal.dot(*p) as f64))
+        })
+        .unzip();
+    let point = linalg::least_squares(&a, &b)?;
+    Some(Vec2::new(point[0] as f32, point[1] as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Vec2 = Vec2::new(1600.0, 1200.0);
+
+    /// A camera at `eye` looking at `target`, upright.
+    fn camera(eye: Vec3, target: Vec3, focal: f32) -> Calibration {
+        let forward = (target - eye).normalize();
+        let right = forward.cross(Vec3::Y).normalize();
+        let down = forward.cross(right);
+        let rotation = Mat3::from_cols(right, down, forward).transpose();
+        Calibration {
+            focal,
+            principal: SIZE / 2.0,
+            rotation,
+            translation: -(rotation * eye),
+            rms_error: 0.0,
+        }
+    }
+
+    /// A metre grid of floor points, and where `camera` sees them.
+    fn marked(camera: &Calibration) -> (Vec<Vec2>, Vec<Vec2>) {
+        let floor: Vec<Vec2> = (-2..=2)
+            .flat_map(|x| (-1..=2).map(move |z| Vec2::new(x as f32, z as f32)))
+            .collect();
+        let image = floor.iter().map(|p| camera.project(Vec3::new(p.x, 0.0, p.y)).unwrap()).collect();
+        (image, floor)
+    }
+
+    #[test]
+    fn solve_recovers_a_synthetic_pose() {
+        let truth = camera(Vec3::new(1.0, 1.6, 6.0), Vec3::new(0.0, 0.0, 0.5), 1100.0);
+        let (image, floor) = marked(&truth);
+        for focal in [Focal::Known(1100.0), Focal::FromFloor] {
+            let solved = Calibration::solve(&image, &floor, SIZE, focal).unwrap();
+            assert!(solved.eye().distance(truth.eye()) < 1e-2, "{focal:?}: eye at {}", solved.eye());
+            assert!((solved.focal - truth.focal).abs() < 1.0, "{focal:?}: focal {}", solved.focal);
+            assert!(solved.rotation.abs_diff_eq(truth.rotation, 1e-3));
+            assert!(solved.rms_error < 0.05);
+        }
+    }
+
+    #[test]
+    fn noisy_marks_still_land_near_the_pose() {
+        let truth = camera(Vec3::new(-0.5, 1.7, 5.0), Vec3::ZERO, 900.0);
+        let (mut image, floor) = marked(&truth);
+        // a click or so out either way
+        for (i, p) in image.iter_mut().enumerate() {
+            *p += Vec2::new((i % 3) as f32 - 1.0, (i % 2) as f32 * 2.0 - 1.0);
+        }
+        let solved = Calibration::solve(&image, &floor, SIZE, Focal::Known(900.0)).unwrap();
+        assert!(solved.eye().distance(truth.eye()) < 0.05, "eye at {}", solved.eye());
+        assert!(solved.rms_error > 0.0 && solved.rms_error < 2.0);
+    }
+
+    #[test]
+    fn solve_needs_four_points_in_general_position() {
+        let truth = camera(Vec3::new(0.0, 1.6, 5.0), Vec3::ZERO, 1000.0);
+        let (image, floor) = marked(&truth);
+        assert_eq!(
+            Calibration::solve(&image[..3], &floor[..3], SIZE, Focal::Known(1000.0)),
+            Err(CalibrationError::TooFewPoints)
+        );
+        assert_eq!(
+            Calibration::solve(&image, &floor[1..], SIZE, Focal::Known(1000.0)),
+            Err(CalibrationError::TooFewPoints)
+        );
+        let line: Vec<Vec2> = (0..5).map(|x| Vec2::new(x as f32, 0.0)).collect();
+        let seen: Vec<Vec2> = line.iter().map(|p| truth.project(Vec3::new(p.x, 0.0, p.y)).unwrap()).collect();
+        assert_eq!(Calibration::solve(&seen, &line, SIZE, Focal::Known(1000.0)), Err(CalibrationError::Degenerate));
+    }
+
+    #[test]
+    fn vanishing_points_give_the_focal_length() {
+        let truth = camera(Vec3::new(1.0, 1.6, 6.0), Vec3::new(0.0, 0.0, 0.5), 1100.0);
+        // lines along the floor's x and z axes
+        let along = |direction: Vec3| -> Vec<(Vec2, Vec2)> {
+            (0..3)
+                .map(|i| {
+                    let start = Vec3::new(i as f32 - 1.0, 0.0, i as f32 - 1.0);
+                    (truth.project(start).unwrap(), truth.project(start + direction).unwrap())
+                })
+                .collect()
+        };
+        let a = vanishing_point(&along(Vec3::X)).unwrap();
+        let b = vanishing_point(&along(Vec3::Z)).unwrap();
+        let focal = focal_from_vanishing_points(a, b, truth.principal).unwrap();
+        assert!((focal - 1100.0).abs() < 1.0, "{focal}");
+    }
+
+    #[test]
+    fn calibrations_round_trip_through_a_file() {
+        let calibration = camera(Vec3::new(0.3, 1.5, 4.0), Vec3::ZERO, 1000.0);
+        let path = std::env::temp_dir().join(format!("calibration-{}.txt", std::process::id()));
+        calibration.save(&path).unwrap();
+        let loaded = Calibration::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.focal, calibration.focal);
+        assert!(loaded.rotation.abs_diff_eq(calibration.rotation, 1e-6));
+        assert!(loaded.eye().distance(calibration.eye()) < 1e-5);
+    }
+}
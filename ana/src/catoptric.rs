@@ -0,0 +1,307 @@
+//! Catoptric anamorphosis: a distorted ring on the floor that resolves in a
+//! reflective cylinder or cone standing in its middle.
+//!
+//! Sight lines from the eye through the picture bounce off the mirror and land
+//! on the floor. That mapping isn't projective, so it is sampled on a dense
+//! grid over the picture and the floor image is filled from the resulting
+//! triangles.
+
+use glam::{Vec2, Vec3};
+use image::RgbaImage;
+
+use crate::lookup::TriangleLookup;
+use crate::projection::PlaneRect;
+use crate::warp::{self, WarpOptions};
+
+/// A mirror standing upright on the floor, `base` being the centre of its
+/// footprint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirror {
+    Cylinder { base: Vec3, radius: f32, height: f32 },
+    /// Stands on its base, apex up.
+    Cone { base: Vec3, radius: f32, height: f32 },
+}
+
+impl Mirror {
+    pub fn base(&self) -> Vec3 {
+        match *self {
+            Mirror::Cylinder { base, .. } | Mirror::Cone { base, .. } => base,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Mirror::Cylinder { radius, .. } | Mirror::Cone { radius, .. } => radius,
+        }
+    }
+
+    /// First hit of the ray with the reflective surface, as `t` and the
+    /// outward surface normal there.
+    pub fn intersect_ray(&self, origin: Vec3, direction: Vec3) -> Option<(f32, Vec3)> {
+        match *self {
+            Mirror::Cylinder { base, radius, height } => {
+                let o = origin - base;
+                let a = direction.x * direction.x + direction.z * direction.z;
+                let b = 2.0 * (o.x * direction.x + o.z * direction.z);
+                let c = o.x * o.x + o.z * o.z - radius * radius;
+                // the cylinder is solid: a sight line that enters over the
+                // rim hits the top, not the far wall
+                positive_roots(a, b, c)
+                    .first()
+                    .copied()
+                    .filter(|t| (0.0..=height).contains(&(o.y + t * direction.y)))
+                    .map(|t| {
+                        let p = o + direction * t;
+                        (t, Vec3::new(p.x, 0.0, p.z).normalize())
+                    })
+            }
+            Mirror::Cone { base, radius, height } => {
+                let o = origin - base;
+                let k2 = (radius / height).powi(2);
+                let a = direction.x * direction.x + direction.z * direction.z
+                    - k2 * direction.y * direction.y;
+                let b = 2.0 * (o.x * direction.x + o.z * direction.z + k2 * (height - o.y) * direction.y);
+                let c = o.x * o.x + o.z * o.z - k2 * (height - o.y).powi(2);
+                // the quadratic also describes the mirror image of the cone
+                // above its apex, so skip hits outside the real one. The apex
+                // itself has no normal to reflect off.
+                positive_roots(a, b, c)
+                    .into_iter()
+                    .find(|t| (0.0..=height).contains(&(o.y + t * direction.y)))
+                    .and_then(|t| {
+                        let p = o + direction * t;
+                        let normal = Vec3::new(p.x, k2 * (height - p.y), p.z).try_normalize()?;
+                        Some((t, normal))
+                    })
+            }
+        }
+    }
+
+    /// A picture rectangle on the mirror's axis, square-on to the eye, where
+    /// the artwork should appear. `fill` is the fraction of the mirror's width
+    /// (and, for a cylinder, height) that the picture takes up.
+    pub fn picture(&self, eye: Vec3, fill: f32) -> PlaneRect {
+        match *self {
+            Mirror::Cylinder { base, radius, height } => {
+                let center = base + Vec3::Y * height / 2.0;
+                PlaneRect::facing(eye, center, Vec2::new(2.0 * radius, height) * fill)
+            }
+            Mirror::Cone { base, radius, .. } => {
+                let width = 2.0 * radius * fill;
+                PlaneRect::facing(eye, base, Vec2::new(width, width / 2.0))
+            }
+        }
+    }
+}
+
+/// Roots `t > 0` of `a t^2 + b t + c`, ascending.
+fn positive_roots(a: f32, b: f32, c: f32) -> Vec<f32> {
+    let roots = if a.abs() < 1e-9 {
+        if b.abs() < 1e-9 {
+            return Vec::new();
+        }
+        vec![-c / b]
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return Vec::new();
+        }
+        let sqrt = discriminant.sqrt();
+        let (t0, t1) = ((-b - sqrt) / (2.0 * a), (-b + sqrt) / (2.0 * a));
+        vec![t0.min(t1), t0.max(t1)]
+    };
+    roots.into_iter().filter(|&t| t > 1e-6).collect()
+}
+
+/// A solved eye / picture / mirror / floor arrangement.
+#[derive(Clone, Copy, Debug)]
+pub struct CatoptricLayout {
+    pub eye: Vec3,
+    pub mirror: Mirror,
+    pub picture: PlaneRect,
+    /// The printed area around the mirror.
+    pub floor: PlaneRect,
+}
+
+impl CatoptricLayout {
+    /// Grid steps across the picture used to sample the reflection mapping.
+    const GRID: u32 = 256;
+
+    pub fn new(eye: Vec3, mirror: Mirror, picture: PlaneRect, floor: PlaneRect) -> Self {
+        Self {
+            eye,
+            mirror,
+            picture,
+            floor,
+        }
+    }
+
+    /// Where the sight line through `picture_coords` lands on the floor after
+    /// bouncing off the mirror, in floor coordinates.
+    pub fn floor_coords(&self, picture_coords: Vec2) -> Option<Vec2> {
+        let direction = self.picture.point_at(picture_coords) - self.eye;
+        let (t, normal) = self.mirror.intersect_ray(self.eye, direction)?;
+        let hit = self.eye + direction * t;
+        let reflected = direction - 2.0 * direction.dot(normal) * normal;
+        let (_, coords) = self.floor.intersect_ray(hit, reflected)?;
+        Some(coords)
+    }
+
+    /// Produces the floor image at `pixels_per_metre`, covering the whole
+    /// floor rectangle. `artwork` is stretched over the picture.
+    pub fn render(&self, artwork: &RgbaImage, pixels_per_metre: f32, options: WarpOptions) -> RgbaImage {
+        let width = (self.floor.size.x * pixels_per_metre).round().max(1.0) as u32;
+        let height = (self.floor.size.y * pixels_per_metre).round().max(1.0) as u32;
+
+        // sample the mapping on a grid over the picture, in floor pixels
+        let n = Self::GRID;
+        let step = self.picture.size / n as f32;
+        let mut vertex = vec![None; ((n + 1) * (n + 1)) as usize];
+        let mut points = Vec::new();
+        let mut picture_coords = Vec::new();
+        for j in 0..=n {
+            for i in 0..=n {
+                let coords = Vec2::new(i as f32, j as f32) * step;
+                if let Some(floor_coords) = self.floor_coords(coords) {
+                    vertex[(j * (n + 1) + i) as usize] = Some(points.len() as u32);
+                    points.push(floor_coords * pixels_per_metre);
+                    picture_coords.push(coords);
+                }
+            }
+        }
+
+        // grid cells whose corners all reach the floor, skipping the ones
+        // torn apart where sight lines graze the mirror's silhouette
+        let max_edge = (width.max(height) as f32) / 8.0;
+        let mut triangles = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let corner = |di: u32, dj: u32| vertex[((j + dj) * (n + 1) + i + di) as usize];
+                let (Some(a), Some(b), Some(c), Some(d)) =
+                    (corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1))
+                else {
+                    continue;
+                };
+                for triangle in [[a, b, c], [a, c, d]] {
+                    let [p, q, r] = triangle.map(|v| points[v as usize]);
+                    if p.distance(q).max(q.distance(r)).max(r.distance(p)) < max_edge {
+                        triangles.push(triangle);
+                    }
+                }
+            }
+        }
+
+        let lookup = TriangleLookup::new(points, triangles, 128);
+        let artwork_scale = Vec2::new(artwork.width() as f32, artwork.height() as f32)
+            / self.picture.size;
+        warp::warp(artwork, width, height, options, |p| {
+            let ([a, b, c], weights) = lookup.locate(p)?;
+            let coords = picture_coords[a as usize] * weights.x
+                + picture_coords[b as usize] * weights.y
+                + picture_coords[c as usize] * weights.z;
+            Some(coords * artwork_scale)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CYLINDER: Mirror = Mirror::Cylinder {
+        base: Vec3::ZERO,
+        radius: 0.5,
+        height: 1.0,
+    };
+    const CONE: Mirror = Mirror::Cone {
+        base: Vec3::ZERO,
+        radius: 1.0,
+        height: 2.0,
+    };
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn rays_at_the_axis_hit_the_near_side() {
+        let (t, normal) = CYLINDER.intersect_ray(Vec3::new(0.0, 0.5, 3.0), Vec3::NEG_Z).unwrap();
+        assert!((t - 2.5).abs() < 1e-5);
+        assert_close(normal, Vec3::Z);
+
+        // halfway up the cone its radius is halved, and its slope tilts the
+        // normal upwards
+        let (t, normal) = CONE.intersect_ray(Vec3::new(0.0, 1.0, 3.0), Vec3::NEG_Z).unwrap();
+        assert!((t - 2.5).abs() < 1e-5);
+        assert_close(normal, Vec3::new(0.0, 1.0, 2.0).normalize());
+
+        // over the rim, beside the mirror or onto the apex, there is nothing
+        // to reflect off
+        assert!(CYLINDER.intersect_ray(Vec3::new(0.0, 1.5, 3.0), Vec3::NEG_Z).is_none());
+        assert!(CONE.intersect_ray(Vec3::new(2.0, 1.0, 3.0), Vec3::NEG_Z).is_none());
+        assert!(CONE.intersect_ray(Vec3::new(0.0, 3.0, 0.0), Vec3::NEG_Y).is_none());
+    }
+
+    #[test]
+    fn cone_takes_the_hit_on_the_real_cone() {
+        // straight down, a quarter off the axis: the quadratic first meets the
+        // mirror image of the cone above the apex at y = 2.5, then the cone
+        // itself at y = 1.5
+        let (t, normal) = CONE.intersect_ray(Vec3::new(0.0, 10.0, 0.25), Vec3::NEG_Y).unwrap();
+        assert!((t - 8.5).abs() < 1e-4, "{t}");
+        assert_close(normal, Vec3::new(0.0, 1.0, 2.0).normalize());
+
+        // straight through, the near side comes first
+        let (t, _) = CONE.intersect_ray(Vec3::new(0.0, 1.0, -3.0), Vec3::Z).unwrap();
+        assert!((t - 2.5).abs() < 1e-5, "{t}");
+    }
+
+    #[test]
+    fn floor_points_reflect_back_onto_the_sight_line() {
+        let layouts = [
+            (CYLINDER, Vec3::new(0.0, 2.0, 2.0)),
+            (CONE, Vec3::new(0.0, 6.0, 0.0)),
+        ]
+        .map(|(mirror, eye)| {
+            let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(12.0));
+            CatoptricLayout::new(eye, mirror, mirror.picture(eye, 0.8), floor)
+        });
+        for layout in layouts {
+            let mut reflected = 0;
+            for j in 0..=8 {
+                for i in 0..=8 {
+                    let coords = layout.picture.size * Vec2::new(i as f32, j as f32) / 8.0;
+                    let Some(floor_coords) = layout.floor_coords(coords) else {
+                        continue;
+                    };
+                    reflected += 1;
+                    let floor = layout.floor.point_at(floor_coords);
+                    let sight = layout.picture.point_at(coords) - layout.eye;
+                    let (t, _) = layout.mirror.intersect_ray(layout.eye, sight).unwrap();
+                    let hit = layout.eye + sight * t;
+
+                    // the hit is on the mirror, and its normal is the
+                    // surface's gradient there
+                    let normal = match layout.mirror {
+                        Mirror::Cylinder { radius, .. } => {
+                            assert!((hit.x.hypot(hit.z) - radius).abs() < 1e-4);
+                            Vec3::new(hit.x, 0.0, hit.z).normalize()
+                        }
+                        Mirror::Cone { radius, height, .. } => {
+                            let k = radius / height;
+                            assert!((hit.x.hypot(hit.z) - k * (height - hit.y)).abs() < 1e-4);
+                            Vec3::new(hit.x, k * k * (height - hit.y), hit.z).normalize()
+                        }
+                    };
+
+                    // the floor point's image in the mirror's tangent plane
+                    // lies on the sight line
+                    let image = floor - 2.0 * (floor - hit).dot(normal) * normal;
+                    let along = (image - layout.eye).normalize().dot(sight.normalize());
+                    assert!(along > 1.0 - 1e-5, "{coords}: {along}");
+                }
+            }
+            assert!(reflected > 20, "only {reflected} sight lines reached the floor");
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! Finding a printed checkerboard in a site photo, so the floor points for
+//! [`Calibration`](crate::calibration::Calibration) don't have to be clicked
+//! by hand.
+//!
+//! Inner corners of the board are saddle points of the brightness, where the
+//! Hessian's determinant is negative. Candidates that also show the
+//! light/dark/light/dark pattern around them are refined to sub-pixel
+//! accuracy and then linked up into a grid by growing it from a seed corner,
+//! each step predicted from the neighbours already found, so perspective
+//! doesn't get in the way.
+//!
+//! The board's squares should be at least a dozen pixels across in the photo.
+
+use std::collections::{HashMap, VecDeque};
+
+use glam::{IVec2, Vec2};
+use image::{ImageBuffer, Luma, RgbaImage};
+use imageproc::filter::gaussian_blur_f32;
+
+/// A printed board, counted by its inner corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checkerboard {
+    pub columns: u32,
+    pub rows: u32,
+    /// Side of a square, in metres.
+    pub square: f32,
+}
+
+/// The board's inner corners as found in a photo.
+///
+/// Corners are stored row by row. The first is the top left one as seen in
+/// the photo; columns run to the right and rows towards the camera, which
+/// on the floor is +X and +Z. The board may be found turned a quarter turn
+/// from how it was described, in which case `columns` and `rows` swap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CornerGrid {
+    pub columns: u32,
+    pub rows: u32,
+    /// Sub-pixel positions in the photo.
+    pub corners: Vec<Vec2>,
+}
+
+impl CornerGrid {
+    /// Floor positions, as world `(x, z)`, of the corners of a board with
+    /// squares `square` metres across whose first corner is at `origin`.
+    pub fn floor_points(&self, square: f32, origin: Vec2) -> Vec<Vec2> {
+        (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| Vec2::new(column as f32, row as f32)))
+            .map(|cell| origin + cell * square)
+            .collect()
+    }
+}
+
+type Gray = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Blur applied before looking for saddle points, in pixels.
+const SIGMA: f32 = 1.5;
+/// Radius of the circle sampled around a candidate to check for the
+/// alternating pattern, and of the sub-pixel refinement window.
+const RADIUS: f32 = 5.0;
+/// The least difference in grey level, out of 255, between the light and dark
+/// squares around a corner.
+const MIN_CONTRAST: f32 = 30.0;
+/// How far a corner may be from where the grid predicts it, as a fraction of
+/// the step between neighbours.
+const GRID_TOLERANCE: f32 = 0.35;
+/// How far off a board line through the seed, as the sine of the angle, its
+/// first neighbours may be.
+const EDGE_TOLERANCE: f32 = 0.2;
+/// Seed corners tried before giving up.
+const SEEDS: usize = 25;
+
+/// Finds `board` in `photo`. `None` if no complete grid of its size is found,
+/// or the board has fewer than two corners either way.
+pub fn find_corners(photo: &RgbaImage, board: &Checkerboard) -> Option<CornerGrid> {
+    if board.columns < 2 || board.rows < 2 {
+        return None;
+    }
+    let gray = Gray::from_fn(photo.width(), photo.height(), |x, y| {
+        let p = photo.get_pixel(x, y);
+        Luma([0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32])
+    });
+    let blurred = gaussian_blur_f32(&gray, SIGMA);
+    let (candidates, edges): (Vec<Vec2>, Vec<[Vec2; 2]>) = saddle_points(&blurred)
+        .into_iter()
+        .filter_map(|p| refine(&blurred, p))
+        .filter_map(|p| Some((p, checker_edges(&blurred, p)?)))
+        .unzip();
+
+    let centroid = candidates.iter().sum::<Vec2>() / candidates.len().max(1) as f32;
+    let mut seeds: Vec<usize> = (0..candidates.len()).collect();
+    seeds.sort_by(|&a, &b| {
+        candidates[a].distance_squared(centroid).total_cmp(&candidates[b].distance_squared(centroid))
+    });
+    seeds
+        .into_iter()
+        .take(SEEDS)
+        .filter_map(|seed| grow_grid(&candidates, seed, edges[seed]))
+        .find_map(|grid| to_corner_grid(&candidates, &grid, board))
+}
+
+fn at(image: &Gray, x: i64, y: i64) -> f32 {
+    let x = x.clamp(0, image.width() as i64 - 1) as u32;
+    let y = y.clamp(0, image.height() as i64 - 1) as u32;
+    image.get_pixel(x, y)[0]
+}
+
+fn bilinear(image: &Gray, p: Vec2) -> f32 {
+    let (x, y) = (p.x.floor(), p.y.floor());
+    let (fx, fy) = (p.x - x, p.y - y);
+    let (x, y) = (x as i64, y as i64);
+    let top = at(image, x, y) * (1.0 - fx) + at(image, x + 1, y) * fx;
+    let bottom = at(image, x, y + 1) * (1.0 - fx) + at(image, x + 1, y + 1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Pixels where the brightness is a strong local saddle.
+fn saddle_points(image: &Gray) -> Vec<Vec2> {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let mut response = vec![0.0f32; (width * height) as usize];
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let c = at(image, x, y);
+            let xx = at(image, x + 1, y) - 2.0 * c + at(image, x - 1, y);
+            let yy = at(image, x, y + 1) - 2.0 * c + at(image, x, y - 1);
+            let xy = (at(image, x + 1, y + 1) - at(image, x - 1, y + 1)
+                - at(image, x + 1, y - 1) + at(image, x - 1, y - 1)) / 4.0;
+            response[(y * width + x) as usize] = (xy * xy - xx * yy).max(0.0);
+        }
+    }
+
+    let threshold = response.iter().copied().fold(0.0, f32::max) * 0.02;
+    let radius = RADIUS as i64;
+    let mut points = vec![];
+    for y in radius..height - radius {
+        for x in radius..width - radius {
+            let value = response[(y * width + x) as usize];
+            if value <= threshold {
+                continue;
+            }
+            // strictly greater than earlier pixels, at least as great as later
+            // ones, so a plateau keeps exactly one
+            let is_peak = (-radius..=radius).all(|dy| {
+                (-radius..=radius).all(|dx| {
+                    let other = response[((y + dy) * width + x + dx) as usize];
+                    match (dy, dx).cmp(&(0, 0)) {
+                        std::cmp::Ordering::Less => value > other,
+                        std::cmp::Ordering::Equal => true,
+                        std::cmp::Ordering::Greater => value >= other,
+                    }
+                })
+            });
+            if is_peak {
+                points.push(Vec2::new(x as f32, y as f32));
+            }
+        }
+    }
+    points
+}
+
+/// The directions of the two board lines crossing at `p`, if the brightness
+/// around it goes light, dark, light, dark.
+fn checker_edges(image: &Gray, p: Vec2) -> Option<[Vec2; 2]> {
+    const SAMPLES: usize = 48;
+    let step = std::f32::consts::TAU / SAMPLES as f32;
+    let ring: Vec<f32> = (0..SAMPLES)
+        .map(|i| bilinear(image, p + Vec2::from_angle(i as f32 * step) * RADIUS))
+        .collect();
+    let (min, max) = ring.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    if max - min < MIN_CONTRAST {
+        return None;
+    }
+    let middle = (min + max) / 2.0;
+    let crossings: Vec<Vec2> = (0..SAMPLES)
+        .filter_map(|i| {
+            let (a, b) = (ring[i] - middle, ring[(i + 1) % SAMPLES] - middle);
+            ((a > 0.0) != (b > 0.0)).then(|| Vec2::from_angle((i as f32 + a / (a - b)) * step))
+        })
+        .collect();
+    if crossings.len() != 4 {
+        return None;
+    }
+    // opposite crossings lie on the same line
+    Some([
+        (crossings[0] - crossings[2]).normalize(),
+        (crossings[1] - crossings[3]).normalize(),
+    ])
+}
+
+/// Moves `p` to the point every image gradient nearby is perpendicular to,
+/// which for a checker corner is the corner itself. `None` if it wanders off.
+fn refine(image: &Gray, start: Vec2) -> Option<Vec2> {
+    let radius = RADIUS as i32;
+    let mut p = start;
+    for _ in 0..20 {
+        let (mut a, mut b, mut c) = (0.0, 0.0, 0.0);
+        let mut rhs = Vec2::ZERO;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let offset = Vec2::new(dx as f32, dy as f32);
+                let q = p + offset;
+                let gradient = Vec2::new(
+                    bilinear(image, q + Vec2::X) - bilinear(image, q - Vec2::X),
+                    bilinear(image, q + Vec2::Y) - bilinear(image, q - Vec2::Y),
+                ) / 2.0;
+                let weight = (-offset.length_squared() / (RADIUS * RADIUS)).exp();
+                let (gxx, gxy, gyy) = (
+                    gradient.x * gradient.x * weight,
+                    gradient.x * gradient.y * weight,
+                    gradient.y * gradient.y * weight,
+                );
+                a += gxx;
+                b += gxy;
+                c += gyy;
+                rhs += Vec2::new(gxx * q.x + gxy * q.y, gxy * q.x + gyy * q.y);
+            }
+        }
+        let determinant = a * c - b * b;
+        if determinant.abs() < 1e-6 {
+            return None;
+        }
+        let next = Vec2::new(c * rhs.x - b * rhs.y, a * rhs.y - b * rhs.x) / determinant;
+        let moved = next.distance(p);
+        p = next;
+        if p.distance(start) > RADIUS {
+            return None;
+        }
+        if moved < 0.01 {
+            break;
+        }
+    }
+    Some(p)
+}
+
+/// Links corners into a grid, starting from `seed` and its nearest
+/// neighbours along the two board lines through it. Cells map to candidate
+/// indices.
+fn grow_grid(candidates: &[Vec2], seed: usize, edges: [Vec2; 2]) -> Option<HashMap<IVec2, usize>> {
+    let origin = candidates[seed];
+    let along = |edge: Vec2| {
+        (0..candidates.len())
+            .filter(|&i| i != seed)
+            .filter(|&i| {
+                let offset = candidates[i] - origin;
+                edge.perp_dot(offset).abs() < offset.length() * EDGE_TOLERANCE
+            })
+            .min_by(|&a, &b| {
+                candidates[a].distance_squared(origin).total_cmp(&candidates[b].distance_squared(origin))
+            })
+    };
+    let (first, second) = (along(edges[0])?, along(edges[1])?);
+
+    let mut grid = HashMap::from([(IVec2::ZERO, seed), (IVec2::X, first), (IVec2::Y, second)]);
+    let mut used = vec![false; candidates.len()];
+    for &i in grid.values() {
+        used[i] = true;
+    }
+    let mut queue: VecDeque<IVec2> = grid.keys().copied().collect();
+    let position = |grid: &HashMap<IVec2, usize>, cell: IVec2| grid.get(&cell).map(|&i| candidates[i]);
+
+    while let Some(cell) = queue.pop_front() {
+        let here = candidates[grid[&cell]];
+        for direction in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let next = cell + direction;
+            if grid.contains_key(&next) {
+                continue;
+            }
+            // carry on in a straight line, or copy a neighbouring row's step
+            let side = direction.perp();
+            let step = position(&grid, cell - direction)
+                .map(|behind| here - behind)
+                .or_else(|| {
+                    [cell + side, cell - side].into_iter().find_map(|beside| {
+                        Some(position(&grid, beside + direction)? - position(&grid, beside)?)
+                    })
+                });
+            let Some(step) = step else {
+                continue;
+            };
+            let predicted = here + step;
+            let tolerance = step.length() * GRID_TOLERANCE;
+            let found = (0..candidates.len())
+                .filter(|&i| !used[i])
+                .map(|i| (i, candidates[i].distance(predicted)))
+                .filter(|(_, distance)| *distance < tolerance)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((i, _)) = found {
+                used[i] = true;
+                grid.insert(next, i);
+                queue.push_back(next);
+            }
+        }
+    }
+    Some(grid)
+}
+
+/// Checks the grid is complete and the size of `board`, and orders it as
+/// [`CornerGrid`] describes.
+fn to_corner_grid(candidates: &[Vec2], grid: &HashMap<IVec2, usize>, board: &Checkerboard) -> Option<CornerGrid> {
+    // stray corners just off the board can give the grid a ragged edge, so
+    // look for the one complete window the size of the board
+    let low = grid.keys().fold(IVec2::MAX, |a, b| a.min(*b));
+    let high = grid.keys().fold(IVec2::MIN, |a, b| a.max(*b));
+    let mut sizes = vec![IVec2::new(board.columns as i32, board.rows as i32)];
+    if board.columns != board.rows {
+        sizes.push(IVec2::new(board.rows as i32, board.columns as i32));
+    }
+    let complete = |min: IVec2, size: IVec2| {
+        (0..size.y).all(|y| (0..size.x).all(|x| grid.contains_key(&(min + IVec2::new(x, y)))))
+    };
+    let mut windows = sizes.into_iter().flat_map(|size| {
+        (low.y..=high.y - size.y + 1)
+            .flat_map(move |y| (low.x..=high.x - size.x + 1).map(move |x| (IVec2::new(x, y), size)))
+            .filter(|&(min, size)| complete(min, size))
+    });
+    let (min, size) = windows.next()?;
+    if windows.next().is_some() {
+        return None;
+    }
+    let size = size.as_uvec2();
+
+    // the average step along each grid axis in the photo
+    let point = |cell: IVec2| candidates[grid[&(cell + min)]];
+    let axis_step = |axis: IVec2, count: u32| {
+        let far = point(axis * (count as i32 - 1));
+        (far - point(IVec2::ZERO)) / (count - 1) as f32
+    };
+    let (x_step, y_step) = (axis_step(IVec2::X, size.x), axis_step(IVec2::Y, size.y));
+
+    // columns along whichever axis runs more across the photo, to the right,
+    // and rows turning clockwise from them, so the board isn't mirrored
+    let across = |step: Vec2| step.x.abs() / step.length();
+    let swap = across(y_step) > across(x_step);
+    let (column_step, row_step) = if swap { (y_step, x_step) } else { (x_step, y_step) };
+    let flip_columns = column_step.x < 0.0;
+    let flip_rows = (column_step.perp_dot(row_step) < 0.0) != flip_columns;
+    let (columns, rows) = if swap { (size.y, size.x) } else { (size.x, size.y) };
+
+    let corners = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let column = if flip_columns { columns - 1 - column } else { column };
+            let row = if flip_rows { rows - 1 - row } else { row };
+            let cell = if swap { IVec2::new(row as i32, column as i32) } else { IVec2::new(column as i32, row as i32) };
+            // pixel (i, j) covers [i, i + 1) x [j, j + 1), so its centre is
+            // half a pixel in
+            point(cell) + 0.5
+        })
+        .collect();
+    Some(CornerGrid { columns, rows, corners })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::Homography;
+    use image::Rgba;
+
+    /// Squares across the synthetic boards, in pixels.
+    const SQUARE: f32 = 20.0;
+
+    /// A board with `columns` by `rows` inner corners, on a square's width
+    /// of white paper, on a grey floor. `to_photo` takes board coordinates,
+    /// in squares from the paper's top left corner, to continuous photo
+    /// coordinates. Pixels are averaged over a 4 by 4 grid of samples.
+    fn photo(columns: u32, rows: u32, to_photo: &Homography) -> RgbaImage {
+        let from_photo = to_photo.inverse().unwrap();
+        let paper = Vec2::new(columns as f32 + 3.0, rows as f32 + 3.0);
+        RgbaImage::from_fn(320, 240, |x, y| {
+            let mut total = 0.0f32;
+            for sy in 0..4 {
+                for sx in 0..4 {
+                    let sample = Vec2::new(x as f32 + (sx as f32 + 0.5) / 4.0, y as f32 + (sy as f32 + 0.5) / 4.0);
+                    let p = from_photo.apply(sample).unwrap();
+                    total += if p.cmplt(Vec2::ZERO).any() || p.cmpge(paper).any() {
+                        128.0
+                    } else if p.cmplt(Vec2::ONE).any() || p.cmpge(paper - 1.0).any() {
+                        255.0
+                    } else {
+                        let square = (p - 1.0).floor();
+                        if (square.x + square.y) as i32 % 2 == 0 { 0.0 } else { 255.0 }
+                    };
+                }
+            }
+            let grey = (total / 16.0).round() as u8;
+            Rgba([grey, grey, grey, 255])
+        })
+    }
+
+    /// Where the inner corners of the board drawn by [`photo`] are, row by
+    /// row from the top left.
+    fn expected(columns: u32, rows: u32, to_photo: &Homography) -> Vec<Vec2> {
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| Vec2::new(column as f32, row as f32)))
+            .map(|cell| to_photo.apply(cell + 2.0).unwrap())
+            .collect()
+    }
+
+    fn assert_found(found: &CornerGrid, columns: u32, rows: u32, expected: &[Vec2]) {
+        assert_eq!((found.columns, found.rows), (columns, rows));
+        assert_eq!(found.corners.len(), expected.len());
+        for (found, expected) in found.corners.iter().zip(expected) {
+            assert!(found.distance(*expected) < 0.2, "{found} should be {expected}");
+        }
+    }
+
+    fn square_on(offset: Vec2) -> Homography {
+        let corners = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        Homography::from_correspondences(corners, corners.map(|c| offset + c * SQUARE)).unwrap()
+    }
+
+    #[test]
+    fn finds_a_board_square_on() {
+        let board = Checkerboard { columns: 7, rows: 5, square: 0.03 };
+        let to_photo = square_on(Vec2::new(45.3, 38.6));
+        let found = find_corners(&photo(7, 5, &to_photo), &board).unwrap();
+        assert_found(&found, 7, 5, &expected(7, 5, &to_photo));
+    }
+
+    #[test]
+    fn finds_a_board_in_perspective() {
+        // the far edge of the paper is narrower, as when photographed on the
+        // floor from a standing eye
+        let board = Checkerboard { columns: 7, rows: 5, square: 0.03 };
+        let paper = [Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(10.0, 8.0), Vec2::new(0.0, 8.0)];
+        let seen = [
+            Vec2::new(95.0, 40.0),
+            Vec2::new(235.0, 52.0),
+            Vec2::new(290.0, 215.0),
+            Vec2::new(30.0, 200.0),
+        ];
+        let to_photo = Homography::from_correspondences(paper, seen).unwrap();
+        let found = find_corners(&photo(7, 5, &to_photo), &board).unwrap();
+        assert_found(&found, 7, 5, &expected(7, 5, &to_photo));
+    }
+
+    #[test]
+    fn finds_a_board_turned_a_quarter() {
+        // described as 7 by 5, but lying with its 5 corner side across
+        let board = Checkerboard { columns: 7, rows: 5, square: 0.03 };
+        let to_photo = square_on(Vec2::new(60.0, 20.0));
+        let found = find_corners(&photo(5, 7, &to_photo), &board).unwrap();
+        assert_found(&found, 5, 7, &expected(5, 7, &to_photo));
+    }
+
+    #[test]
+    fn nothing_to_find() {
+        let board = Checkerboard { columns: 7, rows: 5, square: 0.03 };
+        let blank = RgbaImage::from_pixel(320, 240, Rgba([128, 128, 128, 255]));
+        assert!(find_corners(&blank, &board).is_none());
+
+        let photo = photo(7, 5, &square_on(Vec2::new(45.0, 38.0)));
+        for (columns, rows) in [(8, 5), (6, 4), (1, 5), (7, 1), (0, 0)] {
+            let board = Checkerboard { columns, rows, square: 0.03 };
+            assert!(find_corners(&photo, &board).is_none(), "{columns}x{rows}");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Effects around rasterised text: outlines, drop shadows, glows, and
+//! knockouts through a coloured panel.
+//!
+//! All of them work from the text's coverage, its alpha channel. It is grown
+//! outwards with a distance transform, softened with a Gaussian blur and
+//! moved by an offset, then filled with the effect's colour behind the
+//! letters.
+
+use glam::Vec2;
+use image::{ImageBuffer, Luma, Pixel, Rgba, RgbaImage};
+use imageproc::distance_transform::euclidean_squared_distance_transform;
+use imageproc::filter::gaussian_blur_f32;
+
+/// Coverage from 0 to 1 per pixel.
+type Coverage = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Something drawn around or through the letters. Distances are in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// A band `width` wide around each letter.
+    Outline { color: Rgba<u8>, width: f32 },
+    /// The letters' shadow, moved by `offset` and blurred by `blur`, to
+    /// fake depth.
+    Shadow { color: Rgba<u8>, offset: Vec2, blur: f32 },
+    /// A halo reaching `radius` out from the letters and fading as it goes,
+    /// to read at night.
+    Glow { color: Rgba<u8>, radius: f32 },
+    /// A panel over the whole image with the letters cut out of it, and out
+    /// of everything behind it, so what the image is laid on shows through.
+    Knockout { color: Rgba<u8> },
+}
+
+/// Draws `effects` for `text`, the letters on a transparent background, with
+/// the first furthest back, and the letters over them unless they are
+/// knocked out.
+pub fn apply(text: &RgbaImage, effects: &[Effect]) -> RgbaImage {
+    let coverage: Coverage = ImageBuffer::from_fn(text.width(), text.height(), |x, y| {
+        Luma([text.get_pixel(x, y)[3] as f32 / 255.0])
+    });
+    let mut image = RgbaImage::new(text.width(), text.height());
+    let mut knocked_out = false;
+    for effect in effects {
+        match *effect {
+            Effect::Outline { color, width } => paint(&mut image, &grow(&coverage, width), color),
+            Effect::Shadow { color, offset, blur } => paint(&mut image, &soften(&shift(&coverage, offset), blur), color),
+            Effect::Glow { color, radius } => {
+                paint(&mut image, &soften(&grow(&coverage, radius / 2.0), radius / 2.0), color)
+            }
+            Effect::Knockout { color } => {
+                let panel = ImageBuffer::from_pixel(text.width(), text.height(), Luma([1.0]));
+                paint(&mut image, &panel, color);
+                knocked_out = true;
+            }
+        }
+    }
+    if knocked_out {
+        for (pixel, covered) in image.pixels_mut().zip(coverage.pixels()) {
+            pixel[3] = (pixel[3] as f32 * (1.0 - covered[0])).round() as u8;
+        }
+    } else {
+        for (pixel, letter) in image.pixels_mut().zip(text.pixels()) {
+            pixel.blend(letter);
+        }
+    }
+    image
+}
+
+/// Fills `coverage` with `color` over what `image` already has.
+fn paint(image: &mut RgbaImage, coverage: &Coverage, color: Rgba<u8>) {
+    for (pixel, covered) in image.pixels_mut().zip(coverage.pixels()) {
+        let alpha = (color[3] as f32 * covered[0].clamp(0.0, 1.0)).round() as u8;
+        if alpha > 0 {
+            pixel.blend(&Rgba([color[0], color[1], color[2], alpha]));
+        }
+    }
+}
+
+/// Coverage grown outwards by `distance`, antialiased at its new edge.
+fn grow(coverage: &Coverage, distance: f32) -> Coverage {
+    let inside = ImageBuffer::from_fn(coverage.width(), coverage.height(), |x, y| {
+        Luma([if coverage.get_pixel(x, y)[0] >= 0.5 { 255u8 } else { 0 }])
+    });
+    let squared = euclidean_squared_distance_transform(&inside);
+    ImageBuffer::from_fn(coverage.width(), coverage.height(), |x, y| {
+        // the letter's edge is half a pixel short of the nearest pixel inside
+        let reach = (distance + 1.0 - squared.get_pixel(x, y)[0].sqrt() as f32).clamp(0.0, 1.0);
+        Luma([reach.max(coverage.get_pixel(x, y)[0])])
+    })
+}
+
+fn soften(coverage: &Coverage, blur: f32) -> Coverage {
+    if blur > 0.0 {
+        gaussian_blur_f32(coverage, blur)
+    } else {
+        coverage.clone()
+    }
+}
+
+/// Coverage moved by `offset`, sampled bilinearly.
+fn shift(coverage: &Coverage, offset: Vec2) -> Coverage {
+    let at = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= coverage.width() as i64 || y >= coverage.height() as i64 {
+            0.0
+        } else {
+            coverage.get_pixel(x as u32, y as u32)[0]
+        }
+    };
+    ImageBuffer::from_fn(coverage.width(), coverage.height(), |x, y| {
+        let source = Vec2::new(x as f32, y as f32) - offset;
+        let (base, fraction) = (source.floor(), source - source.floor());
+        let (x0, y0) = (base.x as i64, base.y as i64);
+        let top = at(x0, y0) * (1.0 - fraction.x) + at(x0 + 1, y0) * fraction.x;
+        let bottom = at(x0, y0 + 1) * (1.0 - fraction.x) + at(x0 + 1, y0 + 1) * fraction.x;
+        Luma([top * (1.0 - fraction.y) + bottom * fraction.y])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `size` square of nothing but the pixel at `dot`.
+    fn dot(size: u32, dot: (u32, u32)) -> Coverage {
+        ImageBuffer::from_fn(size, size, |x, y| Luma([if (x, y) == dot { 1.0 } else { 0.0 }]))
+    }
+
+    fn at(coverage: &Coverage, x: u32, y: u32) -> f32 {
+        coverage.get_pixel(x, y)[0]
+    }
+
+    #[test]
+    fn growing_reaches_out_evenly() {
+        let grown = grow(&dot(21, (10, 10)), 3.0);
+        // whole out to the distance, then fading over a pixel
+        assert_eq!(at(&grown, 10, 10), 1.0);
+        assert_eq!(at(&grown, 13, 10), 1.0);
+        assert_eq!(at(&grown, 10, 7), 1.0);
+        assert!((at(&grown, 13, 12) - (4.0 - 13f32.sqrt())).abs() < 1e-5);
+        assert_eq!(at(&grown, 14, 10), 0.0);
+        assert_eq!(at(&grown, 12, 12), at(&grown, 8, 8));
+        // and by nothing, only the half covered edge is kept
+        let mut edge = dot(5, (2, 2));
+        edge.put_pixel(3, 2, Luma([0.25]));
+        assert_eq!(grow(&edge, 0.0), edge);
+    }
+
+    #[test]
+    fn softening_spreads_coverage_out() {
+        let coverage = dot(21, (10, 10));
+        let soft = soften(&coverage, 1.5);
+        let total: f32 = soft.pixels().map(|p| p[0]).sum();
+        // all but the tails imageproc cuts off its kernel
+        assert!((total - 1.0).abs() < 0.05, "{total}");
+        assert!(at(&soft, 10, 10) < 0.5 && at(&soft, 11, 10) > 0.0);
+        assert!((at(&soft, 11, 10) - at(&soft, 9, 10)).abs() < 1e-6);
+        assert!(at(&soft, 11, 10) > at(&soft, 12, 10));
+        assert_eq!(soften(&coverage, 0.0), coverage);
+    }
+
+    #[test]
+    fn shifting_moves_and_splits_coverage() {
+        let coverage = dot(8, (2, 2));
+        let moved = shift(&coverage, Vec2::new(3.0, 1.0));
+        assert_eq!(at(&moved, 5, 3), 1.0);
+        assert_eq!(moved.pixels().map(|p| p[0]).sum::<f32>(), 1.0);
+        let halfway = shift(&coverage, Vec2::new(0.5, 0.0));
+        assert_eq!((at(&halfway, 2, 2), at(&halfway, 3, 2)), (0.5, 0.5));
+        // what leaves the image is gone
+        assert!(shift(&coverage, Vec2::new(-3.0, 0.0)).pixels().all(|p| p[0] == 0.0));
+    }
+
+    #[test]
+    fn effects_stack_behind_the_letters() {
+        let mut letters = RgbaImage::new(9, 9);
+        letters.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let red = Rgba([255, 0, 0, 255]);
+        let image = apply(&letters, &[Effect::Outline { color: red, width: 1.0 }]);
+        assert_eq!(image.get_pixel(4, 4), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(5, 4), &red);
+        assert_eq!(image.get_pixel(7, 4)[3], 0);
+        let shadow = Effect::Shadow { color: red, offset: Vec2::new(2.0, 2.0), blur: 0.0 };
+        let image = apply(&letters, &[shadow]);
+        assert_eq!(image.get_pixel(6, 6), &red);
+        assert_eq!(image.get_pixel(5, 5)[3], 0);
+        let image = apply(&letters, &[Effect::Knockout { color: red }]);
+        assert_eq!(image.get_pixel(4, 4)[3], 0);
+        assert_eq!(image.get_pixel(0, 0), &red);
+    }
+}
@@ -0,0 +1,665 @@
+//! Colour glyphs, as emoji fonts draw them: layers of outlines filled with
+//! palette colours and gradients (COLR, versions 0 and 1), or embedded
+//! pictures (CBDT and sbix bitmaps).
+//!
+//! swash only paints the first version of COLR, so the layers are painted
+//! here from what ttf-parser reads out of the table. Pictures are scaled by
+//! swash, then resampled here so they turn and lean with the rest of the
+//! text.
+
+use glam::{Affine2, IVec2, Vec2, Vec4};
+use image::{Rgba, RgbaImage};
+use swash::scale::image::Content;
+use swash::scale::{Scaler, StrikeWith};
+use swash::zeno::{Command, Mask, Vector};
+use ttf_parser::colr::{ClipBox, CompositeMode, GradientExtend, Paint, Painter};
+use ttf_parser::{Face, GlyphId, OutlineBuilder, RgbaColor, Tag};
+
+/// A glyph drawn in colour, in image pixels.
+pub(crate) struct ColorGlyph {
+    /// Straight, not premultiplied, alpha.
+    pub(crate) image: RgbaImage,
+    /// Where the image's top left pixel goes.
+    pub(crate) corner: IVec2,
+}
+
+/// Paints `glyph_id`'s colour layers, if the font has any, mapping font
+/// units to image pixels with `to_image`. Layers that ask for the text's
+/// own colour get `foreground`.
+pub(crate) fn layers(
+    data: &[u8],
+    index: u32,
+    glyph_id: u16,
+    axes: &[(u32, f32)],
+    foreground: Rgba<u8>,
+    to_image: Affine2,
+) -> Option<ColorGlyph> {
+    let mut face = Face::parse(data, index).ok()?;
+    let glyph = GlyphId(glyph_id);
+    if !face.is_color_glyph(glyph) {
+        return None;
+    }
+    for (tag, value) in axes {
+        face.set_variation(Tag(*tag), *value);
+    }
+    let foreground = RgbaColor::new(foreground[0], foreground[1], foreground[2], foreground[3]);
+
+    // once to find how far the layers reach, then again to paint them
+    let mut reach = Layers::new(&face, to_image, None);
+    face.paint_color_glyph(glyph, 0, foreground, &mut reach)?;
+    let (min, max) = reach.reach;
+    if min.x >= max.x || min.y >= max.y {
+        return None;
+    }
+    let corner = min.floor().as_ivec2();
+    let size = (max.ceil().as_ivec2() - corner).as_uvec2();
+    let mut layers = Layers::new(&face, to_image, Some(Canvas::new(corner, size.x, size.y)));
+    face.paint_color_glyph(glyph, 0, foreground, &mut layers)?;
+    let canvas = layers.canvas?;
+    Some(ColorGlyph { image: canvas.image(), corner })
+}
+
+/// The glyph's embedded picture, if the font has one, mapping the glyph's
+/// pixels, y up from its origin, to image pixels with `to_image`.
+pub(crate) fn picture(scaler: &mut Scaler, glyph_id: u16, to_image: Affine2) -> Option<ColorGlyph> {
+    if !scaler.has_color_bitmaps() {
+        return None;
+    }
+    let bitmap = scaler.scale_color_bitmap(glyph_id, StrikeWith::BestFit)?;
+    let placement = bitmap.placement;
+    if bitmap.content != Content::Color || placement.width == 0 || placement.height == 0 {
+        return None;
+    }
+    // bitmap rows run down from the picture's top
+    let from_bitmap = to_image
+        * Affine2::from_cols(Vec2::X, Vec2::NEG_Y, Vec2::new(placement.left as f32, placement.top as f32));
+    let extent = Vec2::new(placement.width as f32, placement.height as f32);
+    let corners = [Vec2::ZERO, Vec2::new(extent.x, 0.0), extent, Vec2::new(0.0, extent.y)]
+        .map(|corner| from_bitmap.transform_point2(corner));
+    let min = corners.iter().fold(Vec2::MAX, |a, b| a.min(*b)).floor();
+    let max = corners.iter().fold(Vec2::MIN, |a, b| a.max(*b)).ceil();
+    let corner = min.as_ivec2();
+    let size = (max - min).as_uvec2();
+
+    let texel = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x as u32 >= placement.width || y as u32 >= placement.height {
+            return Vec4::ZERO;
+        }
+        let i = (y as u32 * placement.width + x as u32) as usize * 4;
+        premultiplied(Rgba([bitmap.data[i], bitmap.data[i + 1], bitmap.data[i + 2], bitmap.data[i + 3]]))
+    };
+    let to_bitmap = from_bitmap.inverse();
+    let image = RgbaImage::from_fn(size.x, size.y, |x, y| {
+        let p = to_bitmap.transform_point2(min + Vec2::new(x as f32, y as f32) + 0.5) - 0.5;
+        let (whole, fraction) = (p.floor(), p - p.floor());
+        let (x0, y0) = (whole.x as i32, whole.y as i32);
+        let top = texel(x0, y0).lerp(texel(x0 + 1, y0), fraction.x);
+        let bottom = texel(x0, y0 + 1).lerp(texel(x0 + 1, y0 + 1), fraction.x);
+        straight(top.lerp(bottom, fraction.y))
+    });
+    Some(ColorGlyph { image, corner })
+}
+
+/// Pixels, premultiplied, as RGBA from 0 to 1.
+struct Canvas {
+    corner: IVec2,
+    width: u32,
+    height: u32,
+    /// How much of each pixel the clips leave open, innermost last.
+    clips: Vec<Vec<f32>>,
+    /// Layers being painted, with how each goes onto the one below.
+    layers: Vec<(Vec<Vec4>, CompositeMode)>,
+}
+
+impl Canvas {
+    fn new(corner: IVec2, width: u32, height: u32) -> Self {
+        let pixels = (width * height) as usize;
+        Self {
+            corner,
+            width,
+            height,
+            clips: vec![vec![1.0; pixels]],
+            layers: vec![(vec![Vec4::ZERO; pixels], CompositeMode::SourceOver)],
+        }
+    }
+
+    /// How much of each pixel `path`, in image pixels, covers.
+    fn coverage(&self, path: &[Command]) -> Vec<f32> {
+        let shift = -self.corner.as_vec2();
+        let path: Vec<Command> = path.iter().map(|command| moved(*command, shift)).collect();
+        let (mask, _) = Mask::new(&path).size(self.width, self.height).render();
+        mask.iter().map(|coverage| *coverage as f32 / 255.0).collect()
+    }
+
+    fn push_clip(&mut self, path: &[Command]) {
+        let coverage = self.coverage(path);
+        let clip = self.clips.last().unwrap().iter().zip(coverage).map(|(a, b)| a * b).collect();
+        self.clips.push(clip);
+    }
+
+    /// Fills `path` with `color`, which gives the colour at each pixel
+    /// centre.
+    fn fill(&mut self, path: &[Command], color: impl Fn(Vec2) -> Vec4) {
+        let coverage = self.coverage(path);
+        let clip = self.clips.last().unwrap();
+        let (pixels, _) = self.layers.last_mut().unwrap();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let amount = coverage[i] * clip[i];
+            if amount <= 0.0 {
+                continue;
+            }
+            let at = self.corner.as_vec2()
+                + Vec2::new((i as u32 % self.width) as f32, (i as u32 / self.width) as f32)
+                + 0.5;
+            let source = color(at) * amount;
+            *pixel = source + *pixel * (1.0 - source.w);
+        }
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let (source, mode) = self.layers.pop().unwrap();
+        let (backdrop, _) = self.layers.last_mut().unwrap();
+        for (below, above) in backdrop.iter_mut().zip(source) {
+            *below = composite(above, *below, mode);
+        }
+    }
+
+    fn image(&self) -> RgbaImage {
+        let (pixels, _) = &self.layers[0];
+        RgbaImage::from_fn(self.width, self.height, |x, y| straight(pixels[(y * self.width + x) as usize]))
+    }
+}
+
+/// Follows ttf-parser through a colour glyph's paint graph, keeping track of
+/// how far it reaches and, given a canvas, painting it.
+struct Layers<'f, 'a> {
+    face: &'f Face<'a>,
+    /// From the current paint's space to image pixels, innermost last.
+    transforms: Vec<Affine2>,
+    /// The last outline given, in image pixels.
+    path: Vec<Command>,
+    reach: (Vec2, Vec2),
+    canvas: Option<Canvas>,
+}
+
+impl<'f, 'a> Layers<'f, 'a> {
+    fn new(face: &'f Face<'a>, to_image: Affine2, canvas: Option<Canvas>) -> Self {
+        Self {
+            face,
+            transforms: vec![to_image],
+            path: Vec::new(),
+            reach: (Vec2::MAX, Vec2::MIN),
+            canvas,
+        }
+    }
+
+    fn transform(&self) -> Affine2 {
+        *self.transforms.last().unwrap()
+    }
+
+    fn colour_at(&self, paint: &Paint<'a>) -> impl Fn(Vec2) -> Vec4 {
+        let to_paint = self.transform().inverse();
+        let coords = self.face.variation_coordinates();
+        let (shape, stops, extend) = match paint {
+            Paint::Solid(color) => (Shape::Solid, vec![(0.0, rgba(*color))], GradientExtend::Pad),
+            Paint::LinearGradient(g) => (
+                Shape::linear(Vec2::new(g.x0, g.y0), Vec2::new(g.x1, g.y1), Vec2::new(g.x2, g.y2)),
+                g.stops(0, coords).map(|stop| (stop.stop_offset, rgba(stop.color))).collect(),
+                g.extend,
+            ),
+            Paint::RadialGradient(g) => (
+                Shape::Radial {
+                    centres: (Vec2::new(g.x0, g.y0), Vec2::new(g.x1, g.y1)),
+                    radii: (g.r0, g.r1),
+                },
+                g.stops(0, coords).map(|stop| (stop.stop_offset, rgba(stop.color))).collect(),
+                g.extend,
+            ),
+            Paint::SweepGradient(g) => (
+                Shape::Sweep {
+                    centre: Vec2::new(g.center_x, g.center_y),
+                    // stored in half turns, counterclockwise, less one
+                    angles: ((g.start_angle + 1.0) * 180.0, (g.end_angle + 1.0) * 180.0),
+                },
+                g.stops(0, coords).map(|stop| (stop.stop_offset, rgba(stop.color))).collect(),
+                g.extend,
+            ),
+        };
+        let mut stops: Vec<(f32, Vec4)> = stops;
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        move |at| match shape.position(to_paint.transform_point2(at)) {
+            Some(t) => color_line(&stops, t, extend),
+            None => Vec4::ZERO,
+        }
+    }
+}
+
+impl<'a> Painter<'a> for Layers<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: GlyphId) {
+        let mut outline = Outline {
+            transform: self.transform(),
+            commands: Vec::new(),
+        };
+        self.face.outline_glyph(glyph_id, &mut outline);
+        self.path = outline.commands;
+        for command in &self.path {
+            for p in points(command) {
+                self.reach = (self.reach.0.min(p), self.reach.1.max(p));
+            }
+        }
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let color = self.colour_at(&paint);
+        if let Some(canvas) = &mut self.canvas {
+            canvas.fill(&self.path, color);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.push_clip(&self.path);
+        }
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        let transform = self.transform();
+        let corners = [
+            Vec2::new(clipbox.x_min, clipbox.y_min),
+            Vec2::new(clipbox.x_max, clipbox.y_min),
+            Vec2::new(clipbox.x_max, clipbox.y_max),
+            Vec2::new(clipbox.x_min, clipbox.y_max),
+        ]
+        .map(|corner| vector(transform.transform_point2(corner)));
+        let path = [
+            Command::MoveTo(corners[0]),
+            Command::LineTo(corners[1]),
+            Command::LineTo(corners[2]),
+            Command::LineTo(corners[3]),
+            Command::Close,
+        ];
+        if let Some(canvas) = &mut self.canvas {
+            canvas.push_clip(&path);
+        }
+    }
+
+    fn pop_clip(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            if canvas.clips.len() > 1 {
+                canvas.clips.pop();
+            }
+        }
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        if let Some(canvas) = &mut self.canvas {
+            let pixels = (canvas.width * canvas.height) as usize;
+            canvas.layers.push((vec![Vec4::ZERO; pixels], mode));
+        }
+    }
+
+    fn pop_layer(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.pop_layer();
+        }
+    }
+
+    fn push_transform(&mut self, t: ttf_parser::Transform) {
+        let transform = self.transform() * Affine2::from_cols_array(&[t.a, t.b, t.c, t.d, t.e, t.f]);
+        self.transforms.push(transform);
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+/// A glyph outline, moved into image pixels as it is read.
+struct Outline {
+    transform: Affine2,
+    commands: Vec<Command>,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> Vector {
+        vector(self.transform.transform_point2(Vec2::new(x, y)))
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.commands.push(Command::MoveTo(p));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.commands.push(Command::LineTo(p));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (c, p) = (self.point(x1, y1), self.point(x, y));
+        self.commands.push(Command::QuadTo(c, p));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (c1, c2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.commands.push(Command::CurveTo(c1, c2, p));
+    }
+
+    fn close(&mut self) {
+        self.commands.push(Command::Close);
+    }
+}
+
+/// Where a point falls along a paint's colour line.
+enum Shape {
+    Solid,
+    /// From `start` towards `end`, with stripes of one colour at right angles.
+    Linear { start: Vec2, end: Vec2 },
+    /// Circles swept from the first to the second.
+    Radial { centres: (Vec2, Vec2), radii: (f32, f32) },
+    /// Round `centre`, between angles in degrees.
+    Sweep { centre: Vec2, angles: (f32, f32) },
+}
+
+impl Shape {
+    /// A COLR linear gradient runs from `p0` towards `p1`, with stripes
+    /// parallel to `p0`–`p2`.
+    fn linear(p0: Vec2, p1: Vec2, p2: Vec2) -> Self {
+        let stripe = (p2 - p0).perp();
+        let end = if stripe.length_squared() > 0.0 {
+            p0 + (p1 - p0).project_onto(stripe)
+        } else {
+            p1
+        };
+        Shape::Linear { start: p0, end }
+    }
+
+    fn position(&self, p: Vec2) -> Option<f32> {
+        match *self {
+            Shape::Solid => Some(0.0),
+            Shape::Linear { start, end } => {
+                let line = end - start;
+                (line.length_squared() > 0.0).then(|| (p - start).dot(line) / line.length_squared())
+            }
+            Shape::Radial { centres: (c0, c1), radii: (r0, r1) } => {
+                // the largest t whose circle, of radius zero or more, passes
+                // through p
+                let (cd, pd, dr) = (c1 - c0, p - c0, r1 - r0);
+                let a = cd.dot(cd) - dr * dr;
+                let b = pd.dot(cd) + r0 * dr;
+                let c = pd.dot(pd) - r0 * r0;
+                let radius = |t: f32| r0 + t * dr;
+                if a.abs() < 1e-6 {
+                    let t = c / (2.0 * b);
+                    return (b != 0.0 && radius(t) >= 0.0).then_some(t);
+                }
+                let discriminant = b * b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                let (high, low) = ((b + root) / a, (b - root) / a);
+                let (high, low) = if high >= low { (high, low) } else { (low, high) };
+                [high, low].into_iter().find(|t| radius(*t) >= 0.0)
+            }
+            Shape::Sweep { centre, angles: (start, end) } => {
+                let d = p - centre;
+                let angle = d.y.atan2(d.x).to_degrees().rem_euclid(360.0);
+                (end != start).then(|| (angle - start) / (end - start))
+            }
+        }
+    }
+}
+
+/// The colour at `t` along `stops`, which are sorted by offset, carried on
+/// past the ends as `extend` says.
+fn color_line(stops: &[(f32, Vec4)], t: f32, extend: GradientExtend) -> Vec4 {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return Vec4::ZERO;
+    };
+    let span = last.0 - first.0;
+    let t = if span > 0.0 {
+        let u = (t - first.0) / span;
+        let u = match extend {
+            GradientExtend::Pad => u.clamp(0.0, 1.0),
+            GradientExtend::Repeat => u.rem_euclid(1.0),
+            GradientExtend::Reflect => 1.0 - ((u.rem_euclid(2.0)) - 1.0).abs(),
+        };
+        first.0 + u * span
+    } else {
+        first.0
+    };
+    let i = stops.partition_point(|(offset, _)| *offset <= t);
+    match (i.checked_sub(1).map(|i| stops[i]), stops.get(i).copied()) {
+        (Some((o0, c0)), Some((o1, c1))) => c0.lerp(c1, (t - o0) / (o1 - o0)),
+        (Some((_, color)), None) | (None, Some((_, color))) => color,
+        (None, None) => Vec4::ZERO,
+    }
+}
+
+/// `source` put onto `backdrop`, both premultiplied.
+fn composite(source: Vec4, backdrop: Vec4, mode: CompositeMode) -> Vec4 {
+    use CompositeMode::*;
+    let (sa, ba) = (source.w, backdrop.w);
+    let porter_duff = |fs: f32, fb: f32| source * fs + backdrop * fb;
+    match mode {
+        Clear => Vec4::ZERO,
+        Source => source,
+        Destination => backdrop,
+        SourceOver => porter_duff(1.0, 1.0 - sa),
+        DestinationOver => porter_duff(1.0 - ba, 1.0),
+        SourceIn => porter_duff(ba, 0.0),
+        DestinationIn => porter_duff(0.0, sa),
+        SourceOut => porter_duff(1.0 - ba, 0.0),
+        DestinationOut => porter_duff(0.0, 1.0 - sa),
+        SourceAtop => porter_duff(ba, 1.0 - sa),
+        DestinationAtop => porter_duff(1.0 - ba, sa),
+        Xor => porter_duff(1.0 - ba, 1.0 - sa),
+        Plus => (source + backdrop).min(Vec4::ONE),
+        _ => {
+            // the separable blend modes, channel by channel on straight
+            // colours; the others (hue, saturation, colour, luminosity)
+            // are painted as plain source over
+            let blend = |b: f32, s: f32| match mode {
+                Multiply => b * s,
+                Screen => b + s - b * s,
+                Overlay => hard_light(s, b),
+                Darken => b.min(s),
+                Lighten => b.max(s),
+                ColorDodge if b == 0.0 => 0.0,
+                ColorDodge if s >= 1.0 => 1.0,
+                ColorDodge => (b / (1.0 - s)).min(1.0),
+                ColorBurn if b >= 1.0 => 1.0,
+                ColorBurn if s <= 0.0 => 0.0,
+                ColorBurn => 1.0 - ((1.0 - b) / s).min(1.0),
+                HardLight => hard_light(b, s),
+                SoftLight if s <= 0.5 => b - (1.0 - 2.0 * s) * b * (1.0 - b),
+                SoftLight => {
+                    let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+                    b + (2.0 * s - 1.0) * (d - b)
+                }
+                Difference => (b - s).abs(),
+                Exclusion => b + s - 2.0 * b * s,
+                _ => s,
+            };
+            let unmix = |c: f32, a: f32| if a > 0.0 { c / a } else { 0.0 };
+            let channel = |s: f32, b: f32| {
+                s * (1.0 - ba) + b * (1.0 - sa) + sa * ba * blend(unmix(b, ba), unmix(s, sa))
+            };
+            Vec4::new(
+                channel(source.x, backdrop.x),
+                channel(source.y, backdrop.y),
+                channel(source.z, backdrop.z),
+                sa + ba - sa * ba,
+            )
+        }
+    }
+}
+
+/// Hard light of `s` over `b`; overlay is the same with the two swapped.
+fn hard_light(b: f32, s: f32) -> f32 {
+    if s <= 0.5 {
+        b * 2.0 * s
+    } else {
+        let s = 2.0 * s - 1.0;
+        b + s - b * s
+    }
+}
+
+fn rgba(color: RgbaColor) -> Vec4 {
+    premultiplied(Rgba([color.red, color.green, color.blue, color.alpha]))
+}
+
+fn premultiplied(color: Rgba<u8>) -> Vec4 {
+    let [r, g, b, a] = color.0.map(|c| c as f32 / 255.0);
+    Vec4::new(r * a, g * a, b * a, a)
+}
+
+fn straight(color: Vec4) -> Rgba<u8> {
+    if color.w <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let rgb = (color.truncate() / color.w).clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
+    let to_byte = |c: f32| (c * 255.0).round() as u8;
+    Rgba([to_byte(rgb.x), to_byte(rgb.y), to_byte(rgb.z), to_byte(color.w.min(1.0))])
+}
+
+fn vector(p: Vec2) -> Vector {
+    Vector::new(p.x, p.y)
+}
+
+fn moved(command: Command, by: Vec2) -> Command {
+    let shift = |p: Vector| Vector::new(p.x + by.x, p.y + by.y);
+    match command {
+        Command::MoveTo(p) => Command::MoveTo(shift(p)),
+        Command::LineTo(p) => Command::LineTo(shift(p)),
+        Command::QuadTo(c, p) => Command::QuadTo(shift(c), shift(p)),
+        Command::CurveTo(c1, c2, p) => Command::CurveTo(shift(c1), shift(c2), shift(p)),
+        Command::Close => Command::Close,
+    }
+}
+
+/// The points a command passes through or is pulled towards, which between
+/// them hold the curve.
+fn points(command: &Command) -> Vec<Vec2> {
+    let point = |p: &Vector| Vec2::new(p.x, p.y);
+    match command {
+        Command::MoveTo(p) | Command::LineTo(p) => vec![point(p)],
+        Command::QuadTo(c, p) => vec![point(c), point(p)],
+        Command::CurveTo(c1, c2, p) => vec![point(c1), point(c2), point(p)],
+        Command::Close => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Google's COLRv1 test font, whose glyphs each try out one kind of paint.
+    const COLR_1: &[u8] = include_bytes!("../assets/fonts/colr_1/colr_1.ttf");
+
+    /// Glyph `id` painted at 100 pixels to the em, with the font's origin at
+    /// the image's bottom left.
+    fn painted(id: u16) -> ColorGlyph {
+        let to_image = Affine2::from_cols(Vec2::new(0.1, 0.0), Vec2::new(0.0, -0.1), Vec2::new(0.0, 100.0));
+        layers(COLR_1, 0, id, &[], Rgba([0, 0, 0, 255]), to_image).expect("a colour glyph")
+    }
+
+    /// The pixel at `x`, `y` in image pixels.
+    fn at(glyph: &ColorGlyph, x: i32, y: i32) -> Rgba<u8> {
+        let local = IVec2::new(x, y) - glyph.corner;
+        *glyph.image.get_pixel(local.x as u32, local.y as u32)
+    }
+
+    fn assert_near(pixel: Rgba<u8>, expected: [u8; 4]) {
+        let close = pixel.0.iter().zip(expected).all(|(a, b)| a.abs_diff(b) <= 3);
+        assert!(close, "{pixel:?} is not {expected:?}");
+    }
+
+    #[test]
+    fn linear_gradients_run_between_their_stops() {
+        // red at a fifth of the way from x = 100 to 900 units, and blue at
+        // four fifths, repeating
+        let glyph = painted(9);
+        assert_near(at(&glyph, 30, 40), [231, 0, 24, 255]);
+        assert_near(at(&glyph, 49, 40), [130, 0, 125, 255]);
+        // past the blue stop the red comes round again
+        assert_near(at(&glyph, 85, 40), [194, 0, 61, 255]);
+        assert_eq!(at(&glyph, 30, 40), at(&glyph, 30, 60), "stripes run straight up");
+    }
+
+    #[test]
+    fn radial_gradients_spread_from_their_centre() {
+        // green at (166, 768) units, white 128 units out and red from 256
+        let glyph = painted(93);
+        assert_near(at(&glyph, 16, 22), [14, 135, 14, 255]);
+        assert_near(at(&glyph, 80, 80), [255, 0, 0, 255]);
+        let x = (16.6 + 12.8 - 0.5) as i32;
+        let pixel = at(&glyph, x, 22);
+        assert!(pixel.0.iter().all(|c| *c > 230), "{pixel:?} is not white");
+    }
+
+    #[test]
+    fn sweep_gradients_turn_about_their_centre() {
+        // from 60 to 300 degrees round (500, 600) units, so straight left of
+        // it is about halfway between the blue and red stops
+        let glyph = painted(13);
+        assert_near(at(&glyph, 30, 39), [118, 0, 137, 255]);
+        let below = at(&glyph, 50, 60);
+        assert_eq!(below, Rgba([47, 79, 79, 255]), "padded past the last stop");
+    }
+
+    #[test]
+    fn layers_composite_in_their_modes() {
+        // orange goes behind half transparent blue where the two overlap
+        let glyph = painted(84);
+        assert_near(at(&glyph, 50, 50), [105, 68, 150, 216]);
+        assert_near(at(&glyph, 50, 85), [255, 165, 0, 178]);
+        assert_eq!(at(&glyph, 27, 14)[3], 0, "unpainted corners stay clear");
+    }
+
+    #[test]
+    fn plain_glyphs_have_no_layers() {
+        let to_image = Affine2::IDENTITY;
+        assert!(layers(COLR_1, 0, 1, &[], Rgba([0, 0, 0, 255]), to_image).is_none());
+    }
+
+    /// A 4 by 4 pixel picture at 20 pixels to the em, in red, green, blue
+    /// and white quarters, from 2 pixels right of the origin and 3 below.
+    const SBIX: &[u8] = include_bytes!("../assets/fonts/sbix/sbix.ttf");
+
+    #[test]
+    fn pictures_are_resampled_into_place() {
+        let font = swash::FontRef::from_index(SBIX, 0).unwrap();
+        let mut context = swash::scale::ScaleContext::new();
+        let mut scaler = context.builder(font).size(20.0).build();
+        // twice the size, y down, with the origin at (10, 30)
+        let to_image = Affine2::from_cols(Vec2::new(2.0, 0.0), Vec2::new(0.0, -2.0), Vec2::new(10.0, 30.0));
+        let glyph = picture(&mut scaler, 1, to_image).expect("a picture");
+
+        // the picture's top left is at (2, 1) glyph pixels, so (14, 28)
+        assert_eq!(glyph.corner, IVec2::new(14, 28));
+        assert_eq!(glyph.image.dimensions(), (8, 8));
+        assert_eq!(at(&glyph, 15, 29), Rgba([255, 0, 0, 255]));
+        assert_eq!(at(&glyph, 20, 29), Rgba([0, 255, 0, 255]));
+        assert_eq!(at(&glyph, 15, 34), Rgba([0, 0, 255, 255]));
+        assert_eq!(at(&glyph, 20, 34), Rgba([255, 255, 255, 255]));
+        // the outermost pixels are partly covered, keeping their colour, and
+        // the quarters blend where they meet
+        assert_near(at(&glyph, 14, 28), [255, 0, 0, 143]);
+        assert_near(at(&glyph, 17, 28), [191, 64, 0, 191]);
+
+        // glyphs the strike has no picture for, and fonts with no strikes
+        assert!(picture(&mut scaler, 0, to_image).is_none());
+        let font = swash::FontRef::from_index(COLR_1, 0).unwrap();
+        let mut scaler = context.builder(font).size(20.0).build();
+        assert!(picture(&mut scaler, 9, to_image).is_none());
+    }
+}
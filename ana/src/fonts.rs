@@ -0,0 +1,323 @@
+//! The fonts text is set in.
+//!
+//! Only the fonts bundled in `ana/assets/fonts`, and any the caller ships and
+//! adds, are ever used, never the system's, so a layout comes out the same on
+//! every machine. Each character is set in the first family of the fallback
+//! chain that has a glyph for it.
+
+use std::{error::Error, fmt, ops::Range, sync::Arc};
+
+use cosmic_text::fontdb::{Database, FaceInfo, Query, Stretch, Style, Weight, ID};
+use cosmic_text::{Attrs, Family, Font, FontSystem};
+
+pub const FIRA_SANS: &str = "Fira Sans";
+pub const FIRA_MONO: &str = "Fira Mono";
+pub const NOTO_EMOJI: &str = "Noto Emoji";
+pub const DEJAVU_SANS: &str = "DejaVu Sans";
+
+const BUNDLED: [&[u8]; 4] = [
+    include_bytes!("../assets/fonts/FiraSans-Bold.ttf"),
+    include_bytes!("../assets/fonts/FiraMono-Medium.ttf"),
+    include_bytes!("../assets/fonts/Noto_Emoji/NotoEmoji-VariableFont_wght.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontError {
+    /// No bundled font has this family name.
+    MissingFamily { family: String, available: Vec<String> },
+    /// The fallback chain names no families at all.
+    EmptyFallback,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::MissingFamily { family, available } => write!(
+                f,
+                "no bundled font family {family:?}; available: {}",
+                available.join(", ")
+            ),
+            FontError::EmptyFallback => write!(f, "the font fallback chain is empty"),
+        }
+    }
+}
+
+impl Error for FontError {}
+
+/// One bundled font, as chosen for a family.
+#[derive(Clone)]
+pub(crate) struct Face {
+    pub(crate) family: String,
+    pub(crate) font: Arc<Font>,
+    /// Which face of a font collection it is.
+    pub(crate) index: u32,
+    pub(crate) weight: Weight,
+    pub(crate) style: Style,
+}
+
+/// The bundled fonts, with a locale and a fallback chain.
+pub struct FontRegistry {
+    font_system: FontSystem,
+    chain: Vec<Face>,
+}
+
+impl FontRegistry {
+    /// Fira Sans, falling back to Noto Emoji and then DejaVu Sans, which has
+    /// Hebrew and Arabic, for `en-US`.
+    pub fn bundled() -> Self {
+        Self::new("en-US", &[FIRA_SANS, NOTO_EMOJI, DEJAVU_SANS]).expect("bundled fonts cover the default chain")
+    }
+
+    /// The bundled fonts for `locale`, trying the `fallback` families in order
+    /// for each character.
+    pub fn new(locale: &str, fallback: &[&str]) -> Result<Self, FontError> {
+        let mut db = Database::new();
+        for data in BUNDLED {
+            db.load_font_data(data.to_vec());
+        }
+        let mut font_system = FontSystem::new_with_locale_and_db(locale.into(), db);
+        let chain = Self::resolve(&mut font_system, fallback)?;
+        Ok(Self { font_system, chain })
+    }
+
+    /// Adds a font file the caller ships, such as a colour emoji font, and
+    /// returns the families it holds. Name them in the fallback chain to use
+    /// them.
+    pub fn add_font(&mut self, data: Vec<u8>) -> Vec<String> {
+        let before: Vec<ID> = self.font_system.db().faces().map(|face| face.id).collect();
+        self.font_system.db_mut().load_font_data(data);
+        let mut added: Vec<String> = self
+            .font_system
+            .db()
+            .faces()
+            .filter(|face| !before.contains(&face.id))
+            .flat_map(|face| face.families.iter().map(|(name, _)| name.clone()))
+            .collect();
+        added.dedup();
+        added
+    }
+
+    /// Replaces the fallback chain, keeping the locale.
+    pub fn set_fallback(&mut self, fallback: &[&str]) -> Result<(), FontError> {
+        self.chain = Self::resolve(&mut self.font_system, fallback)?;
+        Ok(())
+    }
+
+    fn resolve(font_system: &mut FontSystem, fallback: &[&str]) -> Result<Vec<Face>, FontError> {
+        if fallback.is_empty() {
+            return Err(FontError::EmptyFallback);
+        }
+        fallback
+            .iter()
+            .map(|family| closest(font_system, family, Weight::NORMAL, Style::Normal))
+            .collect()
+    }
+
+    pub fn locale(&self) -> &str {
+        self.font_system.locale()
+    }
+
+    /// The family names of every bundled font, sorted.
+    pub fn families(&self) -> Vec<String> {
+        families(self.font_system.db())
+    }
+
+    /// The fallback chain, first choice first.
+    pub fn fallback(&self) -> Vec<&str> {
+        self.chain.iter().map(|face| face.family.as_str()).collect()
+    }
+
+    /// Attributes selecting `family` as bundled, whichever weight and style
+    /// that is.
+    pub fn attrs<'a>(&self, family: &'a str) -> Result<Attrs<'a>, FontError> {
+        let info = find_face(self.font_system.db(), family).ok_or_else(|| FontError::MissingFamily {
+                family: family.to_string(),
+                available: self.families(),
+            })?;
+        Ok(Attrs::new()
+            .family(Family::Name(family))
+            .weight(info.weight)
+            .style(info.style)
+            .stretch(info.stretch))
+    }
+
+    /// The fallback chain for text asking for `family` at `weight`, in
+    /// italics or not: that family first, if given, then the usual chain,
+    /// each in its bundled face closest to the request.
+    pub(crate) fn chain_for(&mut self, family: Option<&str>, weight: u16, italic: bool) -> Result<Vec<Face>, FontError> {
+        let style = if italic { Style::Italic } else { Style::Normal };
+        let families: Vec<String> = family
+            .into_iter()
+            .map(str::to_string)
+            .chain(self.chain.iter().map(|face| face.family.clone()).filter(|f| Some(f.as_str()) != family))
+            .collect();
+        families
+            .iter()
+            .map(|family| closest(&mut self.font_system, family, Weight(weight), style))
+            .collect()
+    }
+}
+
+/// The bundled face of `family` closest to `weight` and `style`, matched the
+/// way CSS does.
+fn closest(font_system: &mut FontSystem, family: &str, weight: Weight, style: Style) -> Result<Face, FontError> {
+    let query = Query {
+        families: &[Family::Name(family)],
+        weight,
+        stretch: Stretch::Normal,
+        style,
+    };
+    let found = font_system
+        .db()
+        .query(&query)
+        .and_then(|id| font_system.db().face(id))
+        .map(|info| (info.id, info.index, info.weight, info.style));
+    let font = found.and_then(|(id, ..)| font_system.get_font(id));
+    let (Some((_, index, weight, style)), Some(font)) = (found, font) else {
+        return Err(FontError::MissingFamily {
+            family: family.to_string(),
+            available: families(font_system.db()),
+        });
+    };
+    Ok(Face { family: family.to_string(), font, index, weight, style })
+}
+
+/// Splits `line` into runs each set in one face: the first of `chain`
+/// with glyphs for its characters. Characters no face of the chain has
+/// go in its last, to be drawn as its missing glyph box, rather than in
+/// whatever else happens to be loaded.
+pub(crate) fn face_runs(chain: &[Face], line: &str) -> Vec<(Range<usize>, Face)> {
+    let missing = chain.len() - 1;
+    let chosen = line
+        .char_indices()
+        .map(|(i, c)| (i, c, chain.iter().position(|face| has_glyph(face, c)).unwrap_or(missing)))
+        .collect();
+    runs(line, chosen).into_iter().map(|(range, face)| (range, chain[face].clone())).collect()
+}
+
+/// Gathers characters, with the face chosen for each, into runs of one
+/// face.
+fn runs(line: &str, chosen: Vec<(usize, char, usize)>) -> Vec<(Range<usize>, usize)> {
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for (i, c, face) in chosen {
+        match runs.last_mut() {
+            Some((_, current)) if *current == face || joins_run(c) => {}
+            _ => {
+                if let Some((range, _)) = runs.last_mut() {
+                    range.end = i;
+                }
+                runs.push((i..i, face));
+            }
+        }
+    }
+    if let Some((range, _)) = runs.last_mut() {
+        range.end = line.len();
+    }
+    runs
+}
+
+fn has_glyph(face: &Face, c: char) -> bool {
+    face.font.rustybuzz().glyph_index(c).is_some()
+}
+
+/// Variation selectors and joiners stay in the run they are in.
+fn joins_run(c: char) -> bool {
+    c.is_control() || ('\u{fe00}'..='\u{fe0f}').contains(&c) || c == '\u{200d}'
+}
+
+fn find_face<'a>(db: &'a Database, family: &str) -> Option<&'a FaceInfo> {
+    db.faces().find(|face| face.families.iter().any(|(name, _)| name == family))
+}
+
+fn families(db: &Database) -> Vec<String> {
+    let mut names: Vec<String> = db
+        .faces()
+        .flat_map(|face| face.families.iter().map(|(name, _)| name.clone()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bundled_families_are_loaded() {
+        let fonts = FontRegistry::bundled();
+        assert_eq!(fonts.families(), [DEJAVU_SANS, FIRA_MONO, FIRA_SANS, NOTO_EMOJI]);
+        assert_eq!(fonts.fallback(), [FIRA_SANS, NOTO_EMOJI, DEJAVU_SANS]);
+        assert_eq!(fonts.locale(), "en-US");
+    }
+
+    #[test]
+    fn missing_families_are_errors() {
+        let Err(FontError::MissingFamily { family, available }) = FontRegistry::new("en-GB", &[FIRA_SANS, "Comic Sans MS"]) else {
+            panic!("Comic Sans MS is not bundled");
+        };
+        assert_eq!(family, "Comic Sans MS");
+        assert!(available.contains(&FIRA_SANS.to_string()));
+        assert_eq!(FontRegistry::new("en-GB", &[]).err(), Some(FontError::EmptyFallback));
+        assert!(FontRegistry::bundled().attrs("Helvetica").is_err());
+    }
+
+    #[test]
+    fn runs_take_the_first_face_with_each_glyph() {
+        let mut fonts = FontRegistry::bundled();
+        let chain = fonts.chain_for(None, 400, false).unwrap();
+        let line = "hi 🦀 שלום";
+        let runs: Vec<(&str, String)> = face_runs(&chain, line)
+            .into_iter()
+            .map(|(range, face)| (&line[range], face.family))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("hi ", FIRA_SANS.to_string()),
+                ("🦀", NOTO_EMOJI.to_string()),
+                (" ", FIRA_SANS.to_string()),
+                ("שלום", DEJAVU_SANS.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn added_fonts_can_join_the_chain() {
+        let mut fonts = FontRegistry::bundled();
+        let added = fonts.add_font(include_bytes!("../assets/fonts/sbix/sbix.ttf").to_vec());
+        assert_eq!(added, ["Sbix Test"]);
+        assert!(fonts.add_font(b"not a font".to_vec()).is_empty());
+        // not used until it is named in the chain
+        let chain = fonts.chain_for(None, 400, false).unwrap();
+        assert_eq!(face_runs(&chain, "😀")[0].1.family, NOTO_EMOJI);
+        fonts.set_fallback(&[FIRA_SANS, "Sbix Test", NOTO_EMOJI]).unwrap();
+        let chain = fonts.chain_for(None, 400, false).unwrap();
+        let line = "a😀🦀";
+        let runs: Vec<(&str, String)> = face_runs(&chain, line)
+            .into_iter()
+            .map(|(range, face)| (&line[range], face.family))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("a", FIRA_SANS.to_string()),
+                ("😀", "Sbix Test".to_string()),
+                ("🦀", NOTO_EMOJI.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn characters_outside_the_chain_go_in_its_last_face() {
+        // DejaVu Sans is loaded, and has Hebrew, but isn't in the chain
+        let mut fonts = FontRegistry::new("en-US", &[FIRA_MONO, NOTO_EMOJI]).unwrap();
+        let chain = fonts.chain_for(Some(FIRA_SANS), 700, false).unwrap();
+        let families: Vec<String> = chain.iter().map(|face| face.family.clone()).collect();
+        assert_eq!(families, [FIRA_SANS, FIRA_MONO, NOTO_EMOJI]);
+        let runs = face_runs(&chain, "a שלום");
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[1].0.clone(), runs[1].1.family.as_str()), (2..10, NOTO_EMOJI));
+    }
+}
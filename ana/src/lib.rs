@@ -0,0 +1,27 @@
+//! Shared anamorphic machinery used by the Bevy scenes.
+//!
+//! The scenes (`integrate2`, `ortho`, ...) stay small binaries; anything that
+//! has to be the same across them lives here.
+
+pub mod calibration;
+pub mod catoptric;
+pub mod checkerboard;
+pub mod effects;
+mod emoji;
+pub mod fonts;
+mod linalg;
+mod lookup;
+pub mod mesh;
+pub mod poster;
+pub mod projection;
+pub mod projector;
+pub mod rich;
+pub mod road_marking;
+pub mod sdf;
+pub mod shadow;
+pub mod stencil;
+pub mod surfaces;
+pub mod text;
+pub mod vector;
+pub mod viewing_zone;
+pub mod warp;
@@ -0,0 +1,125 @@
+//! Small dense linear algebra helpers, enough for the solvers in this crate.
+
+/// Solves `a * x = b` for square `a` using Gaussian elimination with partial
+/// pivoting. Returns `None` if the system is singular.
+pub(crate) fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (pivot_rows, rows_below) = a.split_at_mut(col + 1);
+        let pivot_row = &pivot_rows[col];
+        for (offset, row) in rows_below.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            if factor == 0.0 {
+                continue;
+            }
+            for (value, pivot_value) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Least squares solution of the overdetermined system `a * x = b`, through
+/// the normal equations.
+pub(crate) fn least_squares(a: &[Vec<f64>], b: &[f64]) -> Option<Vec<f64>> {
+    let n = a.first()?.len();
+    let mut ata = vec![vec![0.0; n]; n];
+    let mut atb = vec![0.0; n];
+    for (row, &rhs) in a.iter().zip(b) {
+        for i in 0..n {
+            atb[i] += row[i] * rhs;
+            for j in 0..n {
+                ata[i][j] += row[i] * row[j];
+            }
+        }
+    }
+    solve(ata, atb)
+}
+
+/// Minimises the sum of squared `residuals` over the parameters, starting
+/// from `params`, with Levenberg-Marquardt and a forward difference Jacobian.
+pub(crate) fn levenberg_marquardt(
+    mut params: Vec<f64>,
+    residuals: impl Fn(&[f64]) -> Vec<f64>,
+    iterations: usize,
+) -> Vec<f64> {
+    let cost = |r: &[f64]| r.iter().map(|v| v * v).sum::<f64>();
+    let mut r = residuals(&params);
+    let mut current = cost(&r);
+    let mut damping = 1e-3;
+
+    for _ in 0..iterations {
+        let jacobian: Vec<Vec<f64>> = (0..params.len())
+            .map(|k| {
+                let step = 1e-6 * params[k].abs().max(1.0);
+                let mut nudged = params.clone();
+                nudged[k] += step;
+                residuals(&nudged)
+                    .iter()
+                    .zip(&r)
+                    .map(|(a, b)| (a - b) / step)
+                    .collect()
+            })
+            .collect();
+
+        let n = params.len();
+        let mut jtj = vec![vec![0.0; n]; n];
+        let mut jtr = vec![0.0; n];
+        for i in 0..n {
+            jtr[i] = -jacobian[i].iter().zip(&r).map(|(j, r)| j * r).sum::<f64>();
+            for k in 0..n {
+                jtj[i][k] = jacobian[i].iter().zip(&jacobian[k]).map(|(a, b)| a * b).sum();
+            }
+        }
+
+        // raise the damping until a step helps
+        loop {
+            let mut damped = jtj.clone();
+            for (i, row) in damped.iter_mut().enumerate() {
+                row[i] += damping * jtj[i][i].max(1e-12);
+            }
+            let Some(delta) = solve(damped, jtr.clone()) else {
+                damping *= 10.0;
+                if damping > 1e12 {
+                    return params;
+                }
+                continue;
+            };
+            let trial: Vec<f64> = params.iter().zip(&delta).map(|(p, d)| p + d).collect();
+            let trial_r = residuals(&trial);
+            let trial_cost = cost(&trial_r);
+            if trial_cost < current {
+                let converged = current - trial_cost < 1e-12 * current.max(1e-12);
+                params = trial;
+                r = trial_r;
+                current = trial_cost;
+                damping = (damping / 10.0).max(1e-12);
+                if converged {
+                    return params;
+                }
+                break;
+            }
+            damping *= 10.0;
+            if damping > 1e12 {
+                return params;
+            }
+        }
+    }
+    params
+}
@@ -0,0 +1,149 @@
+//! Finding which triangle of a 2D triangulation covers a point.
+
+use glam::{Vec2, Vec3};
+
+/// Buckets triangles on a uniform grid over their bounds, so a point only has
+/// to be tested against the few triangles overlapping its cell.
+pub(crate) struct TriangleLookup {
+    points: Vec<Vec2>,
+    triangles: Vec<[u32; 3]>,
+    min: Vec2,
+    max: Vec2,
+    cell_size: Vec2,
+    cells: u32,
+    buckets: Vec<Vec<usize>>,
+}
+
+impl TriangleLookup {
+    pub(crate) fn new(points: Vec<Vec2>, triangles: Vec<[u32; 3]>, cells: u32) -> Self {
+        let (min, max) = points.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        );
+        let cell_size = ((max - min) / cells as f32).max(Vec2::splat(1e-6));
+
+        let mut lookup = Self {
+            points,
+            triangles,
+            min,
+            max,
+            cell_size,
+            cells,
+            buckets: vec![Vec::new(); (cells * cells) as usize],
+        };
+        for t in 0..lookup.triangles.len() {
+            let [a, b, c] = lookup.corners(t);
+            let (x0, y0) = lookup.cell(a.min(b).min(c));
+            let (x1, y1) = lookup.cell(a.max(b).max(c));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    lookup.buckets[(y * cells + x) as usize].push(t);
+                }
+            }
+        }
+        lookup
+    }
+
+    fn corners(&self, triangle: usize) -> [Vec2; 3] {
+        self.triangles[triangle].map(|i| self.points[i as usize])
+    }
+
+    fn cell(&self, p: Vec2) -> (u32, u32) {
+        let c = ((p - self.min) / self.cell_size).floor();
+        let clamp = |v: f32| (v.max(0.0) as u32).min(self.cells - 1);
+        (clamp(c.x), clamp(c.y))
+    }
+
+    /// The vertex indices of the triangle covering `p`, with the barycentric
+    /// weights of `p` in it.
+    pub(crate) fn locate(&self, p: Vec2) -> Option<([u32; 3], Vec3)> {
+        if self.triangles.is_empty() || p.cmplt(self.min).any() || p.cmpgt(self.max).any() {
+            return None;
+        }
+        // points on the max edge belong to the last row or column of cells
+        let (x, y) = self.cell(p);
+        let index = (y * self.cells + x) as usize;
+        self.buckets[index].iter().find_map(|&t| {
+            let [a, b, c] = self.corners(t);
+            barycentric(p, a, b, c).map(|weights| (self.triangles[t], weights))
+        })
+    }
+}
+
+/// Barycentric weights of `p` in the triangle `abc`, if `p` is inside it.
+fn barycentric(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> Option<Vec3> {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = p - a;
+    let denominator = v0.perp_dot(v1);
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let v = v2.perp_dot(v1) / denominator;
+    let w = v0.perp_dot(v2) / denominator;
+    let u = 1.0 - v - w;
+    const EPSILON: f32 = -1e-5;
+    (u >= EPSILON && v >= EPSILON && w >= EPSILON).then_some(Vec3::new(u, v, w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit square split along its diagonal.
+    fn square() -> TriangleLookup {
+        let points = vec![
+            Vec2::ZERO,
+            Vec2::new(1.0, 0.0),
+            Vec2::ONE,
+            Vec2::new(0.0, 1.0),
+        ];
+        TriangleLookup::new(points, vec![[0, 1, 2], [0, 2, 3]], 4)
+    }
+
+    #[test]
+    fn finds_the_triangle_and_weights_inside() {
+        let lookup = square();
+        let (triangle, weights) = lookup.locate(Vec2::new(0.75, 0.25)).unwrap();
+        assert_eq!(triangle, [0, 1, 2]);
+        assert!((weights - Vec3::new(0.25, 0.5, 0.25)).abs().max_element() < 1e-5);
+        let (triangle, _) = lookup.locate(Vec2::new(0.25, 0.75)).unwrap();
+        assert_eq!(triangle, [0, 2, 3]);
+    }
+
+    #[test]
+    fn edges_are_inside() {
+        let lookup = square();
+        for p in [
+            Vec2::ZERO,
+            Vec2::new(0.5, 0.0),
+            Vec2::new(0.0, 0.5),
+            Vec2::new(0.5, 0.5),
+            Vec2::new(1.0, 0.5),
+            Vec2::new(0.5, 1.0),
+            Vec2::ONE,
+        ] {
+            let (triangle, weights) = lookup.locate(p).unwrap_or_else(|| panic!("{p} missed"));
+            let [a, b, c] = triangle.map(|i| lookup.points[i as usize]);
+            let back = a * weights.x + b * weights.y + c * weights.z;
+            assert!(back.distance(p) < 1e-5, "{p} came back as {back}");
+        }
+    }
+
+    #[test]
+    fn outside_is_none() {
+        let lookup = square();
+        for p in [
+            Vec2::new(-0.01, 0.5),
+            Vec2::new(0.5, -0.01),
+            Vec2::new(1.01, 0.5),
+            Vec2::new(0.5, 1.01),
+        ] {
+            assert!(lookup.locate(p).is_none(), "{p}");
+        }
+        // within the bounds but outside every triangle
+        let points = vec![Vec2::ZERO, Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)];
+        let lookup = TriangleLookup::new(points, vec![[0, 1, 2]], 4);
+        assert!(lookup.locate(Vec2::new(0.9, 0.9)).is_none());
+    }
+}
@@ -0,0 +1,449 @@
+//! Baking the eye-projected artwork into the UV texture of an arbitrary mesh.
+//!
+//! Each texel is located on the mesh surface through its UV coordinates, then
+//! traced back to the eye. Texels the eye can see take the artwork colour seen
+//! along that sight line, so the baked texture reads correctly from the eye
+//! once applied as an ordinary base colour texture.
+
+use std::{error::Error, fmt, fs, path::Path};
+
+use glam::{Mat4, Vec2, Vec3};
+use image::RgbaImage;
+
+use crate::lookup::TriangleLookup;
+use crate::projection::PlaneRect;
+use crate::warp::{self, WarpOptions};
+
+/// An indexed triangle list with one UV per vertex.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    /// A line that couldn't be parsed, with its 1-based line number.
+    Parse(usize, String),
+    /// A face refers to a position or UV that doesn't exist.
+    BadIndex(usize),
+    /// A face vertex has no texture coordinate, so it can't be baked.
+    MissingUv(usize),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "could not read obj: {e}"),
+            ObjError::Parse(line, text) => write!(f, "line {line}: could not parse {text:?}"),
+            ObjError::BadIndex(line) => write!(f, "line {line}: index out of range"),
+            ObjError::MissingUv(line) => write!(f, "line {line}: face vertex has no uv"),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(e: std::io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, uvs: Vec<Vec2>, indices: Vec<u32>) -> Self {
+        Self {
+            positions,
+            uvs,
+            indices,
+        }
+    }
+
+    pub fn load_obj(path: impl AsRef<Path>) -> Result<Self, ObjError> {
+        Self::from_obj(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the positions, texture coordinates and faces of a Wavefront OBJ.
+    /// Polygons are fanned into triangles; normals, groups and materials are
+    /// ignored. OBJ puts `v = 0` at the bottom of the texture, so it is flipped
+    /// to match the top-down UVs Bevy uses.
+    pub fn from_obj(source: &str) -> Result<Self, ObjError> {
+        use std::collections::HashMap;
+
+        let mut obj_positions = Vec::new();
+        let mut obj_uvs = Vec::new();
+        let mut mesh = TriangleMesh::default();
+        let mut vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let number = i + 1;
+            let parse_error = || ObjError::Parse(number, line.to_string());
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => {
+                    let v: Vec<f32> = parts
+                        .take(3)
+                        .map(|p| p.parse().map_err(|_| parse_error()))
+                        .collect::<Result<_, _>>()?;
+                    if v.len() != 3 {
+                        return Err(parse_error());
+                    }
+                    obj_positions.push(Vec3::new(v[0], v[1], v[2]));
+                }
+                Some("vt") => {
+                    let vt: Vec<f32> = parts
+                        .take(2)
+                        .map(|p| p.parse().map_err(|_| parse_error()))
+                        .collect::<Result<_, _>>()?;
+                    if vt.len() != 2 {
+                        return Err(parse_error());
+                    }
+                    obj_uvs.push(Vec2::new(vt[0], 1.0 - vt[1]));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in parts {
+                        let mut refs = corner.split('/');
+                        let position = resolve(refs.next(), obj_positions.len())
+                            .ok_or(ObjError::BadIndex(number))?;
+                        let uv = match refs.next() {
+                            Some(r) if !r.is_empty() => {
+                                resolve(Some(r), obj_uvs.len()).ok_or(ObjError::BadIndex(number))?
+                            }
+                            _ => return Err(ObjError::MissingUv(number)),
+                        };
+                        let index = *vertices.entry((position, uv)).or_insert_with(|| {
+                            mesh.positions.push(obj_positions[position]);
+                            mesh.uvs.push(obj_uvs[uv]);
+                            mesh.positions.len() as u32 - 1
+                        });
+                        face.push(index);
+                    }
+                    if face.len() < 3 {
+                        return Err(parse_error());
+                    }
+                    for k in 1..face.len() - 1 {
+                        mesh.indices.extend([face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    /// The mesh with its positions moved by `transform`, e.g. into world space.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        Self {
+            positions: self
+                .positions
+                .iter()
+                .map(|p| transform.transform_point3(*p))
+                .collect(),
+            uvs: self.uvs.clone(),
+            indices: self.indices.clone(),
+        }
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+}
+
+/// Resolves a 1-based (or negative, relative) OBJ index.
+fn resolve(reference: Option<&str>, len: usize) -> Option<usize> {
+    let index: i64 = reference?.parse().ok()?;
+    let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+    (0..len as i64).contains(&resolved).then_some(resolved as usize)
+}
+
+/// Bakes `artwork`, hung on `picture` and seen from `eye`, into a
+/// `width` x `height` texture addressed by the UVs of `mesh`. The mesh must
+/// already be in world space, and its UVs should not overlap. Triangles wound
+/// clockwise from the eye face away from it and are left transparent.
+pub fn bake(
+    mesh: &TriangleMesh,
+    eye: Vec3,
+    picture: &PlaneRect,
+    artwork: &RgbaImage,
+    width: u32,
+    height: u32,
+    options: WarpOptions,
+) -> RgbaImage {
+    let bvh = Bvh::new(mesh);
+    let uv_lookup = TriangleLookup::new(mesh.uvs.clone(), mesh.triangles().collect(), 64);
+    // the point a texel lies on, and its face's normal
+    let surface_point = |uv: Vec2| {
+        uv_lookup.locate(uv).map(|([a, b, c], weights)| {
+            let [a, b, c] = [a, b, c].map(|i| mesh.positions[i as usize]);
+            (a * weights.x + b * weights.y + c * weights.z, (b - a).cross(c - a))
+        })
+    };
+    let artwork_scale = Vec2::new(artwork.width() as f32, artwork.height() as f32) / picture.size;
+    let texture_size = Vec2::new(width as f32, height as f32);
+
+    let mut baked = warp::warp(artwork, width, height, options, |p| {
+        let (point, normal) = surface_point(p / texture_size)?;
+        let direction = point - eye;
+        if normal.dot(direction) >= 0.0 {
+            return None;
+        }
+        // anything nearer the eye along the same sight line hides this point
+        if let Some(t) = bvh.nearest_hit(eye, direction) {
+            if t < 1.0 - 1e-4 {
+                return None;
+            }
+        }
+        let (_, coords) = picture.intersect_ray(eye, direction)?;
+        Some(coords * artwork_scale)
+    });
+
+    // bleed island edges outwards so texture filtering doesn't pull in the
+    // empty space between UV islands
+    let covered: Vec<bool> = (0..width * height)
+        .map(|i| {
+            let p = Vec2::new((i % width) as f32, (i / width) as f32) + 0.5;
+            surface_point(p / texture_size).is_some()
+        })
+        .collect();
+    pad_islands(&mut baked, covered, 4);
+    baked
+}
+
+/// Copies texels from covered neighbours into uncovered ones, `passes` times.
+fn pad_islands(image: &mut RgbaImage, mut covered: Vec<bool>, passes: u32) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    for _ in 0..passes {
+        let mut next = covered.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if covered[index] {
+                    continue;
+                }
+                let neighbour = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .map(|(dx, dy)| (x + dx, y + dy))
+                    .find(|&(nx, ny)| {
+                        nx >= 0 && ny >= 0 && nx < width && ny < height
+                            && covered[(ny * width + nx) as usize]
+                    });
+                if let Some((nx, ny)) = neighbour {
+                    let pixel = *image.get_pixel(nx as u32, ny as u32);
+                    image.put_pixel(x as u32, y as u32, pixel);
+                    next[index] = true;
+                }
+            }
+        }
+        covered = next;
+    }
+}
+
+/// A bounding volume hierarchy over the mesh triangles, for sight line tests.
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[Vec3; 3]>,
+}
+
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    /// Children for interior nodes, triangle range for leaves.
+    kind: BvhKind,
+}
+
+enum BvhKind {
+    Interior(usize, usize),
+    Leaf(usize, usize),
+}
+
+impl Bvh {
+    const LEAF_SIZE: usize = 4;
+
+    fn new(mesh: &TriangleMesh) -> Self {
+        let mut triangles: Vec<[Vec3; 3]> = mesh
+            .triangles()
+            .map(|t| t.map(|i| mesh.positions[i as usize]))
+            .collect();
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            let len = triangles.len();
+            Self::build(&mut nodes, &mut triangles, 0, len);
+        }
+        Self { nodes, triangles }
+    }
+
+    fn build(nodes: &mut Vec<BvhNode>, triangles: &mut [[Vec3; 3]], start: usize, end: usize) -> usize {
+        let (min, max) = triangles[start..end].iter().flatten().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        );
+        let index = nodes.len();
+        nodes.push(BvhNode {
+            min,
+            max,
+            kind: BvhKind::Leaf(start, end),
+        });
+        if end - start <= Self::LEAF_SIZE {
+            return index;
+        }
+
+        // split at the median along the longest axis
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let centroid = |t: &[Vec3; 3]| (t[0] + t[1] + t[2])[axis];
+        triangles[start..end].sort_by(|a, b| centroid(a).total_cmp(&centroid(b)));
+        let middle = (start + end) / 2;
+        let left = Self::build(nodes, triangles, start, middle);
+        let right = Self::build(nodes, triangles, middle, end);
+        nodes[index].kind = BvhKind::Interior(left, right);
+        index
+    }
+
+    /// Smallest `t > 0` at which `origin + t * direction` hits a triangle.
+    fn nearest_hit(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inverse = direction.recip();
+        let mut nearest: Option<f32> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let limit = nearest.unwrap_or(f32::MAX);
+            if !ray_hits_box(origin, inverse, node.min, node.max, limit) {
+                continue;
+            }
+            match node.kind {
+                BvhKind::Interior(left, right) => stack.extend([left, right]),
+                BvhKind::Leaf(start, end) => {
+                    for triangle in &self.triangles[start..end] {
+                        if let Some(t) = ray_triangle(origin, direction, triangle) {
+                            if t < nearest.unwrap_or(f32::MAX) {
+                                nearest = Some(t);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        nearest
+    }
+}
+
+fn ray_hits_box(origin: Vec3, inverse_direction: Vec3, min: Vec3, max: Vec3, limit: f32) -> bool {
+    let t0 = (min - origin) * inverse_direction;
+    let t1 = (max - origin) * inverse_direction;
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element().min(limit);
+    near <= far
+}
+
+/// Möller–Trumbore ray/triangle intersection, double-sided.
+fn ray_triangle(origin: Vec3, direction: Vec3, [a, b, c]: &[Vec3; 3]) -> Option<f32> {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = origin - *a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) * inverse;
+    (t > 1e-6).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A unit square in the z = 0 plane, facing +z.
+    fn square() -> TriangleMesh {
+        TriangleMesh::new(
+            vec![Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y],
+            vec![Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0)],
+            vec![0, 1, 2, 0, 2, 3],
+        )
+    }
+
+    #[test]
+    fn obj_faces_are_fanned_and_uvs_flipped() {
+        let mesh = TriangleMesh::from_obj(
+            "# a square\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1 -1/-1/1\n",
+        )
+        .unwrap();
+        assert_eq!(mesh.positions, square().positions);
+        assert_eq!(mesh.uvs, square().uvs);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn obj_corners_sharing_a_position_and_uv_are_shared() {
+        let mesh = TriangleMesh::from_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nvt 0 0\nf 1/1 2/1 3/1\nf 2/1 4/1 3/1\n").unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 1, 3, 2]);
+    }
+
+    #[test]
+    fn obj_errors_name_the_line() {
+        assert!(matches!(TriangleMesh::from_obj("v 0 0\n"), Err(ObjError::Parse(1, _))));
+        assert!(matches!(TriangleMesh::from_obj("v 0 0 0\nvt 0 0\nf 1/1 2/1 3/1\n"), Err(ObjError::BadIndex(3))));
+        assert!(matches!(TriangleMesh::from_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"), Err(ObjError::MissingUv(4))));
+        assert!(matches!(TriangleMesh::from_obj("v 0 0 0\nvt 0 0\nf 1/1 1/1\n"), Err(ObjError::Parse(3, _))));
+    }
+
+    #[test]
+    fn bvh_finds_the_nearest_of_many_triangles() {
+        // a stack of squares, one every unit along z
+        let mut mesh = TriangleMesh::default();
+        for layer in 0..10 {
+            let base = mesh.positions.len() as u32;
+            let lifted = square().transformed(Mat4::from_translation(Vec3::Z * layer as f32));
+            mesh.positions.extend(lifted.positions);
+            mesh.uvs.extend(lifted.uvs);
+            mesh.indices.extend(lifted.indices.iter().map(|i| base + i));
+        }
+        let bvh = Bvh::new(&mesh);
+        let hit = |origin: Vec3, direction: Vec3| bvh.nearest_hit(origin, direction);
+        assert_eq!(hit(Vec3::new(0.3, 0.6, 20.0), Vec3::NEG_Z), Some(11.0));
+        assert_eq!(hit(Vec3::new(0.3, 0.6, 4.5), Vec3::NEG_Z), Some(0.5));
+        assert_eq!(hit(Vec3::new(0.3, 0.6, -1.0), Vec3::Z * 2.0), Some(0.5));
+        assert_eq!(hit(Vec3::new(1.5, 0.6, 20.0), Vec3::NEG_Z), None);
+        assert_eq!(Bvh::new(&TriangleMesh::default()).nearest_hit(Vec3::ZERO, Vec3::X), None);
+    }
+
+    #[test]
+    fn faces_turned_away_from_the_eye_are_not_baked() {
+        let picture = PlaneRect::new(Vec3::new(-1.0, -1.0, 1.0), Vec3::X, Vec3::Y, Vec2::splat(3.0));
+        let artwork = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let bake_from = |eye: Vec3, mesh: &TriangleMesh| bake(mesh, eye, &picture, &artwork, 8, 8, WarpOptions::default());
+
+        let front = bake_from(Vec3::new(0.5, 0.5, 2.0), &square());
+        assert!(front.pixels().all(|p| *p == Rgba([255, 0, 0, 255])));
+
+        let mut turned = square();
+        turned.indices = vec![0, 2, 1, 0, 3, 2];
+        let back = bake_from(Vec3::new(0.5, 0.5, 2.0), &turned);
+        assert!(back.pixels().all(|p| p[3] == 0));
+    }
+}
@@ -0,0 +1,384 @@
+//! Splitting floor art into printable sheets at its real size.
+//!
+//! Each sheet has a blank margin holding crop marks and a label, and overlaps
+//! its right and lower neighbours by a strip of artwork. Crosses printed in
+//! that strip land on top of each other when two sheets are lined up.
+
+use std::{error::Error, fmt, fs::File, io::{self, BufWriter}, path::Path};
+
+use glam::Vec2;
+use image::{imageops, Pixel, Rgba, RgbaImage};
+use imageproc::drawing::{draw_hollow_circle_mut, draw_line_segment_mut};
+
+use crate::fonts::FontRegistry;
+use crate::text::draw_text;
+use crate::warp::{self, Filter, WarpOptions};
+
+const MM_PER_INCH: f32 = 25.4;
+const MARK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PosterError {
+    /// The margins and overlap take up the whole sheet.
+    NoRoom,
+    /// The poster or its resolution is not a positive size.
+    BadSize,
+}
+
+impl fmt::Display for PosterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PosterError::NoRoom => write!(f, "margins and overlap leave no room on the sheet"),
+            PosterError::BadSize => write!(f, "the poster and its dpi must be larger than nothing"),
+        }
+    }
+}
+
+impl Error for PosterError {}
+
+/// A sheet size, in portrait orientation unless rotated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paper {
+    pub width_mm: f32,
+    pub height_mm: f32,
+}
+
+impl Paper {
+    pub const A4: Self = Self { width_mm: 210.0, height_mm: 297.0 };
+    pub const A3: Self = Self { width_mm: 297.0, height_mm: 420.0 };
+
+    pub fn landscape(self) -> Self {
+        Self {
+            width_mm: self.height_mm,
+            height_mm: self.width_mm,
+        }
+    }
+
+    fn size_mm(&self) -> Vec2 {
+        Vec2::new(self.width_mm, self.height_mm)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PosterOptions {
+    pub paper: Paper,
+    pub dpi: f32,
+    /// Blank border around the printed area, holding the marks and label.
+    pub margin_mm: f32,
+    /// How far each sheet's artwork runs under its right and lower neighbours.
+    pub overlap_mm: f32,
+    /// Where the poster's top left corner sits on the floor, in metres, as
+    /// printed on the labels.
+    pub origin: Vec2,
+    /// Printed wherever the artwork is transparent.
+    pub background: Rgba<u8>,
+    pub filter: Filter,
+}
+
+impl Default for PosterOptions {
+    fn default() -> Self {
+        Self {
+            paper: Paper::A4,
+            dpi: 150.0,
+            margin_mm: 12.0,
+            overlap_mm: 10.0,
+            origin: Vec2::ZERO,
+            background: Rgba([255, 255, 255, 255]),
+            filter: Filter::Bilinear,
+        }
+    }
+}
+
+/// One printed sheet. Sheets are named like spreadsheet cells: columns are
+/// lettered left to right and rows numbered top to bottom, so `B3` is the
+/// second sheet of the third row.
+pub struct PosterSheet {
+    pub column: u32,
+    pub row: u32,
+    pub image: RgbaImage,
+}
+
+impl PosterSheet {
+    pub fn name(&self) -> String {
+        sheet_name(self.column, self.row)
+    }
+
+    /// Writes the sheet as a PNG that records its resolution, so it prints at
+    /// the right size.
+    pub fn save(&self, path: impl AsRef<Path>, dpi: f32) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            self.image.width(),
+            self.image.height(),
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels_per_metre = (dpi / MM_PER_INCH * 1000.0).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: pixels_per_metre,
+            yppu: pixels_per_metre,
+            unit: png::Unit::Meter,
+        }));
+        encoder.write_header()?.write_image_data(self.image.as_raw())?;
+        Ok(())
+    }
+}
+
+/// `A` to `Z`, then `AA`, `AB`, ...
+pub(crate) fn column_name(column: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = column + 1;
+    while n > 0 {
+        letters.push(b'A' + ((n - 1) % 26) as u8);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap()
+}
+
+pub(crate) fn sheet_name(column: u32, row: u32) -> String {
+    format!("{}{}", column_name(column), row + 1)
+}
+
+/// The smallest part of `image` holding every visible pixel, and where it
+/// starts.
+pub fn crop_to_content(image: &RgbaImage) -> Option<(RgbaImage, Vec2)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] > 0 {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    if min_x > max_x {
+        return None;
+    }
+    let cropped = imageops::crop_imm(image, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1);
+    Some((cropped.to_image(), Vec2::new(min_x as f32, min_y as f32)))
+}
+
+/// Splits `artwork`, printed at `size` metres, into sheets. Returned in
+/// reading order.
+pub fn tile_poster(artwork: &RgbaImage, size: Vec2, options: &PosterOptions) -> Result<Vec<PosterSheet>, PosterError> {
+    let size_mm = size * 1000.0;
+    let paper = options.paper.size_mm();
+    let printable = paper - 2.0 * options.margin_mm;
+    let step = printable - options.overlap_mm;
+    if !step.cmpgt(Vec2::ZERO).all() {
+        return Err(PosterError::NoRoom);
+    }
+    if !size.cmpgt(Vec2::ZERO).all() || options.dpi <= 0.0 || options.dpi.is_nan() {
+        return Err(PosterError::BadSize);
+    }
+
+    let columns = ((size_mm.x - options.overlap_mm) / step.x).ceil().max(1.0) as u32;
+    let rows = ((size_mm.y - options.overlap_mm) / step.y).ceil().max(1.0) as u32;
+
+    let pixels_per_mm = options.dpi / MM_PER_INCH;
+    let mut fonts = FontRegistry::bundled();
+    let sheet_pixels = (paper * pixels_per_mm).round();
+    let artwork_scale = Vec2::new(artwork.width() as f32, artwork.height() as f32) / size_mm;
+    let warp_options = WarpOptions {
+        filter: options.filter,
+        supersample: 1,
+    };
+
+    let mut sheets = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let offset = Vec2::new(column as f32, row as f32) * step;
+            let mut image = warp::warp(
+                artwork,
+                sheet_pixels.x as u32,
+                sheet_pixels.y as u32,
+                warp_options,
+                |p| {
+                    let local = p / pixels_per_mm - options.margin_mm;
+                    let poster = local + offset;
+                    let inside = local.cmpge(Vec2::ZERO).all()
+                        && local.cmple(printable).all()
+                        && poster.cmple(size_mm).all();
+                    inside.then_some(poster * artwork_scale)
+                },
+            );
+            for pixel in image.pixels_mut() {
+                let mut background = options.background;
+                background.blend(pixel);
+                *pixel = background;
+            }
+
+            let sheet = Sheet {
+                column,
+                row,
+                columns,
+                rows,
+                step,
+                overlap: options.overlap_mm,
+                printable,
+                margin: options.margin_mm,
+                pixels_per_mm,
+            };
+            sheet.crop_marks(&mut image);
+            sheet.alignment_crosses(&mut image);
+            let label = format!(
+                "{}   column {} of {}, row {} of {}   x {:.3} m, y {:.3} m   {:.0} dpi",
+                sheet_name(column, row),
+                column + 1,
+                columns,
+                row + 1,
+                rows,
+                options.origin.x + offset.x / 1000.0,
+                options.origin.y + offset.y / 1000.0,
+                options.dpi,
+            );
+            let font_size = (options.margin_mm * 0.3 * pixels_per_mm).max(8.0);
+            draw_text(
+                &mut fonts,
+                &mut image,
+                &label,
+                font_size,
+                sheet.pixel(Vec2::new(0.0, printable.y + options.margin_mm * 0.4)),
+                MARK,
+            );
+            sheets.push(PosterSheet { column, row, image });
+        }
+    }
+    Ok(sheets)
+}
+
+/// Sheet geometry for drawing marks. Positions are in millimetres from the
+/// printed area's top left corner.
+struct Sheet {
+    column: u32,
+    row: u32,
+    columns: u32,
+    rows: u32,
+    /// Distance between the printed areas of neighbouring sheets.
+    step: Vec2,
+    overlap: f32,
+    printable: Vec2,
+    margin: f32,
+    pixels_per_mm: f32,
+}
+
+impl Sheet {
+    fn point(&self, mm: Vec2) -> (f32, f32) {
+        let p = (mm + self.margin) * self.pixels_per_mm;
+        (p.x, p.y)
+    }
+
+    fn pixel(&self, mm: Vec2) -> (i32, i32) {
+        let (x, y) = self.point(mm);
+        (x.round() as i32, y.round() as i32)
+    }
+
+    fn line(&self, image: &mut RgbaImage, from: Vec2, to: Vec2) {
+        draw_line_segment_mut(image, self.point(from), self.point(to), MARK);
+    }
+
+    /// Corner ticks in the margin, lined up with the edges of the printed
+    /// area, to trim along.
+    fn crop_marks(&self, image: &mut RgbaImage) {
+        let gap = self.margin * 0.15;
+        let length = self.margin * 0.6;
+        for corner in [Vec2::ZERO, Vec2::new(self.printable.x, 0.0), self.printable, Vec2::new(0.0, self.printable.y)] {
+            let outward = Vec2::new(
+                if corner.x > 0.0 { 1.0 } else { -1.0 },
+                if corner.y > 0.0 { 1.0 } else { -1.0 },
+            );
+            let horizontal = corner + Vec2::X * outward.x * gap;
+            self.line(image, horizontal, horizontal + Vec2::X * outward.x * length);
+            let vertical = corner + Vec2::Y * outward.y * gap;
+            self.line(image, vertical, vertical + Vec2::Y * outward.y * length);
+        }
+    }
+
+    /// Circled crosses in the middle of every strip shared with a neighbour,
+    /// at the same poster position on both sheets.
+    fn alignment_crosses(&self, image: &mut RgbaImage) {
+        let (step, overlap) = (self.step, self.overlap);
+        let mut centres = Vec::new();
+        // strips to the left (shared with the previous column) and right
+        for boundary in [self.column, self.column + 1] {
+            if boundary == 0 || boundary >= self.columns {
+                continue;
+            }
+            let x = boundary as f32 * step.x + overlap / 2.0;
+            let y = self.row as f32 * step.y;
+            centres.push(Vec2::new(x, y + self.printable.y * 0.25));
+            centres.push(Vec2::new(x, y + self.printable.y * 0.75));
+        }
+        // and above and below
+        for boundary in [self.row, self.row + 1] {
+            if boundary == 0 || boundary >= self.rows {
+                continue;
+            }
+            let y = boundary as f32 * step.y + overlap / 2.0;
+            let x = self.column as f32 * step.x;
+            centres.push(Vec2::new(x + self.printable.x * 0.25, y));
+            centres.push(Vec2::new(x + self.printable.x * 0.75, y));
+        }
+
+        let offset = Vec2::new(self.column as f32, self.row as f32) * step;
+        let arm = (overlap * 0.4).max(1.0);
+        for centre in centres {
+            let local = centre - offset;
+            self.line(image, local - Vec2::X * arm, local + Vec2::X * arm);
+            self.line(image, local - Vec2::Y * arm, local + Vec2::Y * arm);
+            let (x, y) = self.pixel(local);
+            draw_hollow_circle_mut(image, (x, y), (arm * 0.6 * self.pixels_per_mm) as i32, MARK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_lettered_like_a_spreadsheet() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(27), "AB");
+        assert_eq!(sheet_name(1, 2), "B3");
+    }
+
+    #[test]
+    fn sheets_cover_the_poster_in_reading_order() {
+        let artwork = RgbaImage::from_pixel(40, 30, Rgba([255, 0, 0, 255]));
+        let options = PosterOptions { dpi: 25.4, ..Default::default() };
+        // each A4 sheet steps on by 176 x 263 mm
+        let sheets = tile_poster(&artwork, Vec2::new(0.4, 0.3), &options).unwrap();
+        let names: Vec<String> = sheets.iter().map(PosterSheet::name).collect();
+        assert_eq!(names, ["A1", "B1", "C1", "A2", "B2", "C2"]);
+        // a millimetre a pixel
+        assert_eq!(sheets[0].image.dimensions(), (210, 297));
+        assert_eq!(sheets[0].image.get_pixel(100, 100), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn sheets_need_room_for_the_artwork() {
+        let artwork = RgbaImage::new(4, 4);
+        let crowded = PosterOptions { margin_mm: 100.0, ..Default::default() };
+        assert_eq!(tile_poster(&artwork, Vec2::ONE, &crowded).err(), Some(PosterError::NoRoom));
+        let overlapping = PosterOptions { overlap_mm: 200.0, ..Default::default() };
+        assert_eq!(tile_poster(&artwork, Vec2::ONE, &overlapping).err(), Some(PosterError::NoRoom));
+        assert_eq!(tile_poster(&artwork, Vec2::new(1.0, 0.0), &PosterOptions::default()).err(), Some(PosterError::BadSize));
+        let no_dots = PosterOptions { dpi: 0.0, ..Default::default() };
+        assert_eq!(tile_poster(&artwork, Vec2::ONE, &no_dots).err(), Some(PosterError::BadSize));
+    }
+
+    #[test]
+    fn cropping_finds_the_visible_part() {
+        let mut image = RgbaImage::new(10, 10);
+        image.put_pixel(2, 3, MARK);
+        image.put_pixel(5, 7, MARK);
+        let (cropped, offset) = crop_to_content(&image).unwrap();
+        assert_eq!(cropped.dimensions(), (4, 5));
+        assert_eq!(offset, Vec2::new(2.0, 3.0));
+        assert!(crop_to_content(&RgbaImage::new(3, 3)).is_none());
+    }
+}
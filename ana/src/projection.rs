@@ -0,0 +1,409 @@
+//! Eye-point anamorphic projection.
+//!
+//! The artwork is hung on a virtual "picture plane" in front of the viewer,
+//! where it should appear upright. Every point of it is pushed along the ray
+//! from the eye until it lands on the target surface. Between two planes that
+//! central projection is a homography, so a planar layout is fully described
+//! by one 3x3 matrix.
+
+use std::{error::Error, fmt};
+
+use glam::{Mat3, Vec2, Vec3};
+use image::RgbaImage;
+
+use crate::linalg;
+use crate::warp::{self, WarpOptions};
+
+/// A bounded rectangle in 3D, addressed by 2D coordinates in metres.
+///
+/// `(0, 0)` is `origin`, `(size.x, size.y)` the opposite corner. When an image
+/// is mapped onto the rectangle, pixel rows run along `v_axis`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneRect {
+    pub origin: Vec3,
+    pub u_axis: Vec3,
+    pub v_axis: Vec3,
+    pub size: Vec2,
+}
+
+impl PlaneRect {
+    pub fn new(origin: Vec3, u_axis: Vec3, v_axis: Vec3, size: Vec2) -> Self {
+        Self {
+            origin,
+            u_axis: u_axis.normalize(),
+            v_axis: v_axis.normalize(),
+            size,
+        }
+    }
+
+    /// A horizontal rectangle matching `shape::Plane::from_size` placed at
+    /// `center`: `u` runs along +X and `v` along +Z, like the plane's UVs.
+    pub fn ground(center: Vec3, size: Vec2) -> Self {
+        Self::new(
+            center - Vec3::new(size.x, 0.0, size.y) / 2.0,
+            Vec3::X,
+            Vec3::Z,
+            size,
+        )
+    }
+
+    /// An upright rectangle centred on `center`, square-on to the line of
+    /// sight from `eye`. `u` points to the viewer's right and `v` down, so an
+    /// image mapped onto it reads normally from the eye. Looking straight
+    /// down, the top of the rectangle points towards -Z.
+    pub fn facing(eye: Vec3, center: Vec3, size: Vec2) -> Self {
+        let forward = (center - eye).normalize();
+        let up = if forward.cross(Vec3::Y).length_squared() < 1e-6 {
+            Vec3::NEG_Z
+        } else {
+            Vec3::Y
+        };
+        let right = forward.cross(up).normalize();
+        let down = forward.cross(right);
+        Self::new(
+            center - right * size.x / 2.0 - down * size.y / 2.0,
+            right,
+            down,
+            size,
+        )
+    }
+
+    /// Unit normal pointing away from the side the rectangle is read from,
+    /// i.e. along the viewer's line of sight.
+    pub fn normal(&self) -> Vec3 {
+        self.u_axis.cross(self.v_axis).normalize()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.point_at(self.size / 2.0)
+    }
+
+    pub fn point_at(&self, coords: Vec2) -> Vec3 {
+        self.origin + self.u_axis * coords.x + self.v_axis * coords.y
+    }
+
+    /// Plane coordinates of `point`, assuming it lies on the plane.
+    pub fn coords_of(&self, point: Vec3) -> Vec2 {
+        let offset = point - self.origin;
+        Vec2::new(offset.dot(self.u_axis), offset.dot(self.v_axis))
+    }
+
+    pub fn contains(&self, coords: Vec2) -> bool {
+        coords.cmpge(Vec2::ZERO).all() && coords.cmple(self.size).all()
+    }
+
+    /// Corners in plane coordinates, clockwise from the origin.
+    pub fn corner_coords(&self) -> [Vec2; 4] {
+        [
+            Vec2::ZERO,
+            Vec2::new(self.size.x, 0.0),
+            self.size,
+            Vec2::new(0.0, self.size.y),
+        ]
+    }
+
+    pub fn corners(&self) -> [Vec3; 4] {
+        self.corner_coords().map(|c| self.point_at(c))
+    }
+
+    /// Intersects the ray `origin + t * direction` with the (unbounded) plane.
+    /// Returns `t` and the plane coordinates of the hit, for `t > 0` only.
+    pub fn intersect_ray(&self, origin: Vec3, direction: Vec3) -> Option<(f32, Vec2)> {
+        let normal = self.normal();
+        let denominator = direction.dot(normal);
+        if denominator.abs() < 1e-9 {
+            return None;
+        }
+        let t = (self.origin - origin).dot(normal) / denominator;
+        if t <= 0.0 {
+            return None;
+        }
+        Some((t, self.coords_of(origin + direction * t)))
+    }
+}
+
+/// A projective mapping between the 2D coordinates of two planes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Homography(pub Mat3);
+
+impl Homography {
+    pub const IDENTITY: Self = Self(Mat3::IDENTITY);
+
+    /// Solves the homography taking each `src[i]` to `dst[i]`. Returns `None`
+    /// if three of the points are collinear.
+    pub fn from_correspondences(src: [Vec2; 4], dst: [Vec2; 4]) -> Option<Self> {
+        let mut a = Vec::with_capacity(8);
+        let mut b = Vec::with_capacity(8);
+        for (s, d) in src.iter().zip(dst.iter()) {
+            let (x, y) = (s.x as f64, s.y as f64);
+            let (u, v) = (d.x as f64, d.y as f64);
+            a.push(vec![x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u]);
+            b.push(u);
+            a.push(vec![0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v]);
+            b.push(v);
+        }
+        let h = linalg::solve(a, b)?;
+        Some(Self::from_row_major(&h))
+    }
+
+    /// Least squares homography taking `src` towards `dst`, from four or more
+    /// point pairs. The points are normalised first so the fit doesn't depend
+    /// on the units. Returns `None` for fewer than four pairs or degenerate
+    /// point sets.
+    pub fn fit(src: &[Vec2], dst: &[Vec2]) -> Option<Self> {
+        if src.len() < 4 || src.len() != dst.len() {
+            return None;
+        }
+        // move each set to the origin with mean distance sqrt(2)
+        let normaliser = |points: &[Vec2]| -> Option<Mat3> {
+            let mean = points.iter().sum::<Vec2>() / points.len() as f32;
+            let spread = points.iter().map(|p| p.distance(mean)).sum::<f32>() / points.len() as f32;
+            if spread < 1e-9 {
+                return None;
+            }
+            let scale = std::f32::consts::SQRT_2 / spread;
+            Some(Mat3::from_cols(
+                Vec3::new(scale, 0.0, 0.0),
+                Vec3::new(0.0, scale, 0.0),
+                Vec3::new(-mean.x * scale, -mean.y * scale, 1.0),
+            ))
+        };
+        let (from, to) = (normaliser(src)?, normaliser(dst)?);
+
+        let mut a = Vec::with_capacity(2 * src.len());
+        let mut b = Vec::with_capacity(2 * src.len());
+        for (s, d) in src.iter().zip(dst) {
+            let s = from.transform_point2(*s);
+            let d = to.transform_point2(*d);
+            let (x, y) = (s.x as f64, s.y as f64);
+            let (u, v) = (d.x as f64, d.y as f64);
+            a.push(vec![x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u]);
+            b.push(u);
+            a.push(vec![0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v]);
+            b.push(v);
+        }
+        let h = linalg::least_squares(&a, &b)?;
+        let normalised = Self::from_row_major(&h);
+        Some(Self(to.inverse() * normalised.0 * from))
+    }
+
+    /// Builds from the first eight entries of a row-major matrix, the ninth
+    /// being 1.
+    fn from_row_major(h: &[f64]) -> Self {
+        Self(Mat3::from_cols(
+            Vec3::new(h[0] as f32, h[3] as f32, h[6] as f32),
+            Vec3::new(h[1] as f32, h[4] as f32, h[7] as f32),
+            Vec3::new(h[2] as f32, h[5] as f32, 1.0),
+        ))
+    }
+
+    /// Applies the mapping without the perspective divide.
+    pub fn apply_homogeneous(&self, point: Vec2) -> Vec3 {
+        self.0 * point.extend(1.0)
+    }
+
+    /// Applies the mapping. Returns `None` for points sent to infinity.
+    pub fn apply(&self, point: Vec2) -> Option<Vec2> {
+        let p = self.apply_homogeneous(point);
+        if p.z.abs() < 1e-9 {
+            None
+        } else {
+            Some(p.truncate() / p.z)
+        }
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        if self.0.determinant().abs() < 1e-12 {
+            None
+        } else {
+            Some(Self(self.0.inverse()))
+        }
+    }
+
+    /// The mapping that applies `self` first and then `next`.
+    pub fn then(&self, next: &Homography) -> Self {
+        Self(next.0 * self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionError {
+    /// The eye lies on the target plane, so it sees it edge-on.
+    EyeOnTarget,
+    /// The sight line through this picture corner never reaches the target.
+    CornerMissesTarget(usize),
+    /// The picture projects onto the target as a degenerate quad.
+    Degenerate,
+}
+
+impl fmt::Display for ProjectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectionError::EyeOnTarget => write!(f, "eye lies on the target plane"),
+            ProjectionError::CornerMissesTarget(corner) => write!(
+                f,
+                "sight line through picture corner {corner} does not reach the target plane"
+            ),
+            ProjectionError::Degenerate => write!(f, "picture projects to a degenerate shape"),
+        }
+    }
+}
+
+impl Error for ProjectionError {}
+
+/// A solved eye / picture plane / target plane arrangement.
+#[derive(Clone, Copy, Debug)]
+pub struct AnamorphicLayout {
+    pub eye: Vec3,
+    pub picture: PlaneRect,
+    pub target: PlaneRect,
+    /// Maps picture coordinates to target coordinates.
+    pub picture_to_target: Homography,
+    pub target_to_picture: Homography,
+    /// The picture corners as they land on the target, in target coordinates.
+    pub footprint: [Vec2; 4],
+}
+
+impl AnamorphicLayout {
+    pub fn solve(eye: Vec3, picture: PlaneRect, target: PlaneRect) -> Result<Self, ProjectionError> {
+        if (eye - target.origin).dot(target.normal()).abs() < 1e-6 {
+            return Err(ProjectionError::EyeOnTarget);
+        }
+
+        let mut footprint = [Vec2::ZERO; 4];
+        for (i, corner) in picture.corners().iter().enumerate() {
+            let (_, coords) = target
+                .intersect_ray(eye, *corner - eye)
+                .ok_or(ProjectionError::CornerMissesTarget(i))?;
+            footprint[i] = coords;
+        }
+
+        let picture_to_target = Homography::from_correspondences(picture.corner_coords(), footprint)
+            .ok_or(ProjectionError::Degenerate)?;
+        let target_to_picture = picture_to_target
+            .inverse()
+            .ok_or(ProjectionError::Degenerate)?;
+
+        Ok(Self {
+            eye,
+            picture,
+            target,
+            picture_to_target,
+            target_to_picture,
+            footprint,
+        })
+    }
+
+    /// Picture coordinates seen through the target point `coords`, or `None`
+    /// if the sight line meets the picture plane behind the eye.
+    pub fn picture_coords(&self, coords: Vec2) -> Option<Vec2> {
+        let p = self.target_to_picture.apply_homogeneous(coords);
+        // every point of the footprint maps with the same sign of w; the
+        // other sign belongs to sight lines running backwards through the eye
+        let reference = self
+            .target_to_picture
+            .apply_homogeneous(self.footprint.iter().sum::<Vec2>() / 4.0);
+        if p.z * reference.z <= 0.0 {
+            None
+        } else {
+            Some(p.truncate() / p.z)
+        }
+    }
+
+    /// How far, in metres, the eye is from where picture `coords` land on
+    /// the target.
+    pub fn distance(&self, coords: Vec2) -> Option<f32> {
+        let target = self.picture_to_target.apply(coords)?;
+        Some(self.target.point_at(target).distance(self.eye))
+    }
+
+    /// Produces the pre-distorted target texture for `artwork`, which is
+    /// stretched over the whole picture rectangle. The texture covers the full
+    /// target rectangle at `pixels_per_metre`; outside the footprint it is
+    /// transparent.
+    pub fn render(&self, artwork: &RgbaImage, pixels_per_metre: f32, options: WarpOptions) -> RgbaImage {
+        let width = (self.target.size.x * pixels_per_metre).round().max(1.0) as u32;
+        let height = (self.target.size.y * pixels_per_metre).round().max(1.0) as u32;
+        let artwork_scale = Vec2::new(artwork.width() as f32, artwork.height() as f32)
+            / self.picture.size;
+
+        warp::warp(artwork, width, height, options, |p| {
+            self.picture_coords(p / pixels_per_metre)
+                .map(|coords| coords * artwork_scale)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-4, "{a} is not {b}");
+    }
+
+    #[test]
+    fn homography_round_trips_four_correspondences() {
+        let src = [Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0), Vec2::new(0.0, 1.0)];
+        let dst = [Vec2::new(1.0, 1.0), Vec2::new(4.0, 0.5), Vec2::new(3.5, 3.0), Vec2::new(0.5, 2.0)];
+        let h = Homography::from_correspondences(src, dst).unwrap();
+        let inverse = h.inverse().unwrap();
+        for (s, d) in src.iter().zip(&dst) {
+            assert_near(h.apply(*s).unwrap(), *d);
+            assert_near(inverse.apply(*d).unwrap(), *s);
+        }
+        // points in between go there and back too
+        let middle = Vec2::new(0.7, 0.4);
+        assert_near(inverse.apply(h.apply(middle).unwrap()).unwrap(), middle);
+    }
+
+    #[test]
+    fn fit_recovers_an_exact_homography() {
+        let h = Homography::from_correspondences(
+            [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y],
+            [Vec2::new(0.2, 0.1), Vec2::new(1.3, 0.0), Vec2::new(1.1, 0.9), Vec2::new(-0.1, 1.2)],
+        )
+        .unwrap();
+        let src: Vec<Vec2> = (0..3).flat_map(|i| (0..3).map(move |j| Vec2::new(i as f32, j as f32) / 2.0)).collect();
+        let dst: Vec<Vec2> = src.iter().map(|p| h.apply(*p).unwrap()).collect();
+        let fitted = Homography::fit(&src, &dst).unwrap();
+        for (s, d) in src.iter().zip(&dst) {
+            assert_near(fitted.apply(*s).unwrap(), *d);
+        }
+        assert!(Homography::from_correspondences([Vec2::ZERO, Vec2::X, Vec2::X * 2.0, Vec2::Y], [Vec2::ZERO; 4]).is_none());
+    }
+
+    #[test]
+    fn footprint_from_an_eye_overhead() {
+        // a level picture halfway down to the floor doubles in size
+        let eye = Vec3::new(0.0, 2.0, 0.0);
+        let picture = PlaneRect::new(Vec3::new(-0.5, 1.0, -0.5), Vec3::X, Vec3::Z, Vec2::ONE);
+        let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(10.0));
+        let layout = AnamorphicLayout::solve(eye, picture, floor).unwrap();
+        let expected = [Vec2::new(4.0, 4.0), Vec2::new(6.0, 4.0), Vec2::new(6.0, 6.0), Vec2::new(4.0, 6.0)];
+        for (corner, expected) in layout.footprint.iter().zip(expected) {
+            assert_near(*corner, expected);
+        }
+    }
+
+    #[test]
+    fn points_land_where_the_sight_line_meets_the_floor() {
+        let eye = Vec3::new(0.0, 1.7, 5.0);
+        let picture = PlaneRect::facing(eye, eye.lerp(Vec3::new(0.0, 0.0, 1.0), 0.5), Vec2::new(1.0, 0.6));
+        let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(12.0));
+        let layout = AnamorphicLayout::solve(eye, picture, floor).unwrap();
+        for coords in [Vec2::new(0.1, 0.1), Vec2::new(0.5, 0.3), Vec2::new(0.9, 0.55)] {
+            let through = picture.point_at(coords);
+            let (_, on_floor) = floor.intersect_ray(eye, through - eye).unwrap();
+            assert_near(layout.picture_to_target.apply(coords).unwrap(), on_floor);
+            assert_near(layout.picture_coords(on_floor).unwrap(), coords);
+        }
+    }
+
+    #[test]
+    fn an_eye_on_the_floor_is_refused() {
+        let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(10.0));
+        let picture = PlaneRect::new(Vec3::new(0.0, 1.0, 0.0), Vec3::X, Vec3::NEG_Y, Vec2::ONE);
+        assert_eq!(AnamorphicLayout::solve(Vec3::new(0.0, 0.0, 3.0), picture, floor).unwrap_err(), ProjectionError::EyeOnTarget);
+    }
+}
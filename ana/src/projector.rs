@@ -0,0 +1,292 @@
+//! Projected anamorphosis: the frame a projector has to show so its light
+//! paints the layout's target texture onto the surface.
+//!
+//! The projector is modelled like a camera running backwards: a pinhole with
+//! its image plane in front, looking down its local -Z with +Y up, as Bevy
+//! transforms do.
+
+use glam::{Mat3, Mat4, Quat, UVec2, Vec2, Vec3, Vec4};
+use image::RgbaImage;
+
+use crate::projection::{AnamorphicLayout, PlaneRect};
+use crate::warp::{self, WarpOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projector {
+    pub position: Vec3,
+    pub rotation: Quat,
+    /// Frame size in pixels.
+    pub resolution: UVec2,
+    /// Throw distance divided by the width of the picture it throws.
+    pub throw_ratio: f32,
+    /// Offset of the picture from the lens axis, as fractions of the picture's
+    /// width and height. `(0, 0.5)` puts the bottom edge of the picture on
+    /// the axis, as with most table and ceiling mounts.
+    pub lens_shift: Vec2,
+}
+
+impl Projector {
+    /// A projector at `position` aimed at `target`, with the top of its
+    /// picture towards `up`.
+    pub fn looking_at(
+        position: Vec3,
+        target: Vec3,
+        up: Vec3,
+        resolution: UVec2,
+        throw_ratio: f32,
+        lens_shift: Vec2,
+    ) -> Self {
+        let forward = (target - position).normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        Self {
+            position,
+            rotation: Quat::from_mat3(&Mat3::from_cols(right, up, -forward)),
+            resolution,
+            throw_ratio,
+            lens_shift,
+        }
+    }
+
+    /// The size of the picture one metre from the lens.
+    fn picture_size(&self) -> Vec2 {
+        let width = 1.0 / self.throw_ratio;
+        Vec2::new(width, width * self.resolution.y as f32 / self.resolution.x as f32)
+    }
+
+    /// Direction of the light leaving through `pixel`, given in continuous
+    /// frame coordinates (pixel `(i, j)` covers `[i, i + 1) x [j, j + 1)`).
+    pub fn ray(&self, pixel: Vec2) -> Vec3 {
+        let uv = pixel / self.resolution.as_vec2() - 0.5;
+        let local = (Vec2::new(uv.x, -uv.y) + self.lens_shift) * self.picture_size();
+        self.rotation * Vec3::new(local.x, local.y, -1.0)
+    }
+
+    /// Where the light hitting `point` leaves the frame, in frame
+    /// coordinates. `None` behind the projector; the result may fall outside
+    /// the frame.
+    pub fn project(&self, point: Vec3) -> Option<Vec2> {
+        let local = self.rotation.inverse() * (point - self.position);
+        if local.z >= 0.0 {
+            return None;
+        }
+        let on_plane = Vec2::new(local.x, local.y) / -local.z / self.picture_size() - self.lens_shift;
+        Some((Vec2::new(on_plane.x, -on_plane.y) + 0.5) * self.resolution.as_vec2())
+    }
+
+    /// World to clip space, for projecting in a shader: after the
+    /// perspective divide the frame spans -1 to 1 in x and y, with +y at its
+    /// top, and w is the depth in front of the lens. Matches
+    /// [`Projector::project`].
+    pub fn view_projection(&self) -> Mat4 {
+        let size = self.picture_size();
+        let shift = self.lens_shift * 2.0;
+        let projection = Mat4::from_cols(
+            Vec4::new(2.0 / size.x, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 2.0 / size.y, 0.0, 0.0),
+            Vec4::new(shift.x, shift.y, 0.0, -1.0),
+            Vec4::ZERO,
+        );
+        projection * Mat4::from_rotation_translation(self.rotation, self.position).inverse()
+    }
+
+    /// The solid angle the frame covers, in steradians, for spreading the
+    /// light's output over it.
+    pub fn solid_angle(&self) -> f32 {
+        // the frame one metre out, split at the lens axis into four
+        // rectangles that each have a corner on it
+        let size = self.picture_size();
+        let min = (self.lens_shift - 0.5) * size;
+        let max = (self.lens_shift + 0.5) * size;
+        let corner = |x: f32, y: f32| (x * y / (1.0 + x * x + y * y).sqrt()).atan();
+        corner(max.x, max.y) - corner(min.x, max.y) - corner(max.x, min.y) + corner(min.x, min.y)
+    }
+
+    /// The corners of the frame at `distance` along the lens axis, clockwise
+    /// from the top left, for drawing the light cone.
+    pub fn frame_corners(&self, distance: f32) -> [Vec3; 4] {
+        let size = self.resolution.as_vec2();
+        [Vec2::ZERO, Vec2::new(size.x, 0.0), size, Vec2::new(0.0, size.y)]
+            .map(|pixel| self.position + self.ray(pixel) * distance)
+    }
+
+    /// The frame to show so that `artwork` reads correctly from the layout's
+    /// eye. Light that falls outside the target rectangle, or would be cast
+    /// onto it from behind, is black.
+    pub fn render(&self, layout: &AnamorphicLayout, artwork: &RgbaImage, options: WarpOptions) -> RgbaImage {
+        let artwork_scale = Vec2::new(artwork.width() as f32, artwork.height() as f32)
+            / layout.picture.size;
+        let front = layout.target.normal().dot(self.position - layout.target.origin) < 0.0;
+        let mut frame = warp::warp(artwork, self.resolution.x, self.resolution.y, options, |p| {
+            if !front {
+                return None;
+            }
+            let (_, coords) = layout.target.intersect_ray(self.position, self.ray(p))?;
+            if !layout.target.contains(coords) {
+                return None;
+            }
+            layout.picture_coords(coords).map(|coords| coords * artwork_scale)
+        });
+        // a projector can only add light
+        for pixel in frame.pixels_mut() {
+            let alpha = pixel[3] as u16;
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as u16 * alpha / 255) as u8;
+            }
+            pixel[3] = 255;
+        }
+        frame
+    }
+
+    /// What `frame` looks like thrown onto `surface`, at `pixels_per_metre`
+    /// over the whole rectangle. Unlit parts are transparent.
+    pub fn illuminate(
+        &self,
+        frame: &RgbaImage,
+        surface: &PlaneRect,
+        pixels_per_metre: f32,
+        options: WarpOptions,
+    ) -> RgbaImage {
+        let width = (surface.size.x * pixels_per_metre).round().max(1.0) as u32;
+        let height = (surface.size.y * pixels_per_metre).round().max(1.0) as u32;
+        let frame_scale = Vec2::new(frame.width() as f32, frame.height() as f32)
+            / self.resolution.as_vec2();
+        let mut lit = warp::warp(frame, width, height, options, |p| {
+            let point = surface.point_at(p / pixels_per_metre);
+            if surface.normal().dot(self.position - point) > 0.0 {
+                return None;
+            }
+            self.project(point).map(|pixel| pixel * frame_scale)
+        });
+        // black light is no light: turn brightness into coverage
+        for pixel in lit.pixels_mut() {
+            let brightness = pixel[0].max(pixel[1]).max(pixel[2]) as u16;
+            if brightness == 0 {
+                pixel[3] = 0;
+                continue;
+            }
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as u16 * 255 / brightness) as u8;
+            }
+            pixel[3] = (pixel[3] as u16 * brightness / 255) as u8;
+        }
+        lit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    use crate::warp::Filter;
+
+    /// Hung over the floor, aimed forwards and down, with the picture shifted
+    /// up off the lens axis.
+    fn projector() -> Projector {
+        Projector::looking_at(
+            Vec3::new(0.3, 2.5, 1.0),
+            Vec3::new(0.0, 0.0, -0.5),
+            Vec3::Y,
+            UVec2::new(320, 180),
+            0.8,
+            Vec2::new(0.1, 0.5),
+        )
+    }
+
+    fn pixels() -> Vec<Vec2> {
+        [(0.0, 0.0), (160.0, 90.0), (320.0, 180.0), (17.5, 150.25), (300.0, 3.0)]
+            .map(|(x, y)| Vec2::new(x, y))
+            .to_vec()
+    }
+
+    #[test]
+    fn light_through_a_pixel_lands_back_on_it() {
+        let projector = projector();
+        for pixel in pixels() {
+            for distance in [0.5, 1.0, 4.0] {
+                let point = projector.position + projector.ray(pixel) * distance;
+                let back = projector.project(point).unwrap();
+                assert!(back.distance(pixel) < 1e-3, "{pixel} came back as {back}");
+            }
+        }
+        let behind = projector.position - projector.ray(Vec2::new(160.0, 90.0));
+        assert!(projector.project(behind).is_none());
+    }
+
+    #[test]
+    fn view_projection_matches_project() {
+        let projector = projector();
+        let view_projection = projector.view_projection();
+        let size = projector.resolution.as_vec2();
+        for pixel in pixels() {
+            for distance in [0.5, 3.0] {
+                let point = projector.position + projector.ray(pixel) * distance;
+                let clip = view_projection * point.extend(1.0);
+                let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+                let from_clip = Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) / 2.0 * size;
+                let projected = projector.project(point).unwrap();
+                assert!(from_clip.distance(projected) < 1e-3, "{from_clip} != {projected}");
+                // w is the depth along the lens axis
+                let depth = (projector.rotation.inverse() * (point - projector.position)).z;
+                assert!((clip.w + depth).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn illuminating_the_frame_paints_the_floor_picture() {
+        let eye = Vec3::new(0.0, 1.6, 3.0);
+        let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(2.0));
+        let picture = PlaneRect::facing(eye, Vec3::ZERO, Vec2::new(1.0, 0.5));
+        let layout = AnamorphicLayout::solve(eye, picture, floor).unwrap();
+        let projector = Projector::looking_at(
+            Vec3::new(0.0, 2.5, 0.0),
+            Vec3::ZERO,
+            Vec3::NEG_Z,
+            UVec2::new(640, 640),
+            0.5,
+            Vec2::ZERO,
+        );
+
+        // smooth and never black, so light and picture can be compared
+        let artwork = RgbaImage::from_fn(100, 50, |x, y| {
+            Rgba([(60 + x * 19 / 10) as u8, (60 + y * 19 / 5) as u8, 200, 255])
+        });
+        let options = WarpOptions {
+            filter: Filter::Bilinear,
+            supersample: 1,
+        };
+        let expected = layout.render(&artwork, 100.0, options);
+        let frame = projector.render(&layout, &artwork, options);
+        let lit = projector.illuminate(&frame, &floor, 100.0, options);
+        assert_eq!(lit.dimensions(), expected.dimensions());
+
+        let (mut compared, mut worst) = (0, 0);
+        for (x, y, want) in expected.enumerate_pixels() {
+            // only well inside the picture, clear of its antialiased edge
+            let inside = (-1..=1).all(|dy: i32| {
+                (-1..=1).all(|dx: i32| {
+                    let (x, y) = (x as i32 + dx, y as i32 + dy);
+                    x >= 0
+                        && y >= 0
+                        && expected
+                            .get_pixel_checked(x as u32, y as u32)
+                            .is_some_and(|p| p[3] == 255)
+                })
+            });
+            if !inside {
+                continue;
+            }
+            let got = lit.get_pixel(x, y);
+            compared += 1;
+            for channel in 0..3 {
+                // illuminate hands back colour and coverage separately
+                let light = got[channel] as i32 * got[3] as i32 / 255;
+                worst = worst.max((light - want[channel] as i32).abs());
+            }
+        }
+        assert!(compared > 1000, "only {compared} pixels compared");
+        assert!(worst <= 6, "off by {worst}");
+    }
+}
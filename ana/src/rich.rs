@@ -0,0 +1,1677 @@
+//! Text mixing styles: runs in different families, sizes, weights and
+//! colours, with underlines, strike-throughs and letter spacing.
+//!
+//! cosmic-text lays a buffer out at a single size, so each span is shaped on
+//! its own with rustybuzz, and the lines are broken and put in reading order
+//! here, where Unicode's line breaking and bidi algorithms say. Lines run
+//! across or, for Chinese, Japanese and Korean, down. Glyphs are scaled with
+//! swash directly, which can embolden and slant them when the bundled fonts
+//! have no face of the weight or style asked for. Glyphs a font draws in
+//! colour, like emoji, keep their own colours.
+
+use std::{error::Error, f32::consts::FRAC_PI_2, fmt, ops::Range, sync::Arc};
+
+use cosmic_text::Font;
+use glam::{Affine2, IVec2, Vec2};
+use rustybuzz::{Language, UnicodeBuffer};
+use image::{GrayImage, Luma, Pixel, Rgba, RgbaImage};
+use swash::scale::{Render, ScaleContext, Scaler, Source};
+use swash::zeno::{Angle, Command, Format, Mask, PathData, Transform, Vector};
+
+use crate::effects::{self, Effect};
+use crate::emoji::{self, ColorGlyph};
+use unicode_bidi::{BidiInfo, Level};
+use unicode_linebreak::BreakOpportunity;
+
+use crate::fonts::{face_runs, Face, FontError, FontRegistry};
+use crate::vector::{flatten, PathCommand};
+
+/// How far synthesised italics lean.
+const OBLIQUE_DEGREES: f32 = 12.0;
+/// How finely decorations are bent along a curved baseline, in pixels.
+const RULE_STEP: f32 = 2.0;
+/// Outline growth, as a fraction of the size, per 100 units of weight a
+/// synthesised bold is heavier than the face.
+const EMBOLDEN_PER_100: f32 = 0.012;
+/// The heaviest CSS weight.
+const MAX_WEIGHT: f32 = 1000.0;
+const WGHT: swash::Tag = swash::tag_from_bytes(b"wght");
+const WDTH: swash::Tag = swash::tag_from_bytes(b"wdth");
+
+/// How a run of text looks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    /// A bundled family, tried before the registry's fallback chain. `None`
+    /// follows the chain alone.
+    pub family: Option<String>,
+    /// Font size in pixels.
+    pub size: f32,
+    /// CSS weight, from 100 (thin) to 900 (black).
+    pub weight: u16,
+    /// Percentage of the normal width, for fonts with a `wdth` axis. Others
+    /// keep their own.
+    pub width: f32,
+    pub italic: bool,
+    pub color: Rgba<u8>,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Extra space after each character, as a fraction of `size`.
+    pub letter_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            family: None,
+            size: 28.0,
+            weight: 700,
+            width: 100.0,
+            italic: false,
+            color: Rgba([255, 255, 255, 255]),
+            underline: false,
+            strikethrough: false,
+            letter_spacing: 0.0,
+        }
+    }
+}
+
+/// Some text in one style. Spans may hold line breaks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+impl Span {
+    pub fn new(text: impl Into<String>, style: TextStyle) -> Self {
+        Self { text: text.into(), style }
+    }
+}
+
+/// Limits for [`RichText::fit`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FitOptions {
+    /// Bounds on the largest span's font size, in pixels.
+    pub min_size: f32,
+    pub max_size: f32,
+    /// Line height as a multiple of each line's largest font size.
+    pub line_height: f32,
+    /// Whether lines may be broken where Unicode allows, or only at the
+    /// text's own line breaks.
+    pub wrap: bool,
+    /// How close, in pixels, the chosen size is to the largest that fits.
+    pub precision: f32,
+    pub flow: Flow,
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 6.0,
+            max_size: 400.0,
+            line_height: 1.25,
+            wrap: true,
+            precision: 0.05,
+            flow: Flow::default(),
+        }
+    }
+}
+
+/// What [`RichText::fit`] chose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FitReport {
+    /// Font size of the largest span, in pixels.
+    pub size: f32,
+    /// How much every span was scaled by.
+    pub scale: f32,
+    /// Height of a line in the largest span's size, in pixels.
+    pub line_height: f32,
+    pub lines: usize,
+    /// Width of the longest line and height of all of them, in pixels.
+    pub extent: Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FitError {
+    Font(FontError),
+    /// There is no text, or it is all size zero.
+    Empty,
+    /// Even at the smallest size allowed the text is too big.
+    Overflows { min_size: f32 },
+    /// The smallest size allowed is larger than the largest.
+    BadRange { min_size: f32, max_size: f32 },
+    /// Sizes must be searched to some positive precision, or the search
+    /// never ends.
+    BadPrecision(f32),
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FitError::Font(e) => e.fmt(f),
+            FitError::Empty => write!(f, "no text to fit"),
+            FitError::Overflows { min_size } => write!(f, "text does not fit even at {min_size} px"),
+            FitError::BadRange { min_size, max_size } => {
+                write!(f, "the smallest size, {min_size} px, is larger than the largest, {max_size} px")
+            }
+            FitError::BadPrecision(precision) => write!(f, "sizes must be fitted to a positive precision, not {precision}"),
+        }
+    }
+}
+
+impl Error for FitError {}
+
+impl From<FontError> for FitError {
+    fn from(e: FontError) -> Self {
+        FitError::Font(e)
+    }
+}
+
+/// Where a glyph went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphPlacement {
+    /// Pen position on the baseline, y down. For an upright glyph in a
+    /// column, the top of its box, on the middle of the column.
+    pub pen: Vec2,
+    /// Of the line, clockwise, in radians, about the pen.
+    pub rotation: f32,
+    pub size: f32,
+    /// Along the line.
+    pub advance: f32,
+    /// Stands up on the page, a quarter turn back from the line, as Chinese,
+    /// Japanese and Korean do in columns.
+    pub upright: bool,
+    pub line: usize,
+    pub variation: Variation,
+    /// The lightest and narrowest variation the glyph's font can draw, and
+    /// the heaviest and widest: its `wght` and `wdth` axes, or for a font
+    /// without them, its own weight up to emboldening's heaviest, and the
+    /// width it is drawn at.
+    pub range: (Variation, Variation),
+}
+
+impl GlyphPlacement {
+    /// Halfway along the advance, on the pen's line.
+    pub fn middle(&self) -> Vec2 {
+        self.pen + Vec2::from_angle(self.rotation).rotate(Vec2::new(self.advance / 2.0, 0.0))
+    }
+
+    /// The variation for strokes seen from `distance` that look as heavy as
+    /// the glyph's do from `reference`, as far as its font can go. Strokes
+    /// look thinner in proportion to their distance, and across a family
+    /// stems thicken roughly in step with CSS weight, so the weight is
+    /// scaled by the ratio of the two.
+    pub fn at_distance(&self, distance: f32, reference: f32) -> Variation {
+        let (lightest, heaviest) = self.range;
+        let weight = (self.variation.weight * distance / reference).clamp(lightest.weight, heaviest.weight);
+        let width = self.variation.width.clamp(lightest.width, heaviest.width);
+        Variation { weight, width }
+    }
+}
+
+/// How heavy and wide a glyph is drawn. Variable fonts are set on their
+/// `wght` and `wdth` axes; fonts without a `wght` axis are emboldened to
+/// reach a heavier weight, but never lightened.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Variation {
+    /// CSS weight.
+    pub weight: f32,
+    /// Percentage of the normal width.
+    pub width: f32,
+}
+
+/// A curve for text to run along, as a polyline. Text sits on its left, seen
+/// travelling along it in an image with y running down, so a baseline
+/// drawn left to right carries upright text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Baseline {
+    points: Vec<Vec2>,
+    /// Distance along the line to each point.
+    distances: Vec<f32>,
+}
+
+impl Baseline {
+    /// A baseline through `points`. `None` without two distinct points.
+    pub fn polyline(points: &[Vec2]) -> Option<Self> {
+        let mut kept: Vec<Vec2> = Vec::with_capacity(points.len());
+        for p in points {
+            if kept.last().is_none_or(|last| last.distance(*p) > 1e-6) {
+                kept.push(*p);
+            }
+        }
+        if kept.len() < 2 {
+            return None;
+        }
+        let mut distances = vec![0.0];
+        for pair in kept.windows(2) {
+            distances.push(distances.last().expect("starts at zero") + pair[0].distance(pair[1]));
+        }
+        Some(Self { points: kept, distances })
+    }
+
+    /// A baseline along a path of curves, flattened to within `tolerance`.
+    /// Only its first contour is used.
+    pub fn from_path(path: &[PathCommand], tolerance: f32) -> Option<Self> {
+        let points = flatten(path, Some, tolerance).into_iter().next()?;
+        Self::polyline(&points)
+    }
+
+    /// A circular arc from angle `from` to `to`, in radians clockwise from
+    /// +x. Text on an arc running clockwise stands on the outside of the
+    /// circle, and on one running anticlockwise hangs inside it.
+    pub fn arc(center: Vec2, radius: f32, from: f32, to: f32, tolerance: f32) -> Option<Self> {
+        // the chord of a step of angle a strays r(1 - cos(a/2)) from the arc
+        let step = 2.0 * (1.0 - tolerance / radius).clamp(-1.0, 1.0).acos();
+        let steps = ((to - from).abs() / step.max(1e-3)).ceil().max(1.0) as usize;
+        let points: Vec<Vec2> = (0..=steps)
+            .map(|i| center + Vec2::from_angle(from + (to - from) * i as f32 / steps as f32) * radius)
+            .collect();
+        Self::polyline(&points)
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.distances.last().expect("at least two points")
+    }
+
+    /// The point `distance` along the line and the direction it runs there.
+    /// Past either end the line carries straight on.
+    pub fn at(&self, distance: f32) -> (Vec2, Vec2) {
+        let segment = self
+            .distances
+            .partition_point(|d| *d <= distance)
+            .clamp(1, self.points.len() - 1);
+        let (a, b) = (self.points[segment - 1], self.points[segment]);
+        let direction = (b - a).normalize();
+        (a + direction * (distance - self.distances[segment - 1]), direction)
+    }
+}
+
+/// How text sits along a [`Baseline`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathAlign {
+    Start,
+    Centre,
+    End,
+    /// Spread out, or squeezed up, to run the whole length.
+    Justify,
+}
+
+/// Which way a paragraph reads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// The way its first letter with a direction of its own reads.
+    #[default]
+    Auto,
+    LeftToRight,
+    RightToLeft,
+}
+
+/// Which way lines run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WritingMode {
+    #[default]
+    Horizontal,
+    /// In columns from top to bottom, the first on the right, as Chinese,
+    /// Japanese and Korean are set. Their characters, and emoji, stand
+    /// upright; other scripts lie on their side, turned clockwise.
+    Vertical,
+}
+
+/// How paragraphs are set, for [`RichText::flow`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flow {
+    pub direction: Direction,
+    pub writing_mode: WritingMode,
+}
+
+/// A glyph placed by [`RichText::layout`].
+#[derive(Clone)]
+struct PlacedGlyph {
+    font: Arc<Font>,
+    /// Which face of a font collection it is.
+    index: u32,
+    glyph_id: u16,
+    size: f32,
+    /// Pen position on the baseline, y down.
+    pen: Vec2,
+    /// From the pen to the glyph's origin, before rotating.
+    offset: Vec2,
+    /// Of the line, clockwise, in radians, about the pen.
+    rotation: f32,
+    /// Drawn a quarter turn back from the line.
+    upright: bool,
+    advance: f32,
+    /// The glyph's line box, from this far above the pen.
+    above: f32,
+    line_height: f32,
+    line: usize,
+    color: Rgba<u8>,
+    /// The face's own weight, which emboldening starts from.
+    face_weight: u16,
+    variation: Variation,
+    /// Outline growth in pixels for a synthesised bold.
+    embolden: f32,
+    oblique: bool,
+    /// Settings of the font's variation axes.
+    axes: Vec<(swash::Tag, f32)>,
+    visible: bool,
+}
+
+impl PlacedGlyph {
+    fn scaler<'a>(&'a self, context: &'a mut ScaleContext) -> Scaler<'a> {
+        context
+            .builder(self.font.as_swash())
+            .size(self.size)
+            .variations(self.axes.iter())
+            .build()
+    }
+
+    /// Draws the glyph with `variation`, on the font's axes where it has
+    /// them. Faces heavier than asked for are left as they are.
+    fn set_variation(&mut self, variation: Variation) {
+        self.variation = variation;
+        self.axes = self
+            .font
+            .as_swash()
+            .variations()
+            .filter_map(|axis| {
+                let value = match axis.tag() {
+                    WGHT => variation.weight,
+                    WDTH => variation.width,
+                    _ => return None,
+                };
+                Some((axis.tag(), value.clamp(axis.min_value(), axis.max_value())))
+            })
+            .collect();
+        self.embolden = if self.axes.iter().any(|(tag, _)| *tag == WGHT) {
+            0.0
+        } else {
+            let heavier = (variation.weight - self.face_weight as f32).max(0.0);
+            heavier / 100.0 * EMBOLDEN_PER_100 * self.size
+        };
+    }
+
+    fn placement(&self) -> GlyphPlacement {
+        let mut lightest = Variation { weight: self.face_weight as f32, width: self.variation.width };
+        let mut heaviest = Variation { weight: MAX_WEIGHT, ..lightest };
+        for axis in self.font.as_swash().variations() {
+            let (lowest, highest) = match axis.tag() {
+                WGHT => (&mut lightest.weight, &mut heaviest.weight),
+                WDTH => (&mut lightest.width, &mut heaviest.width),
+                _ => continue,
+            };
+            (*lowest, *highest) = (axis.min_value(), axis.max_value());
+        }
+        GlyphPlacement {
+            pen: self.pen,
+            rotation: self.rotation,
+            size: self.size,
+            advance: self.advance,
+            upright: self.upright,
+            line: self.line,
+            variation: self.variation,
+            range: (lightest, heaviest),
+        }
+    }
+
+    /// How far the glyph itself is turned, clockwise.
+    fn turn(&self) -> f32 {
+        if self.upright {
+            self.rotation - FRAC_PI_2
+        } else {
+            self.rotation
+        }
+    }
+
+    /// Slant, in the outline's y up space.
+    fn skew(&self) -> Option<Transform> {
+        self.oblique.then(|| Transform::skew(Angle::from_degrees(OBLIQUE_DEGREES), Angle::from_degrees(0.0)))
+    }
+
+    /// Slant and rotation, in the outline's y up space.
+    fn transform(&self) -> Option<Transform> {
+        let skew = self.skew();
+        if self.turn() == 0.0 {
+            return skew;
+        }
+        let rotation = Transform::rotation(Angle::from_radians(-self.turn()));
+        Some(skew.map_or(rotation, |skew| skew.then(&rotation)))
+    }
+
+    /// The emboldened outline, through `transform` and then moved to
+    /// `origin`, y down.
+    fn outline(&self, context: &mut ScaleContext, transform: Option<Transform>, origin: Vec2) -> Option<Vec<PathCommand>> {
+        let mut outline = self.scaler(context).scale_outline(self.glyph_id)?;
+        if self.embolden > 0.0 {
+            outline.embolden(self.embolden, self.embolden);
+        }
+        if let Some(transform) = transform {
+            outline.transform(&transform);
+        }
+        // outlines come y up
+        let point = |p: Vector| origin + Vec2::new(p.x, -p.y);
+        let path = outline.path().commands().map(|command| match command {
+            Command::MoveTo(p) => PathCommand::MoveTo(point(p)),
+            Command::LineTo(p) => PathCommand::LineTo(point(p)),
+            Command::QuadTo(c, p) => PathCommand::QuadTo(point(c), point(p)),
+            Command::CurveTo(c1, c2, p) => PathCommand::CurveTo(point(c1), point(c2), point(p)),
+            Command::Close => PathCommand::Close,
+        }).collect();
+        Some(path)
+    }
+
+    fn origin(&self) -> Vec2 {
+        self.pen + Vec2::from_angle(self.rotation).rotate(self.offset)
+    }
+
+    /// From the glyph's own pixels, y up from its origin, to the image's,
+    /// with the glyph's top left at `position`: the same slant and rotation
+    /// as `transform`.
+    fn to_image(&self, position: Vec2) -> Affine2 {
+        let skew = if self.oblique {
+            Affine2::from_cols(Vec2::X, Vec2::new(OBLIQUE_DEGREES.to_radians().tan(), 1.0), Vec2::ZERO)
+        } else {
+            Affine2::IDENTITY
+        };
+        Affine2::from_translation(self.origin() + position)
+            * Affine2::from_angle(self.turn())
+            * Affine2::from_scale(Vec2::new(1.0, -1.0))
+            * skew
+    }
+
+    /// The glyph in its font's colours, if the font draws it that way.
+    /// Colour glyphs are not emboldened.
+    fn in_colour(&self, context: &mut ScaleContext, position: Vec2) -> Option<ColorGlyph> {
+        let to_image = self.to_image(position);
+        let scale = self.size / self.font.rustybuzz().units_per_em() as f32;
+        emoji::layers(
+            self.font.data(),
+            self.index,
+            self.glyph_id,
+            &self.axes,
+            self.color,
+            to_image * Affine2::from_scale(Vec2::splat(scale)),
+        )
+        .or_else(|| emoji::picture(&mut self.scaler(context), self.glyph_id, to_image))
+    }
+
+    /// Corners of the advance by the line box, clockwise from the top left.
+    fn quad(&self) -> [Vec2; 4] {
+        let (top, bottom) = (-self.above, self.line_height - self.above);
+        [
+            Vec2::new(0.0, top),
+            Vec2::new(self.advance, top),
+            Vec2::new(self.advance, bottom),
+            Vec2::new(0.0, bottom),
+        ]
+        .map(|corner| self.pen + Vec2::from_angle(self.rotation).rotate(corner))
+    }
+}
+
+/// An underline or strike-through: a filled rectangle, or a band along a
+/// curved baseline.
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    points: Vec<Vec2>,
+    line: usize,
+    color: Rgba<u8>,
+}
+
+/// Spans laid out into lines.
+#[derive(Clone)]
+pub struct RichText {
+    glyphs: Vec<PlacedGlyph>,
+    rules: Vec<Rule>,
+    /// Bottom right of the last line, from a top left at the origin, as far
+    /// across as the furthest visible glyph reaches; whitespace at the end
+    /// of a line hangs past it.
+    size: Vec2,
+    /// Each line's baseline, and how far its last visible glyph reaches.
+    baselines: Vec<f32>,
+    line_widths: Vec<f32>,
+    /// Each line's top and bottom, across the lines.
+    extents: Vec<(f32, f32)>,
+}
+
+/// Tells apart glyphs drawn differently at the same size.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct GlyphKey {
+    font: cosmic_text::fontdb::ID,
+    index: u32,
+    glyph_id: u16,
+    axes: Vec<(swash::Tag, u32)>,
+    embolden: u32,
+    oblique: bool,
+}
+
+/// A glyph's outline, or its picture for one without, away from where it
+/// was placed.
+pub(crate) struct GlyphShape {
+    pub(crate) key: GlyphKey,
+    pub(crate) form: GlyphForm,
+    /// From the outline to the text's pixels.
+    pub(crate) to_text: Affine2,
+    pub(crate) color: Rgba<u8>,
+}
+
+/// What a glyph is drawn from, in pixels, y down from its origin.
+pub(crate) enum GlyphForm {
+    Outline(Vec<PathCommand>),
+    /// How much of each pixel a colour or bitmap glyph covers, with the
+    /// image's top left at `corner`.
+    Coverage { image: GrayImage, corner: Vec2 },
+}
+
+/// A shaped glyph, before it is placed on a line.
+struct Shaped {
+    glyph: PlacedGlyph,
+    span: usize,
+    level: Level,
+    breaks_after: bool,
+    ascent: f32,
+    descent: f32,
+    underline: (f32, f32),
+    strikeout: (f32, f32),
+}
+
+impl RichText {
+    /// Lays `spans` out in lines at most `width` pixels long, broken where
+    /// Unicode allows. Each line is `line_height` times its largest font
+    /// size. Paragraphs read the way their first strong letter does.
+    pub fn layout(
+        fonts: &mut FontRegistry,
+        spans: &[Span],
+        line_height: f32,
+        width: f32,
+    ) -> Result<Self, FontError> {
+        Self::flow(fonts, spans, line_height, width, Flow::default())
+    }
+
+    /// Lays `spans` out as `flow` says, in lines at most `length` pixels
+    /// long. Lines of right to left paragraphs end at the right, and
+    /// vertical text starts at the top right.
+    pub fn flow(
+        fonts: &mut FontRegistry,
+        spans: &[Span],
+        line_height: f32,
+        length: f32,
+        flow: Flow,
+    ) -> Result<Self, FontError> {
+        let vertical = flow.writing_mode == WritingMode::Vertical;
+        let mut paragraphs: Vec<Vec<(usize, &str)>> = vec![vec![]];
+        for (index, span) in spans.iter().enumerate() {
+            for (i, piece) in span.text.split('\n').enumerate() {
+                if i > 0 {
+                    paragraphs.push(vec![]);
+                }
+                paragraphs.last_mut().expect("at least one paragraph").push((index, piece));
+            }
+        }
+
+        let mut text = Self {
+            glyphs: vec![],
+            rules: vec![],
+            size: Vec2::ZERO,
+            baselines: vec![],
+            line_widths: vec![],
+            extents: vec![],
+        };
+        let mut top = 0.0;
+        let mut right_to_left = vec![];
+        for pieces in paragraphs {
+            let (paragraph, base) = shape_paragraph(fonts, spans, &pieces, flow.direction, vertical)?;
+            for line in break_lines(&paragraph, length) {
+                top = text.place_line(line, base, top, line_height, spans);
+                right_to_left.push(base.is_rtl());
+            }
+        }
+        text.size.y = top;
+
+        // right to left lines end at the right
+        let mut ends = vec![0.0f32; text.baselines.len()];
+        for glyph in text.glyphs.iter().filter(|g| g.visible) {
+            ends[glyph.line] = ends[glyph.line].max(glyph.pen.x + glyph.advance);
+        }
+        let shift = |line: usize| if right_to_left[line] { text.size.x - ends[line] } else { 0.0 };
+        let shifts: Vec<f32> = (0..ends.len()).map(shift).collect();
+        for glyph in text.glyphs.iter_mut() {
+            glyph.pen.x += shifts[glyph.line];
+        }
+        for rule in text.rules.iter_mut() {
+            for point in rule.points.iter_mut() {
+                point.x += shifts[rule.line];
+            }
+        }
+
+        if vertical {
+            // lines become columns, from the right
+            let column = |p: Vec2| Vec2::new(top - p.y, p.x);
+            for glyph in text.glyphs.iter_mut() {
+                glyph.pen = column(glyph.pen);
+                glyph.rotation += FRAC_PI_2;
+            }
+            for rule in text.rules.iter_mut() {
+                for point in rule.points.iter_mut() {
+                    *point = column(*point);
+                }
+            }
+            text.size = Vec2::new(text.size.y, text.size.x);
+        }
+        Ok(text)
+    }
+
+    /// Places one line's glyphs, in logical order, with its top at `top`,
+    /// returning the top of the next line. `base` is the paragraph's bidi
+    /// level.
+    fn place_line(&mut self, line: &[Shaped], base: Level, top: f32, line_height: f32, spans: &[Span]) -> f32 {
+        // an empty line is as high as one in the first span's size
+        let largest = if line.is_empty() {
+            spans.first().map_or(0.0, |span| span.style.size)
+        } else {
+            line.iter().map(|s| s.glyph.size).fold(0.0, f32::max)
+        };
+        let ascent = line.iter().map(|s| s.ascent).fold(0.0, f32::max);
+        let descent = line.iter().map(|s| s.descent).fold(0.0, f32::max);
+        let height = largest * line_height;
+        let baseline = top + (height - ascent - descent) / 2.0 + ascent;
+        let line = visual_order(line, base);
+
+        let index = self.baselines.len();
+        let mut x = 0.0;
+        let mut visible_width = 0.0;
+        let mut rule_start: Option<(usize, f32)> = None;
+        for (i, shaped) in line.iter().enumerate() {
+            let mut glyph = shaped.glyph.clone();
+            let y = if glyph.upright { top + height / 2.0 } else { baseline };
+            glyph.pen = Vec2::new(x, y);
+            glyph.above = y - top;
+            glyph.line_height = height;
+            glyph.line = index;
+            x += glyph.advance;
+            if glyph.visible {
+                visible_width = x;
+            }
+            self.glyphs.push(glyph);
+
+            // decorations run unbroken along each span, from its first
+            // visible glyph to its last
+            let ends_span = line.get(i + 1).is_none_or(|next| next.span != shaped.span);
+            if rule_start.is_none() {
+                rule_start = Some((i, x - shaped.glyph.advance));
+            }
+            if ends_span {
+                let (start, from) = rule_start.take().expect("started above");
+                let run = &line[start..=i];
+                let (Some(first), Some(last)) = (
+                    run.iter().position(|s| s.glyph.visible),
+                    run.iter().rposition(|s| s.glyph.visible),
+                ) else {
+                    continue;
+                };
+                let from = from + run[..first].iter().map(|s| s.glyph.advance).sum::<f32>();
+                let to = from + run[first..=last].iter().map(|s| s.glyph.advance).sum::<f32>()
+                    - spans[shaped.span].style.letter_spacing * shaped.glyph.size;
+                let style = &spans[shaped.span].style;
+                let y = if run[first].glyph.upright { top + height / 2.0 } else { baseline };
+                let mut rule = |(position, thickness): (f32, f32)| {
+                    let (y0, y1) = (y - position - thickness / 2.0, y - position + thickness / 2.0);
+                    self.rules.push(Rule {
+                        points: vec![
+                            Vec2::new(from, y0),
+                            Vec2::new(to, y0),
+                            Vec2::new(to, y1),
+                            Vec2::new(from, y1),
+                        ],
+                        line: index,
+                        color: style.color,
+                    });
+                };
+                if style.underline {
+                    rule(run[first].underline);
+                }
+                if style.strikethrough {
+                    rule(run[first].strikeout);
+                }
+            }
+        }
+        self.size.x = self.size.x.max(visible_width);
+        self.baselines.push(baseline);
+        self.line_widths.push(visible_width);
+        self.extents.push((top, top + height));
+        top + height
+    }
+
+    /// Lays `spans` out along `baseline`, at its own line breaks only. The
+    /// first line sits on the baseline, and later ones follow it at their
+    /// distance below. Each glyph is turned to the direction of the line
+    /// under its middle.
+    pub fn on_baseline(
+        fonts: &mut FontRegistry,
+        spans: &[Span],
+        baseline: &Baseline,
+        line_height: f32,
+        align: PathAlign,
+    ) -> Result<Self, FontError> {
+        let mut text = Self::layout(fonts, spans, line_height, f32::INFINITY)?;
+        let first = text.baselines.first().copied().unwrap_or(0.0);
+        let length = baseline.length();
+
+        // where each line's glyph middles go along the baseline, as (x in the
+        // straight layout, distance along the baseline)
+        let mut stations: Vec<Vec<(f32, f32)>> = vec![vec![]; text.baselines.len()];
+        for (line, width) in text.line_widths.iter().enumerate() {
+            let glyphs: Vec<&PlacedGlyph> = text.glyphs.iter().filter(|g| g.line == line).collect();
+            let (shift, spread) = match align {
+                PathAlign::Start => (0.0, 0.0),
+                PathAlign::Centre => ((length - width) / 2.0, 0.0),
+                PathAlign::End => (length - width, 0.0),
+                PathAlign::Justify => {
+                    let visible = glyphs.iter().rposition(|g| g.visible).unwrap_or(0);
+                    (0.0, if visible > 0 { (length - width) / visible as f32 } else { 0.0 })
+                }
+            };
+            // right to left lines may have been moved along
+            let start = glyphs.first().map_or(0.0, |g| g.pen.x);
+            stations[line] = glyphs
+                .iter()
+                .enumerate()
+                .map(|(i, g)| {
+                    let middle = g.pen.x + g.advance / 2.0;
+                    (middle, shift + middle - start + spread * i as f32)
+                })
+                .collect();
+        }
+
+        let place = |line: usize, p: Vec2| {
+            let (point, direction) = baseline.at(along(&stations[line], p.x));
+            point + direction.perp() * (p.y - first)
+        };
+        for glyph in text.glyphs.iter_mut() {
+            let middle = along(&stations[glyph.line], glyph.pen.x + glyph.advance / 2.0);
+            let (point, direction) = baseline.at(middle);
+            glyph.rotation = direction.y.atan2(direction.x);
+            glyph.pen = point + direction.perp() * (glyph.pen.y - first) - direction * glyph.advance / 2.0;
+        }
+        for rule in text.rules.iter_mut() {
+            // bend the rectangle along the line
+            let min = rule.points.iter().fold(Vec2::MAX, |a, b| a.min(*b));
+            let max = rule.points.iter().fold(Vec2::MIN, |a, b| a.max(*b));
+            let steps = ((max.x - min.x) / RULE_STEP).ceil().max(1.0) as usize;
+            let xs = (0..=steps).map(|i| min.x + (max.x - min.x) * i as f32 / steps as f32);
+            rule.points = xs
+                .clone()
+                .map(|x| place(rule.line, Vec2::new(x, min.y)))
+                .chain(xs.rev().map(|x| place(rule.line, Vec2::new(x, max.y))))
+                .collect();
+        }
+
+        let corners: Vec<Vec2> = text.quads().into_iter().flatten()
+            .chain(text.rules.iter().flat_map(|rule| rule.points.iter().copied()))
+            .collect();
+        text.size = corners.iter().fold(Vec2::ZERO, |a, b| a.max(*b));
+        Ok(text)
+    }
+
+    /// Width of the longest line and height of all of them, in pixels.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    pub fn lines(&self) -> usize {
+        self.baselines.len()
+    }
+
+    /// Lays `spans` out as large as will fit in `bounds` pixels, keeping
+    /// their sizes in proportion. Errors if the text overflows even at
+    /// `options.min_size`.
+    pub fn fit(
+        fonts: &mut FontRegistry,
+        spans: &[Span],
+        bounds: Vec2,
+        options: FitOptions,
+    ) -> Result<(Self, FitReport), FitError> {
+        let FitOptions { min_size, max_size, precision, .. } = options;
+        if precision <= 0.0 || precision.is_nan() {
+            return Err(FitError::BadPrecision(precision));
+        }
+        if min_size > max_size || min_size.is_nan() || max_size.is_nan() {
+            return Err(FitError::BadRange { min_size, max_size });
+        }
+        let largest = spans.iter().map(|span| span.style.size).fold(0.0, f32::max);
+        if largest <= 0.0 {
+            return Err(FitError::Empty);
+        }
+        let length = match (options.wrap, options.flow.writing_mode) {
+            (false, _) => f32::INFINITY,
+            (true, WritingMode::Horizontal) => bounds.x,
+            (true, WritingMode::Vertical) => bounds.y,
+        };
+        let mut layout = |size: f32| -> Result<Option<Self>, FontError> {
+            let scale = size / largest;
+            let scaled: Vec<Span> = spans
+                .iter()
+                .map(|span| Span::new(span.text.clone(), TextStyle { size: span.style.size * scale, ..span.style.clone() }))
+                .collect();
+            let text = Self::flow(fonts, &scaled, options.line_height, length, options.flow)?;
+            // a hair of slack for rounding in the advances
+            let fits = text.size.cmple(bounds + 1e-3).all();
+            Ok(fits.then_some(text))
+        };
+
+        // the largest size that fits, by bisection
+        let (mut low, mut high) = (options.min_size, options.max_size);
+        let mut best = layout(high)?.map(|text| (high, text));
+        if best.is_none() {
+            let Some(text) = layout(low)? else {
+                return Err(FitError::Overflows { min_size: options.min_size });
+            };
+            best = Some((low, text));
+            while high - low > options.precision {
+                let middle = (low + high) / 2.0;
+                match layout(middle)? {
+                    Some(text) => {
+                        low = middle;
+                        best = Some((middle, text));
+                    }
+                    None => high = middle,
+                }
+            }
+        }
+        let (size, text) = best.expect("found above");
+        let report = FitReport {
+            size,
+            scale: size / largest,
+            line_height: size * options.line_height,
+            lines: text.lines(),
+            extent: text.size,
+        };
+        Ok((text, report))
+    }
+
+    /// Draws the text onto `image` with its top left at `position`, blending
+    /// it over what is already there.
+    pub fn draw(&self, image: &mut RgbaImage, position: Vec2) {
+        let mut context = ScaleContext::new();
+        for glyph in self.glyphs.iter().filter(|g| g.visible) {
+            if let Some(colour) = glyph.in_colour(&mut context, position) {
+                // faded with the style's colour, but not tinted by it
+                for (x, y, pixel) in colour.image.enumerate_pixels() {
+                    let at = colour.corner + IVec2::new(x as i32, y as i32);
+                    blend(image, at.x, at.y, *pixel, glyph.color[3]);
+                }
+                continue;
+            }
+            let origin = glyph.origin() + position;
+            let whole = origin.floor();
+            let fraction = origin - whole;
+            let mut scaler = glyph.scaler(&mut context);
+            let Some(rendered) = Render::new(&[Source::Outline])
+                .format(Format::Alpha)
+                .offset(Vector::new(fraction.x, -fraction.y))
+                .embolden(glyph.embolden)
+                .transform(glyph.transform())
+                .render(&mut scaler, glyph.glyph_id)
+            else {
+                continue;
+            };
+            let placement = rendered.placement;
+            for row in 0..placement.height {
+                for column in 0..placement.width {
+                    let coverage = rendered.data[(row * placement.width + column) as usize];
+                    let x = whole.x as i32 + placement.left + column as i32;
+                    let y = whole.y as i32 - placement.top + row as i32;
+                    blend(image, x, y, glyph.color, coverage);
+                }
+            }
+        }
+        for rule in &self.rules {
+            fill_polygon(image, &rule.points, position, rule.color);
+        }
+    }
+
+    /// Draws the text as [`draw`](Self::draw) does, with `effects` around it
+    /// as [`effects::apply`] paints them. A knockout cuts the letters through
+    /// to what `image` already has.
+    pub fn draw_with_effects(&self, image: &mut RgbaImage, position: Vec2, effects: &[Effect]) {
+        let mut letters = RgbaImage::new(image.width(), image.height());
+        self.draw(&mut letters, position);
+        for (pixel, drawn) in image.pixels_mut().zip(effects::apply(&letters, effects).pixels()) {
+            pixel.blend(drawn);
+        }
+    }
+
+    /// The text drawn on a clear image `width` by `height` pixels, with its
+    /// top left in the image's.
+    pub fn image(&self, width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        self.draw(&mut image, Vec2::ZERO);
+        image
+    }
+
+    /// The outlines of the glyphs and decorations, in pixels with y running
+    /// down from the top left.
+    pub fn outlines(&self) -> Vec<Vec<PathCommand>> {
+        self.outlines_on_lines().into_iter().map(|(_, outline)| outline).collect()
+    }
+
+    /// The outlines of [`outlines`](Self::outlines) line by line.
+    pub fn line_outlines(&self) -> Vec<Vec<Vec<PathCommand>>> {
+        let mut lines = vec![vec![]; self.lines()];
+        for (line, outline) in self.outlines_on_lines() {
+            lines[line].push(outline);
+        }
+        lines
+    }
+
+    fn outlines_on_lines(&self) -> Vec<(usize, Vec<PathCommand>)> {
+        let mut context = ScaleContext::new();
+        let mut outlines = Vec::new();
+        for glyph in self.glyphs.iter().filter(|g| g.visible) {
+            if let Some(outline) = glyph.outline(&mut context, glyph.transform(), glyph.origin()) {
+                outlines.push((glyph.line, outline));
+            }
+        }
+        for rule in &self.rules {
+            // glyph outlines wind counterclockwise on screen, so these do too
+            outlines.push((
+                rule.line,
+                rule.points
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(|(i, p)| if i == 0 { PathCommand::MoveTo(*p) } else { PathCommand::LineTo(*p) })
+                    .chain([PathCommand::Close])
+                    .collect(),
+            ));
+        }
+        outlines
+    }
+
+    /// Each visible glyph's outline at `size` pixels, slanted but not turned,
+    /// with how to put it in place. Glyphs with no outline, as in colour
+    /// and bitmap fonts, come as their pictures' coverage instead, and
+    /// those with neither have nothing to draw and are left out.
+    pub(crate) fn shapes(&self, size: f32) -> Vec<GlyphShape> {
+        let mut context = ScaleContext::new();
+        let mut shapes = Vec::new();
+        for glyph in self.glyphs.iter().filter(|g| g.visible) {
+            let scale = glyph.size / size;
+            let unplaced = PlacedGlyph {
+                size,
+                embolden: glyph.embolden / scale,
+                pen: Vec2::ZERO,
+                offset: Vec2::ZERO,
+                rotation: 0.0,
+                upright: false,
+                ..glyph.clone()
+            };
+            let form = match unplaced.outline(&mut context, unplaced.skew(), Vec2::ZERO) {
+                Some(outline) if !outline.is_empty() => GlyphForm::Outline(outline),
+                _ => {
+                    let Some(picture) = unplaced.in_colour(&mut context, Vec2::ZERO) else {
+                        continue;
+                    };
+                    let image = GrayImage::from_fn(picture.image.width(), picture.image.height(), |x, y| {
+                        Luma([picture.image.get_pixel(x, y)[3]])
+                    });
+                    GlyphForm::Coverage { image, corner: picture.corner.as_vec2() }
+                }
+            };
+            shapes.push(GlyphShape {
+                key: GlyphKey {
+                    font: glyph.font.id(),
+                    index: glyph.index,
+                    glyph_id: glyph.glyph_id,
+                    axes: glyph.axes.iter().map(|(tag, value)| (*tag, value.to_bits())).collect(),
+                    embolden: unplaced.embolden.to_bits(),
+                    oblique: glyph.oblique,
+                },
+                form,
+                to_text: Affine2::from_translation(glyph.origin())
+                    * Affine2::from_angle(glyph.turn())
+                    * Affine2::from_scale(Vec2::splat(scale)),
+                color: glyph.color,
+            });
+        }
+        shapes
+    }
+
+    /// The underlines and strike-throughs, each as points along its top
+    /// edge and then back along its bottom.
+    pub(crate) fn rules(&self) -> impl Iterator<Item = (&[Vec2], Rgba<u8>)> {
+        self.rules.iter().map(|rule| (rule.points.as_slice(), rule.color))
+    }
+
+    /// Each line's box, as `(top left, bottom right)`: across its visible
+    /// glyphs and down its full height. Only for horizontal text.
+    pub fn line_boxes(&self) -> Vec<(Vec2, Vec2)> {
+        let mut boxes: Vec<(Vec2, Vec2)> = self
+            .extents
+            .iter()
+            .map(|(top, bottom)| (Vec2::new(f32::INFINITY, *top), Vec2::new(f32::NEG_INFINITY, *bottom)))
+            .collect();
+        for glyph in self.glyphs.iter().filter(|g| g.visible) {
+            let (min, max) = &mut boxes[glyph.line];
+            min.x = min.x.min(glyph.pen.x);
+            max.x = max.x.max(glyph.pen.x + glyph.advance);
+        }
+        for (min, max) in boxes.iter_mut().filter(|(min, _)| min.x > f32::MAX) {
+            // lines with nothing to see have no width
+            (min.x, max.x) = (0.0, 0.0);
+        }
+        boxes
+    }
+
+    /// The boxes of the visible glyphs, as `(top left, bottom right)`. Each
+    /// box spans the glyph's advance and its line's height, and holds the
+    /// whole of it when it is rotated.
+    pub fn boxes(&self) -> Vec<(Vec2, Vec2)> {
+        self.quads()
+            .iter()
+            .map(|quad| (
+                quad.iter().fold(Vec2::MAX, |a, b| a.min(*b)),
+                quad.iter().fold(Vec2::MIN, |a, b| a.max(*b)),
+            ))
+            .collect()
+    }
+
+    /// The corners of the visible glyphs' boxes, clockwise from the top left
+    /// as the glyph reads.
+    pub fn quads(&self) -> Vec<[Vec2; 4]> {
+        self.glyphs.iter().filter(|g| g.visible).map(PlacedGlyph::quad).collect()
+    }
+
+    /// Where each visible glyph is.
+    pub fn placements(&self) -> Vec<GlyphPlacement> {
+        self.glyphs
+            .iter()
+            .filter(|g| g.visible)
+            .map(PlacedGlyph::placement)
+            .collect()
+    }
+
+    /// Redraws each glyph with the variation `vary` gives for where it is,
+    /// leaving it in place: heavier where it is seen from further away, say,
+    /// with [`GlyphPlacement::at_distance`].
+    pub fn vary(&mut self, vary: impl Fn(&GlyphPlacement) -> Variation) {
+        for glyph in self.glyphs.iter_mut() {
+            let variation = vary(&glyph.placement());
+            glyph.set_variation(variation);
+        }
+    }
+}
+
+/// Shapes a paragraph, given as pieces of spans, in logical order, and
+/// finds its bidi level.
+fn shape_paragraph(
+    fonts: &mut FontRegistry,
+    spans: &[Span],
+    pieces: &[(usize, &str)],
+    direction: Direction,
+    vertical: bool,
+) -> Result<(Vec<Shaped>, Level), FontError> {
+    let text: String = pieces.iter().map(|(_, piece)| *piece).collect();
+    let base = match direction {
+        Direction::Auto => None,
+        Direction::LeftToRight => Some(Level::ltr()),
+        Direction::RightToLeft => Some(Level::rtl()),
+    };
+    let bidi = BidiInfo::new(&text, base);
+    let base = bidi.paragraphs.first().map_or(base.unwrap_or_else(Level::ltr), |p| p.level);
+    let mut breaks = vec![false; text.len() + 1];
+    for (at, opportunity) in unicode_linebreak::linebreaks(&text) {
+        breaks[at] = opportunity == BreakOpportunity::Allowed;
+    }
+    let language = fonts.locale().parse::<Language>().ok();
+
+    let mut shaped = Vec::new();
+    let mut start = 0;
+    for (span, piece) in pieces {
+        let style = &spans[*span].style;
+        let chain = fonts.chain_for(style.family.as_deref(), style.weight, style.italic)?;
+        let piece = start..start + piece.len();
+        start = piece.end;
+        for (levels, level) in split(&text, piece, |i, _| bidi.levels[i]) {
+            for (faces, face) in face_runs(&chain, &text[levels.clone()]) {
+                let faces = levels.start + faces.start..levels.start + faces.end;
+                let upright = |_, c| vertical && stands_upright(c);
+                for (run, upright) in split(&text, faces, upright) {
+                    let run = Run { text: &text, range: run, level, upright, face: &face, span: *span };
+                    shaped.extend(shape(&run, style, language.as_ref(), &breaks));
+                }
+            }
+        }
+    }
+    Ok((shaped, base))
+}
+
+/// Text shaped in one go: one span, face, bidi level and orientation.
+struct Run<'a> {
+    text: &'a str,
+    range: Range<usize>,
+    level: Level,
+    upright: bool,
+    face: &'a Face,
+    span: usize,
+}
+
+/// Splits `range` of `text` where `key` changes.
+fn split<K: PartialEq + Copy>(text: &str, range: Range<usize>, key: impl Fn(usize, char) -> K) -> Vec<(Range<usize>, K)> {
+    let mut runs: Vec<(Range<usize>, K)> = Vec::new();
+    for (i, c) in text[range.clone()].char_indices() {
+        let (i, k) = (range.start + i, key(range.start + i, c));
+        match runs.last_mut() {
+            Some((run, current)) if *current == k => run.end = i + c.len_utf8(),
+            _ => runs.push((i..i + c.len_utf8(), k)),
+        }
+    }
+    runs
+}
+
+/// Whether `c` stands upright in a column: Chinese, Japanese and Korean
+/// characters and emoji do.
+fn stands_upright(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF // Hangul Jamo
+        | 0x2E80..=0x303F // CJK radicals, symbols and punctuation
+        | 0x3040..=0x33FF // kana, bopomofo, compatibility
+        | 0x3400..=0x4DBF // CJK extension A
+        | 0x4E00..=0x9FFF // CJK unified ideographs
+        | 0xA960..=0xA97F | 0xAC00..=0xD7FF // Hangul
+        | 0xF900..=0xFAFF // CJK compatibility ideographs
+        | 0xFE30..=0xFE4F // CJK compatibility forms
+        | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 // fullwidth forms
+        | 0x2600..=0x27BF // symbols and dingbats
+        | 0x1F000..=0x1FAFF // emoji
+        | 0x20000..=0x3FFFF // CJK extensions
+    )
+}
+
+/// Shapes a run into glyphs in logical order.
+fn shape(run: &Run, style: &TextStyle, language: Option<&Language>, breaks: &[bool]) -> Vec<Shaped> {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&run.text[run.range.clone()]);
+    buffer.set_direction(if run.upright {
+        rustybuzz::Direction::TopToBottom
+    } else if run.level.is_rtl() {
+        rustybuzz::Direction::RightToLeft
+    } else {
+        rustybuzz::Direction::LeftToRight
+    });
+    if let Some(language) = language {
+        buffer.set_language(language.clone());
+    }
+    buffer.guess_segment_properties();
+    let face = run.face;
+    let metrics = face.font.rustybuzz();
+    let output = rustybuzz::shape(metrics, &[], buffer);
+    let mut glyphs: Vec<_> = output.glyph_infos().iter().zip(output.glyph_positions()).collect();
+    if run.level.is_rtl() && !run.upright {
+        glyphs.reverse();
+    }
+
+    let scale = style.size / metrics.units_per_em() as f32;
+    let line_metrics = |m: Option<(i16, i16)>, fallback: (f32, f32)| {
+        m.map_or(fallback, |(position, thickness)| (position as f32 * scale, thickness as f32 * scale))
+    };
+    let underline = line_metrics(metrics.underline_metrics().map(|m| (m.position, m.thickness)), (-0.1 * style.size, 0.05 * style.size));
+    let strikeout = line_metrics(metrics.strikeout_metrics().map(|m| (m.position, m.thickness)), (0.3 * style.size, 0.05 * style.size));
+    let (ascent, descent, underline, strikeout) = if run.upright {
+        // centred in the column, with rules beside it
+        (style.size / 2.0, style.size / 2.0, (-0.55 * style.size, underline.1), (0.0, strikeout.1))
+    } else {
+        (metrics.ascender() as f32 * scale, -metrics.descender() as f32 * scale, underline, strikeout)
+    };
+    let spacing = style.letter_spacing * style.size;
+
+    // each cluster's text runs to the start of the next
+    let mut starts: Vec<usize> = glyphs.iter().map(|(info, _)| info.cluster as usize).collect();
+    starts.sort_unstable();
+    starts.dedup();
+    let end_of = |cluster: usize| starts.iter().find(|start| **start > cluster).copied().unwrap_or(run.range.len());
+
+    let mut shaped = Vec::new();
+    for (i, (info, position)) in glyphs.iter().enumerate() {
+        let cluster = info.cluster as usize;
+        let end = end_of(cluster);
+        let text = &run.text[run.range.start + cluster..run.range.start + end];
+        let last_in_cluster = glyphs.get(i + 1).is_none_or(|(next, _)| next.cluster != info.cluster);
+        let offset = Vec2::new(position.x_offset as f32, -position.y_offset as f32) * scale;
+        let (advance, offset) = if run.upright {
+            // offsets are to the page, a quarter turn back from the line
+            (-position.y_advance as f32 * scale, Vec2::new(offset.y, -offset.x))
+        } else {
+            (position.x_advance as f32 * scale, offset)
+        };
+        let mut glyph = PlacedGlyph {
+            font: face.font.clone(),
+            index: face.index,
+            glyph_id: info.glyph_id as u16,
+            size: style.size,
+            pen: Vec2::ZERO,
+            offset,
+            rotation: 0.0,
+            upright: run.upright,
+            advance: advance + spacing,
+            above: 0.0,
+            line_height: 0.0,
+            line: 0,
+            color: style.color,
+            face_weight: face.weight.0,
+            variation: Variation { weight: style.weight as f32, width: style.width },
+            embolden: 0.0,
+            oblique: style.italic && face.style == cosmic_text::Style::Normal,
+            axes: vec![],
+            visible: !text.trim().is_empty(),
+        };
+        glyph.set_variation(glyph.variation);
+        shaped.push(Shaped {
+            glyph,
+            span: run.span,
+            level: run.level,
+            breaks_after: last_in_cluster && breaks[run.range.start + end],
+            ascent,
+            descent,
+            underline,
+            strikeout,
+        });
+    }
+    shaped
+}
+
+/// A line's glyphs from left to right, by the Unicode bidi algorithm's
+/// rules L1 and L2: spaces at the end go back to the paragraph's direction,
+/// then runs are reversed from the highest level down to the lowest odd one.
+fn visual_order(line: &[Shaped], base: Level) -> Vec<&Shaped> {
+    let mut levels: Vec<Level> = line.iter().map(|s| s.level).collect();
+    for (level, shaped) in levels.iter_mut().zip(line).rev() {
+        if shaped.glyph.visible {
+            break;
+        }
+        *level = base;
+    }
+    let mut order: Vec<&Shaped> = line.iter().collect();
+    let highest = levels.iter().map(|l| l.number()).max().unwrap_or(0);
+    let lowest_odd = levels.iter().map(|l| l.number()).filter(|n| n % 2 == 1).min().unwrap_or(highest + 1);
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            let start = i;
+            while i < order.len() && levels[i].number() >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+            levels[start..i].reverse();
+            i += 1;
+        }
+    }
+    order
+}
+
+/// Distance along a baseline for `x` in a straight line, between the
+/// `stations` glyph middles were moved to.
+fn along(stations: &[(f32, f32)], x: f32) -> f32 {
+    let i = stations.partition_point(|(from, _)| *from <= x);
+    match (i.checked_sub(1).map(|i| stations[i]), stations.get(i).copied()) {
+        (Some((x0, d0)), Some((x1, d1))) => d0 + (d1 - d0) * (x - x0) / (x1 - x0),
+        (Some((x0, d0)), None) | (None, Some((x0, d0))) => d0 + (x - x0),
+        (None, None) => x,
+    }
+}
+
+/// Breaks a paragraph into lines no longer than `width`, where Unicode allows
+/// if possible. Spaces at the end of a line don't count towards it.
+fn break_lines(paragraph: &[Shaped], width: f32) -> Vec<&[Shaped]> {
+    if paragraph.is_empty() {
+        return vec![paragraph];
+    }
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < paragraph.len() {
+        let mut x = 0.0;
+        let mut end = start;
+        let mut last_break = None;
+        while end < paragraph.len() {
+            let shaped = &paragraph[end];
+            if shaped.glyph.visible && x + shaped.glyph.advance > width && end > start {
+                break;
+            }
+            x += shaped.glyph.advance;
+            if shaped.breaks_after {
+                last_break = Some(end + 1);
+            }
+            end += 1;
+        }
+        if end < paragraph.len() {
+            // overlong words break wherever they must
+            end = last_break.unwrap_or(end);
+        }
+        lines.push(&paragraph[start..end]);
+        start = end;
+    }
+    lines
+}
+
+fn blend(image: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>, coverage: u8) {
+    if x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height() || coverage == 0 {
+        return;
+    }
+    let alpha = (color[3] as u32 * coverage as u32 / 255) as u8;
+    image.get_pixel_mut(x as u32, y as u32).blend(&Rgba([color[0], color[1], color[2], alpha]));
+}
+
+/// Fills a polygon, moved by `offset`, with antialiased edges.
+fn fill_polygon(image: &mut RgbaImage, points: &[Vec2], offset: Vec2, color: Rgba<u8>) {
+    let Some((first, rest)) = points.split_first() else {
+        return;
+    };
+    let point = |p: &Vec2| Vector::new(p.x + offset.x, p.y + offset.y);
+    let path: Vec<Command> = [Command::MoveTo(point(first))]
+        .into_iter()
+        .chain(rest.iter().map(|p| Command::LineTo(point(p))))
+        .chain([Command::Close])
+        .collect();
+    let (coverage, placement) = Mask::new(&path).render();
+    for row in 0..placement.height {
+        for column in 0..placement.width {
+            blend(
+                image,
+                placement.left + column as i32,
+                placement.top + row as i32,
+                color,
+                coverage[(row * placement.width + column) as usize],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &str) -> Vec<Span> {
+        vec![Span::new(text, TextStyle::default())]
+    }
+
+    #[test]
+    fn fitted_text_fills_the_box() {
+        let mut fonts = FontRegistry::bundled();
+        let message = spans("hello world, how are you doing?");
+        let bounds = Vec2::new(300.0, 120.0);
+        let options = FitOptions { precision: 0.1, ..Default::default() };
+        let (text, report) = RichText::fit(&mut fonts, &message, bounds, options).unwrap();
+        assert!(report.extent.cmple(bounds + 1e-3).all(), "{} overflows {bounds}", report.extent);
+        assert_eq!(text.size(), report.extent);
+        assert_eq!(report.lines, text.lines());
+        // a little larger and it would not fit
+        let larger = FitOptions { min_size: report.size + options.precision * 2.0, ..options };
+        assert!(matches!(RichText::fit(&mut fonts, &message, bounds, larger), Err(FitError::Overflows { .. })));
+    }
+
+    #[test]
+    fn fitting_stops_at_the_largest_size() {
+        let mut fonts = FontRegistry::bundled();
+        let options = FitOptions { max_size: 20.0, ..Default::default() };
+        let (_, report) = RichText::fit(&mut fonts, &spans("hi"), Vec2::splat(1000.0), options).unwrap();
+        assert_eq!(report.size, 20.0);
+        assert_eq!(report.scale, 20.0 / TextStyle::default().size);
+    }
+
+    #[test]
+    fn fit_options_are_checked() {
+        let mut fonts = FontRegistry::bundled();
+        let mut fit = |options| RichText::fit(&mut fonts, &spans("hi"), Vec2::splat(100.0), options).err();
+        for precision in [0.0, -1.0, f32::NAN] {
+            let options = FitOptions { precision, ..Default::default() };
+            assert!(matches!(fit(options), Some(FitError::BadPrecision(_))));
+        }
+        let options = FitOptions { min_size: 50.0, max_size: 10.0, ..Default::default() };
+        assert_eq!(fit(options), Some(FitError::BadRange { min_size: 50.0, max_size: 10.0 }));
+        let unsized_text = [Span::new("hi", TextStyle { size: 0.0, ..Default::default() })];
+        assert_eq!(RichText::fit(&mut fonts, &unsized_text, Vec2::splat(100.0), FitOptions::default()).err(), Some(FitError::Empty));
+    }
+
+    #[test]
+    fn trailing_whitespace_hangs_past_the_width() {
+        let mut fonts = FontRegistry::bundled();
+        let word = RichText::layout(&mut fonts, &spans("word"), 1.25, f32::INFINITY).unwrap();
+        let spaced = RichText::layout(&mut fonts, &spans("word   "), 1.25, f32::INFINITY).unwrap();
+        assert_eq!(spaced.size(), word.size());
+        // and so doesn't stop a line fitting
+        let wrapped = RichText::layout(&mut fonts, &spans("word   word"), 1.25, word.size().x + 0.5).unwrap();
+        assert_eq!(wrapped.lines(), 2);
+        assert_eq!(wrapped.size().x, word.size().x);
+    }
+
+    #[test]
+    fn text_on_a_straight_baseline_sits_as_laid_out() {
+        let mut fonts = FontRegistry::bundled();
+        let straight = RichText::layout(&mut fonts, &spans("along"), 1.25, f32::INFINITY).unwrap();
+        let line = Baseline::polyline(&[Vec2::new(10.0, 50.0), Vec2::new(400.0, 50.0)]).unwrap();
+        let text = RichText::on_baseline(&mut fonts, &spans("along"), &line, 1.25, PathAlign::Start).unwrap();
+        for (laid, along) in straight.placements().iter().zip(text.placements()) {
+            assert!(along.pen.distance(Vec2::new(laid.pen.x + 10.0, 50.0)) < 1e-3, "{} {}", laid.pen, along.pen);
+            assert_eq!(along.rotation, 0.0);
+        }
+    }
+
+    #[test]
+    fn text_is_aligned_along_the_baseline() {
+        let mut fonts = FontRegistry::bundled();
+        let line = Baseline::polyline(&[Vec2::new(0.0, 50.0), Vec2::new(300.0, 50.0)]).unwrap();
+        let mut ends = |align| {
+            let text = RichText::on_baseline(&mut fonts, &spans("hi hi"), &line, 1.25, align).unwrap();
+            let placements = text.placements();
+            let last = placements.last().unwrap();
+            (placements[0].pen.x, last.pen.x + last.advance)
+        };
+        let (start, end) = ends(PathAlign::Start);
+        assert_eq!(start, 0.0);
+        let (centre_start, centre_end) = ends(PathAlign::Centre);
+        assert!((centre_start - (300.0 - end) / 2.0).abs() < 1e-3);
+        assert!((centre_end - (300.0 + end) / 2.0).abs() < 1e-3);
+        let (end_start, end_end) = ends(PathAlign::End);
+        assert!((end_start - (300.0 - end)).abs() < 1e-3 && (end_end - 300.0).abs() < 1e-3);
+        let (justified_start, justified_end) = ends(PathAlign::Justify);
+        assert!(justified_start == 0.0 && (justified_end - 300.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn glyphs_turn_with_an_arc() {
+        let mut fonts = FontRegistry::bundled();
+        let (centre, radius, top) = (Vec2::new(200.0, 200.0), 100.0, -std::f32::consts::FRAC_PI_2);
+        let arc = Baseline::arc(centre, radius, top - 0.8, top + 0.8, 0.01).unwrap();
+        let text = RichText::on_baseline(&mut fonts, &spans("nnnnn"), &arc, 1.25, PathAlign::Centre).unwrap();
+        let placements = text.placements();
+        for glyph in &placements {
+            // each middle on the circle, and the glyph along its tangent
+            let out = glyph.middle() - centre;
+            assert!((out.length() - radius).abs() < 0.05, "{} off the arc", glyph.middle());
+            let tangent = Vec2::from_angle(out.y.atan2(out.x)).perp();
+            assert!(Vec2::from_angle(glyph.rotation).dot(tangent) > 0.9999, "{} turned {}", glyph.middle(), glyph.rotation);
+        }
+        // centred on the top, and turning clockwise along the way
+        let (first, last) = (placements[0].rotation, placements[placements.len() - 1].rotation);
+        assert!((first + last).abs() < 1e-3 && first < 0.0);
+        assert!(placements.windows(2).all(|pair| pair[0].rotation < pair[1].rotation));
+    }
+
+    /// The letters of `candidates` that `text`'s visible glyphs draw, in
+    /// the order they are placed along their lines.
+    fn letters_along(text: &RichText, candidates: &str, along: impl Fn(&PlacedGlyph) -> f32) -> String {
+        let mut glyphs: Vec<&PlacedGlyph> = text.glyphs.iter().filter(|g| g.visible).collect();
+        glyphs.sort_by(|a, b| a.line.cmp(&b.line).then(along(a).total_cmp(&along(b))));
+        glyphs
+            .iter()
+            .map(|g| {
+                let face = ttf_parser::Face::parse(g.font.data(), g.index).unwrap();
+                candidates
+                    .chars()
+                    .find(|c| face.glyph_index(*c).map(|id| id.0) == Some(g.glyph_id))
+                    .unwrap_or('?')
+            })
+            .collect()
+    }
+
+    #[test]
+    fn right_to_left_lines_read_from_the_right() {
+        let mut fonts = FontRegistry::bundled();
+        let text = RichText::layout(&mut fonts, &spans("שלום עולם"), 1.25, f32::INFINITY).unwrap();
+        // each letter left of the one before it
+        let leftwards = letters_along(&text, "שלוםע", |g| -g.pen.x);
+        assert_eq!(leftwards, "שלוםעולם");
+        assert!(text.placements().iter().all(|g| g.rotation == 0.0 && g.advance > 0.0));
+        // and in a left to right paragraph, only the Hebrew turns round
+        let mixed = RichText::layout(&mut fonts, &spans("ab שלום"), 1.25, f32::INFINITY).unwrap();
+        assert_eq!(letters_along(&mixed, "abשלום", |g| g.pen.x), "abםולש");
+    }
+
+    #[test]
+    fn right_to_left_paragraphs_end_at_the_right() {
+        let mut fonts = FontRegistry::bundled();
+        let flow = Flow { direction: Direction::RightToLeft, ..Default::default() };
+        let text = RichText::flow(&mut fonts, &spans("שלום עולם"), 1.25, 100.0, flow).unwrap();
+        assert_eq!(text.lines(), 2);
+        for line in 0..2 {
+            let right = text.glyphs.iter().filter(|g| g.line == line && g.visible)
+                .map(|g| g.pen.x + g.advance).fold(f32::MIN, f32::max);
+            assert!((right - text.size().x).abs() < 1e-3, "line {line} ends at {right}");
+        }
+    }
+
+    #[test]
+    fn vertical_lines_run_down_from_the_right() {
+        let mut fonts = FontRegistry::bundled();
+        let flow = Flow { writing_mode: WritingMode::Vertical, ..Default::default() };
+        let text = RichText::flow(&mut fonts, &spans("ab🦀\ncd"), 1.25, f32::INFINITY, flow).unwrap();
+        assert_eq!(letters_along(&text, "abcd🦀", |g| g.pen.y), "ab🦀cd");
+        let placements = text.placements();
+        let (first, second): (Vec<&GlyphPlacement>, Vec<&GlyphPlacement>) = placements.iter().partition(|g| g.line == 0);
+        // latin lies on its side, turned clockwise, and emoji stand up
+        let quarter = std::f32::consts::FRAC_PI_2;
+        assert!(first[..2].iter().chain(&second).all(|g| !g.upright && (g.rotation - quarter).abs() < 1e-6));
+        assert!(first[2].upright);
+        // each letter below the one before, the ones on their sides on one
+        // baseline down the column
+        assert!(first.windows(2).all(|pair| pair[1].pen.y > pair[0].pen.y));
+        assert!((first[1].pen.x - first[0].pen.x).abs() < 1e-3);
+        // with the second column to the left of the first
+        assert!(second.iter().all(|g| g.pen.x < first[0].pen.x));
+    }
+
+    #[test]
+    fn distant_weights_stay_on_the_fonts_axes() {
+        let mut fonts = FontRegistry::bundled();
+        // Noto Emoji's wght axis runs from 300 to 700
+        let style = TextStyle { family: Some(crate::fonts::NOTO_EMOJI.to_string()), weight: 400, ..Default::default() };
+        let mut text = RichText::layout(&mut fonts, &[Span::new("🦀", style)], 1.25, f32::INFINITY).unwrap();
+        let crab = text.placements()[0];
+        assert_eq!((crab.range.0.weight, crab.range.1.weight), (300.0, 700.0));
+        assert_eq!(crab.at_distance(1.5, 1.0).weight, 600.0);
+        assert_eq!(crab.at_distance(3.0, 1.0).weight, 700.0);
+        assert_eq!(crab.at_distance(0.5, 1.0).weight, 300.0);
+        // and is drawn there
+        text.vary(|g| g.at_distance(3.0, 1.0));
+        assert_eq!(text.glyphs[0].axes, vec![(WGHT, 700.0)]);
+        assert_eq!(text.glyphs[0].embolden, 0.0);
+    }
+
+    #[test]
+    fn fixed_fonts_are_only_emboldened() {
+        let mut fonts = FontRegistry::bundled();
+        // the bundled Fira Sans is its bold alone
+        let mut text = RichText::layout(&mut fonts, &spans("a"), 1.25, f32::INFINITY).unwrap();
+        let a = text.placements()[0];
+        assert_eq!(a.range.0, Variation { weight: 700.0, width: 100.0 });
+        assert_eq!(a.range.1, Variation { weight: MAX_WEIGHT, width: 100.0 });
+        assert_eq!(a.at_distance(0.5, 1.0).weight, 700.0);
+        assert_eq!(a.at_distance(2.0, 1.0).weight, MAX_WEIGHT);
+        text.vary(|g| g.at_distance(2.0, 1.0));
+        assert!(text.glyphs[0].axes.is_empty() && text.glyphs[0].embolden > 0.0);
+    }
+
+    #[test]
+    fn rules_run_under_the_visible_glyphs() {
+        let mut fonts = FontRegistry::bundled();
+        let color = Rgba([255, 200, 60, 255]);
+        let style = TextStyle { color, underline: true, strikethrough: true, ..Default::default() };
+        let message = [Span::new("ab ", TextStyle::default()), Span::new(" cd  ", style), Span::new("e", TextStyle::default())];
+        let text = RichText::layout(&mut fonts, &message, 1.25, f32::INFINITY).unwrap();
+        let rules: Vec<(&[Vec2], Rgba<u8>)> = text.rules().collect();
+        assert_eq!(rules.len(), 2);
+
+        // from the start of c to the end of d, missing the spaces round them
+        let visible: Vec<&PlacedGlyph> = text.glyphs.iter().filter(|g| g.visible).collect();
+        let (c, d) = (visible[2], visible[3]);
+        let (from, to) = (c.pen.x, d.pen.x + d.advance);
+        let mut middles = vec![];
+        for (points, rule_color) in rules {
+            assert_eq!(rule_color, color);
+            let min = points.iter().fold(Vec2::MAX, |a, b| a.min(*b));
+            let max = points.iter().fold(Vec2::MIN, |a, b| a.max(*b));
+            assert!((min.x - from).abs() < 1e-3 && (max.x - to).abs() < 1e-3, "{min} to {max}");
+            middles.push((min.y + max.y) / 2.0);
+        }
+        // the underline below the baseline, the strike-through above it
+        let baseline = text.baselines[0];
+        assert!(middles[0] > baseline && middles[1] < baseline, "{middles:?} about {baseline}");
+    }
+
+    #[test]
+    fn letter_spacing_adds_to_every_character() {
+        let mut fonts = FontRegistry::bundled();
+        let plain = RichText::layout(&mut fonts, &spans("spaced"), 1.25, f32::INFINITY).unwrap();
+        let style = TextStyle { letter_spacing: 0.1, ..Default::default() };
+        let spaced = RichText::layout(&mut fonts, &[Span::new("spaced", style.clone())], 1.25, f32::INFINITY).unwrap();
+        let widening = spaced.size().x - plain.size().x;
+        let expected = 6.0 * 0.1 * style.size;
+        assert!((widening - expected).abs() < 1e-3, "{widening} != {expected}");
+        assert_eq!(spaced.size().y, plain.size().y);
+    }
+
+    #[test]
+    fn lines_are_as_tall_as_their_largest_span() {
+        let mut fonts = FontRegistry::bundled();
+        let sized = |size: f32| TextStyle { size, ..Default::default() };
+        let layout = |fonts: &mut FontRegistry, spans: &[Span]| RichText::layout(fonts, spans, 1.25, f32::INFINITY).unwrap();
+        let small = layout(&mut fonts, &[Span::new("small", sized(20.0))]);
+        let large = layout(&mut fonts, &[Span::new("LARGE", sized(40.0))]);
+        let mixed = layout(&mut fonts, &[Span::new("small", sized(20.0)), Span::new("LARGE", sized(40.0))]);
+        assert_eq!(small.size().y, 25.0);
+        assert_eq!(mixed.size().y, large.size().y);
+        assert_eq!(mixed.size().y, 50.0);
+        // on one baseline, where the large text alone would have it
+        assert_eq!(mixed.baselines, large.baselines);
+        let pens: Vec<f32> = mixed.placements().iter().map(|g| g.pen.y).collect();
+        assert!(pens.iter().all(|y| *y == pens[0]), "{pens:?}");
+    }
+
+    #[test]
+    fn effects_go_round_the_letters() {
+        let mut fonts = FontRegistry::bundled();
+        let text = RichText::layout(&mut fonts, &spans("ok"), 1.25, f32::INFINITY).unwrap();
+        let background = Rgba([0, 0, 255, 255]);
+        let plain = {
+            let mut image = RgbaImage::from_pixel(60, 50, background);
+            text.draw(&mut image, Vec2::new(5.0, 5.0));
+            image
+        };
+        let mut outlined = RgbaImage::from_pixel(60, 50, background);
+        let outline = Effect::Outline { color: Rgba([255, 0, 0, 255]), width: 2.0 };
+        text.draw_with_effects(&mut outlined, Vec2::new(5.0, 5.0), &[outline]);
+        // solid letters are as they were, with red round them
+        for (x, y, pixel) in outlined.enumerate_pixels() {
+            if plain.get_pixel(x, y) == &Rgba([255, 255, 255, 255]) {
+                assert_eq!(pixel, &Rgba([255, 255, 255, 255]));
+            }
+        }
+        assert!(outlined.pixels().any(|p| p == &Rgba([255, 0, 0, 255])));
+        // and knocked out, the background shows through the letters
+        let mut knocked = RgbaImage::from_pixel(60, 50, background);
+        text.draw_with_effects(&mut knocked, Vec2::new(5.0, 5.0), &[Effect::Knockout { color: Rgba([0, 0, 0, 255]) }]);
+        for (x, y, pixel) in knocked.enumerate_pixels() {
+            let letter = plain.get_pixel(x, y) == &Rgba([255, 255, 255, 255]);
+            assert_eq!(pixel == &background, letter, "at {x}, {y}");
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Road-marking text: lettering painted flat on the ground and drawn out
+//! along it, as on roads, instead of an exact anamorphosis for one eye.
+//!
+//! Seen from an eye at a given height, every line takes up the same visual
+//! angle, and so do the gaps between them. A line's angle shrinks with the
+//! square of its distance along the ground but its width only in proportion,
+//! so further lines are stretched more along the road than across it. Lines
+//! are widened in proportion to their distance, letter spacing and all, so
+//! each reads as wide as the nearest. The first line is the nearest, as road
+//! users meet it first.
+
+use std::{error::Error, fmt};
+
+use glam::{Affine2, Vec2};
+use image::RgbaImage;
+
+use crate::rich::RichText;
+use crate::vector::flatten;
+use crate::warp::{warp, WarpOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoadMarkingError {
+    /// The eye is not above the ground, or the text doesn't lie ahead of it.
+    BadRange,
+    /// Nothing to see on any line.
+    Empty,
+}
+
+impl fmt::Display for RoadMarkingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoadMarkingError::BadRange => write!(f, "the text must lie ahead of an eye above the ground"),
+            RoadMarkingError::Empty => write!(f, "there is no text to mark"),
+        }
+    }
+}
+
+impl Error for RoadMarkingError {}
+
+/// Where the marking is read from, and how much road it has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoadMarkingOptions {
+    /// Height of the eye above the ground, in metres.
+    pub eye_height: f32,
+    /// Along the ground from below the eye to the near and far ends of the
+    /// text, in metres.
+    pub near: f32,
+    pub far: f32,
+    /// Visual angle of the gap between two lines, as a fraction of a line's.
+    pub line_gap: f32,
+    /// Widest the text may be across the road, in metres.
+    pub width: f32,
+}
+
+impl Default for RoadMarkingOptions {
+    /// A driver's eye, reading a lane-wide marking 20 to 40 metres ahead.
+    fn default() -> Self {
+        Self {
+            eye_height: 1.2,
+            near: 20.0,
+            far: 40.0,
+            line_gap: 0.5,
+            width: 3.0,
+        }
+    }
+}
+
+/// Where each line of some text goes on the ground. Ground coordinates are
+/// in metres, seen from above with the far end at the top: x across the road
+/// from the left edge of a strip `width` wide, y from the far end towards the
+/// eye.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoadMarking {
+    pub options: RoadMarkingOptions,
+    /// From the text's pixels to the ground, line by line. Lines with no
+    /// width or no height have nothing to paint and are `None`, though they
+    /// keep their place on the ground.
+    pub lines: Vec<Option<Affine2>>,
+    /// Each line's span along the ground, as its top and bottom.
+    bands: Vec<(f32, f32)>,
+}
+
+impl RoadMarking {
+    /// Stretches the lines of horizontal `text` over the ground.
+    pub fn solve(text: &RichText, options: RoadMarkingOptions) -> Result<Self, RoadMarkingError> {
+        let RoadMarkingOptions { eye_height, near, far, line_gap, width } = options;
+        if eye_height <= 0.0 || near <= 0.0 || far <= near || width <= 0.0 {
+            return Err(RoadMarkingError::BadRange);
+        }
+        let boxes = text.line_boxes();
+        let visible = |(min, max): &(Vec2, Vec2)| min.x < max.x && min.y < max.y;
+        if !boxes.iter().any(visible) {
+            return Err(RoadMarkingError::Empty);
+        }
+
+        // angles below the horizon, shared out between lines and gaps
+        let below = |distance: f32| (eye_height / distance).atan();
+        let lines = boxes.len() as f32;
+        let angle = (below(near) - below(far)) / (lines + (lines - 1.0) * line_gap.max(0.0));
+        let distance = |below: f32| eye_height / below.tan();
+        let spans: Vec<(f32, f32)> = (0..boxes.len())
+            .map(|i| {
+                let nearest = below(near) - i as f32 * angle * (1.0 + line_gap.max(0.0));
+                (distance(nearest), distance(nearest - angle))
+            })
+            .collect();
+
+        // metres across per pixel grow with the distance, by as much as lets
+        // the widest line fit
+        let across = boxes
+            .iter()
+            .zip(&spans)
+            .filter(|(line, _)| visible(line))
+            .map(|((min, max), (from, to))| width / ((max.x - min.x) * (from + to) / 2.0))
+            .fold(f32::INFINITY, f32::min);
+
+        let mut marking = Self { options, lines: vec![], bands: vec![] };
+        for (line, (from, to)) in boxes.iter().zip(&spans) {
+            let (top, bottom) = (far - to, far - from);
+            marking.bands.push((top, bottom));
+            if !visible(line) {
+                marking.lines.push(None);
+                continue;
+            }
+            let (min, max) = line;
+            let scale = Vec2::new(across * (from + to) / 2.0, (bottom - top) / (max.y - min.y));
+            let centre = (min.x + max.x) / 2.0;
+            let offset = Vec2::new(width / 2.0 - scale.x * centre, top - scale.y * min.y);
+            marking.lines.push(Some(Affine2::from_scale_angle_translation(scale, 0.0, offset)));
+        }
+        Ok(marking)
+    }
+
+    /// Width and length of the ground the marking covers, in metres.
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.options.width, self.options.far - self.options.near)
+    }
+
+    /// The text's outlines on the ground, flattened to within `tolerance`
+    /// metres.
+    pub fn contours(&self, text: &RichText, tolerance: f32) -> Vec<Vec<Vec2>> {
+        text.line_outlines()
+            .iter()
+            .zip(&self.lines)
+            .filter_map(|(outlines, to_ground)| Some((outlines, (*to_ground)?)))
+            .flat_map(|(outlines, to_ground)| {
+                outlines.iter().flat_map(move |outline| flatten(outline, |p| Some(to_ground.transform_point2(p)), tolerance))
+            })
+            .collect()
+    }
+
+    /// Paints `artwork`, the text drawn with its top left at the origin, onto
+    /// the ground at `pixels_per_metre`. The ground between lines is left
+    /// transparent.
+    pub fn render(&self, artwork: &RgbaImage, pixels_per_metre: f32, options: WarpOptions) -> RgbaImage {
+        let size = (self.size() * pixels_per_metre).round().max(Vec2::ONE);
+        let to_text: Vec<Option<Affine2>> = self.lines.iter().map(|line| line.map(|l| l.inverse())).collect();
+        warp(artwork, size.x as u32, size.y as u32, options, |p| {
+            let ground = p / pixels_per_metre;
+            let line = self.bands.iter().position(|(top, bottom)| (*top..*bottom).contains(&ground.y))?;
+            Some(to_text[line]?.transform_point2(ground))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fonts::FontRegistry;
+    use crate::rich::{Span, TextStyle};
+
+    fn text(spans: &[Span]) -> RichText {
+        RichText::layout(&mut FontRegistry::bundled(), spans, 1.25, f32::INFINITY).unwrap()
+    }
+
+    /// The angle below the horizon of the band from `top` to `bottom`.
+    fn visual_angle(options: &RoadMarkingOptions, (top, bottom): (f32, f32)) -> f32 {
+        let below = |y: f32| (options.eye_height / (options.far - y)).atan();
+        below(bottom) - below(top)
+    }
+
+    #[test]
+    fn lines_and_gaps_take_equal_angles() {
+        let options = RoadMarkingOptions::default();
+        let text = text(&[Span::new("SLOW\nAHEAD", TextStyle::default())]);
+        let marking = RoadMarking::solve(&text, options).unwrap();
+        let (near, far) = (marking.bands[0], marking.bands[1]);
+        let (near_angle, far_angle) = (visual_angle(&options, near), visual_angle(&options, far));
+        assert!((near_angle - far_angle).abs() < 1e-6, "{near_angle} and {far_angle}");
+        let gap = visual_angle(&options, (far.0, near.1)) - near_angle - far_angle;
+        assert!((gap - near_angle * options.line_gap).abs() < 1e-6);
+        // the first line nearest, reaching the near end, and the further one
+        // drawn out longer
+        assert!((near.1 - options.far + options.near).abs() < 1e-4 && far.1 < near.0);
+        assert!(far.1 - far.0 > near.1 - near.0);
+        // each line's box fills its band
+        for ((min, max), (line, band)) in text.line_boxes().iter().zip(marking.lines.iter().zip(&marking.bands)) {
+            let line = line.unwrap();
+            assert!((line.transform_point2(*min).y - band.0).abs() < 1e-4);
+            assert!((line.transform_point2(*max).y - band.1).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn lines_without_height_are_skipped() {
+        let flat = TextStyle { size: 0.0, ..Default::default() };
+        let text = text(&[Span::new("SLOW\n", TextStyle::default()), Span::new("AHEAD", flat)]);
+        let marking = RoadMarking::solve(&text, RoadMarkingOptions::default()).unwrap();
+        assert!(marking.lines[0].is_some() && marking.lines[1].is_none());
+        assert_eq!(marking.bands.len(), 2);
+        let ground = marking.render(&text.image(200, 80), 10.0, WarpOptions::default());
+        assert!(ground.pixels().any(|p| p[3] > 0));
+        assert!(marking.contours(&text, 0.01).iter().flatten().all(|p| p.is_finite()));
+    }
+
+    #[test]
+    fn unmarkable_text_is_refused() {
+        let options = RoadMarkingOptions::default();
+        let flat = text(&[Span::new("AHEAD", TextStyle { size: 0.0, ..Default::default() })]);
+        assert_eq!(RoadMarking::solve(&flat, options), Err(RoadMarkingError::Empty));
+        let some = text(&[Span::new("AHEAD", TextStyle::default())]);
+        let behind = RoadMarkingOptions { far: 10.0, ..options };
+        assert_eq!(RoadMarking::solve(&some, behind), Err(RoadMarkingError::BadRange));
+    }
+}
@@ -0,0 +1,340 @@
+//! Signed distance field glyph atlases, for text that stays sharp however it
+//! is magnified or squeezed, as on a floor seen at a grazing angle.
+//!
+//! Each glyph is drawn once, at a fixed size, as the distance from every
+//! texel to its flattened outline: 0.5 on the outline, rising inside and
+//! falling outside to 0 and 1 at `spread` pixels either side. A shader
+//! thresholds the interpolated distance at 0.5, so edges come out crisp
+//! instead of as a blurred bitmap. Text is drawn as one quad per glyph, and
+//! decorations as quads over a block of the atlas that is inside everywhere.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use glam::{UVec2, Vec2};
+use image::{GrayImage, Luma, Rgba};
+use imageproc::distance_transform::euclidean_squared_distance_transform;
+
+use crate::rich::{GlyphForm, RichText};
+use crate::vector::flatten;
+
+/// Texels fully inside, for decorations.
+const SOLID: u32 = 4;
+
+/// How an atlas is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdfOptions {
+    /// Font size the glyphs are drawn at, in atlas pixels.
+    pub size: f32,
+    /// How far either side of an outline distances reach, in atlas pixels.
+    /// Edges drawn much smaller than the atlas are smoothed over it.
+    pub spread: f32,
+    /// Width of the atlas image; it grows downwards as needed, and wider
+    /// for a glyph that would not fit across.
+    pub width: u32,
+}
+
+impl Default for SdfOptions {
+    fn default() -> Self {
+        Self {
+            size: 48.0,
+            spread: 6.0,
+            width: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SdfError {
+    /// Glyphs must be drawn at a positive size.
+    BadSize(f32),
+    /// Distances must reach a positive number of pixels.
+    BadSpread(f32),
+}
+
+impl fmt::Display for SdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdfError::BadSize(size) => write!(f, "glyphs must be drawn at a positive size, not {size}"),
+            SdfError::BadSpread(spread) => write!(f, "distances must spread a positive number of pixels, not {spread}"),
+        }
+    }
+}
+
+impl Error for SdfError {}
+
+/// Part of a text, and where in the atlas to draw it from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdfQuad {
+    /// In the text's pixels, clockwise from the top left.
+    pub corners: [Vec2; 4],
+    /// Atlas texture coordinates for each corner, from 0 to 1.
+    pub uvs: [Vec2; 4],
+    pub color: Rgba<u8>,
+}
+
+/// The distance fields of all the glyphs in a text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfAtlas {
+    pub options: SdfOptions,
+    pub image: GrayImage,
+    /// The parts of the text, ready to draw from `image`.
+    pub quads: Vec<SdfQuad>,
+}
+
+impl SdfAtlas {
+    /// Draws each glyph of `text` into an atlas once, however often it is
+    /// used.
+    pub fn new(text: &RichText, options: SdfOptions) -> Result<Self, SdfError> {
+        let SdfOptions { size, spread, width } = options;
+        if size <= 0.0 || size.is_nan() {
+            return Err(SdfError::BadSize(size));
+        }
+        if spread <= 0.0 || spread.is_nan() {
+            return Err(SdfError::BadSpread(spread));
+        }
+        let shapes = text.shapes(size);
+
+        // shelves of glyphs, after a solid block for decorations
+        let mut packer = Packer { width, cursor: UVec2::new(SOLID, 0), shelf: SOLID };
+        let mut fields = vec![];
+        let mut placed = HashMap::new();
+        let mut quads = vec![];
+        for shape in &shapes {
+            if !placed.contains_key(&shape.key) {
+                let Some((origin, extent, distances)) = field(&shape.form, spread) else {
+                    // a glyph with nothing in it
+                    continue;
+                };
+                let corner = packer.place(extent);
+                fields.push((corner, extent, distances));
+                placed.insert(shape.key.clone(), (corner, extent, origin));
+            }
+            let (corner, extent, origin) = placed[&shape.key];
+            let local = [origin, origin + Vec2::new(extent.x as f32, 0.0), origin + extent.as_vec2(), origin + Vec2::new(0.0, extent.y as f32)];
+            quads.push(SdfQuad {
+                corners: local.map(|p| shape.to_text.transform_point2(p)),
+                uvs: local.map(|p| p - origin + corner.as_vec2()),
+                color: shape.color,
+            });
+        }
+
+        let (width, height) = (packer.width, (packer.cursor.y + packer.shelf).max(SOLID));
+        let mut image = GrayImage::new(width, height);
+        for y in 0..SOLID {
+            for x in 0..SOLID {
+                image.put_pixel(x, y, Luma([255]));
+            }
+        }
+        for (corner, extent, distances) in fields {
+            for (i, distance) in distances.into_iter().enumerate() {
+                let (x, y) = (i as u32 % extent.x, i as u32 / extent.x);
+                let value = (0.5 + distance / spread / 2.0).clamp(0.0, 1.0);
+                image.put_pixel(corner.x + x, corner.y + y, Luma([(value * 255.0).round() as u8]));
+            }
+        }
+
+        // decorations draw from the middle of the solid block
+        let solid = Vec2::splat(SOLID as f32 / 2.0);
+        for (points, color) in text.rules() {
+            // the top edge runs forwards and the bottom back
+            let n = points.len();
+            for i in 0..(n / 2).saturating_sub(1) {
+                quads.push(SdfQuad {
+                    corners: [points[i], points[i + 1], points[n - 2 - i], points[n - 1 - i]],
+                    uvs: [solid; 4],
+                    color,
+                });
+            }
+        }
+
+        let scale = Vec2::new(width as f32, height as f32);
+        for quad in quads.iter_mut() {
+            quad.uvs = quad.uvs.map(|uv| uv / scale);
+        }
+        Ok(Self { options, image, quads })
+    }
+}
+
+/// Rows of boxes packed left to right.
+struct Packer {
+    /// Widened for a box that would not fit across.
+    width: u32,
+    cursor: UVec2,
+    /// Height of the current row.
+    shelf: u32,
+}
+
+impl Packer {
+    /// The top left of a free box of `extent`, a pixel apart from the rest.
+    fn place(&mut self, extent: UVec2) -> UVec2 {
+        if self.cursor.x > 0 && self.cursor.x + extent.x > self.width {
+            self.cursor = UVec2::new(0, self.cursor.y + self.shelf + 1);
+            self.shelf = 0;
+        }
+        self.width = self.width.max(self.cursor.x + extent.x);
+        let corner = self.cursor;
+        self.cursor.x += extent.x + 1;
+        self.shelf = self.shelf.max(extent.y);
+        corner
+    }
+}
+
+/// A glyph's signed distances, a texel apart out to `spread` beyond it: the
+/// corner of the first texel, how many there are across and down, and the
+/// distances row by row. `None` for a glyph with nothing in it.
+fn field(form: &GlyphForm, spread: f32) -> Option<(Vec2, UVec2, Vec<f32>)> {
+    match form {
+        GlyphForm::Outline(outline) => {
+            let contours = flatten(outline, Some, 0.05);
+            let (min, max) = bounds(&contours)?;
+            let origin = (min - spread).floor();
+            let extent = ((max + spread).ceil() - origin).as_uvec2();
+            let distances = (0..extent.y)
+                .flat_map(|y| (0..extent.x).map(move |x| Vec2::new(x as f32, y as f32)))
+                .map(|texel| signed_distance(&contours, origin + texel + 0.5))
+                .collect();
+            Some((origin, extent, distances))
+        }
+        GlyphForm::Coverage { image, corner } => {
+            // measured between texel centres, which sit half a texel either
+            // side of the edge
+            let pad = spread.ceil() as u32;
+            let (width, height) = (image.width() + 2 * pad, image.height() + 2 * pad);
+            let inside = GrayImage::from_fn(width, height, |x, y| {
+                let covered = x >= pad && y >= pad && x - pad < image.width() && y - pad < image.height()
+                    && image.get_pixel(x - pad, y - pad)[0] >= 128;
+                Luma([if covered { 255 } else { 0 }])
+            });
+            if inside.pixels().all(|texel| texel[0] == 0) {
+                return None;
+            }
+            let mut outside = inside.clone();
+            outside.pixels_mut().for_each(|texel| texel[0] = 255 - texel[0]);
+            let (to_inside, to_outside) = (euclidean_squared_distance_transform(&inside), euclidean_squared_distance_transform(&outside));
+            let distances = inside
+                .enumerate_pixels()
+                .map(|(x, y, texel)| {
+                    if texel[0] > 0 {
+                        to_outside.get_pixel(x, y)[0].sqrt() as f32 - 0.5
+                    } else {
+                        0.5 - to_inside.get_pixel(x, y)[0].sqrt() as f32
+                    }
+                })
+                .collect();
+            Some((*corner - pad as f32, UVec2::new(width, height), distances))
+        }
+    }
+}
+
+fn bounds(contours: &[Vec<Vec2>]) -> Option<(Vec2, Vec2)> {
+    let mut points = contours.iter().flatten().peekable();
+    points.peek()?;
+    Some(points.fold((Vec2::MAX, Vec2::MIN), |(min, max), p| (min.min(*p), max.max(*p))))
+}
+
+/// Distance from `p` to the nearest edge of `contours`, positive inside them
+/// by the nonzero rule.
+fn signed_distance(contours: &[Vec<Vec2>], p: Vec2) -> f32 {
+    let mut nearest = f32::INFINITY;
+    let mut winding = 0;
+    for contour in contours {
+        for (i, a) in contour.iter().enumerate() {
+            let b = contour[(i + 1) % contour.len()];
+            let edge = b - *a;
+            let t = ((p - *a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+            nearest = nearest.min(p.distance(*a + edge * t));
+            // crossings of a ray to the right
+            if (a.y <= p.y) != (b.y <= p.y) {
+                let x = a.x + (p.y - a.y) / (b.y - a.y) * edge.x;
+                if x > p.x {
+                    winding += if b.y > a.y { 1 } else { -1 };
+                }
+            }
+        }
+    }
+    if winding != 0 {
+        nearest
+    } else {
+        -nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fonts::FontRegistry;
+    use crate::rich::{Span, TextStyle};
+    use crate::vector::PathCommand;
+
+    /// A square from `min` to `max`, anticlockwise on screen, as fonts wind
+    /// their outer contours with y down.
+    fn square(min: Vec2, max: Vec2) -> Vec<PathCommand> {
+        vec![
+            PathCommand::MoveTo(min),
+            PathCommand::LineTo(Vec2::new(min.x, max.y)),
+            PathCommand::LineTo(max),
+            PathCommand::LineTo(Vec2::new(max.x, min.y)),
+            PathCommand::Close,
+        ]
+    }
+
+    #[test]
+    fn distances_are_positive_inside() {
+        let contours = flatten(&square(Vec2::ZERO, Vec2::splat(10.0)), Some, 0.05);
+        assert_eq!(signed_distance(&contours, Vec2::new(5.0, 5.0)), 5.0);
+        assert_eq!(signed_distance(&contours, Vec2::new(2.0, 7.0)), 2.0);
+        assert_eq!(signed_distance(&contours, Vec2::new(13.0, 5.0)), -3.0);
+        assert_eq!(signed_distance(&contours, Vec2::new(-3.0, -4.0)), -5.0);
+        // either way round
+        let mut reversed = contours.clone();
+        reversed[0].reverse();
+        assert_eq!(signed_distance(&reversed, Vec2::new(5.0, 5.0)), 5.0);
+    }
+
+    #[test]
+    fn pictures_give_the_same_field_as_outlines() {
+        let (spread, corner) = (3.0, Vec2::new(2.0, 1.0));
+        let (origin, extent, traced) = field(&GlyphForm::Outline(square(corner, corner + 8.0)), spread).unwrap();
+        let image = GrayImage::from_pixel(8, 8, Luma([255]));
+        let (picture_origin, picture_extent, measured) = field(&GlyphForm::Coverage { image, corner }, spread).unwrap();
+        assert_eq!((picture_origin, picture_extent), (origin, extent));
+        // the same along the rows and columns through the middle, where the
+        // nearest edge is straight across
+        for i in 0..extent.x as usize {
+            for row in [5, 6] {
+                let at = row * extent.x as usize + i;
+                assert!((traced[at] - measured[at]).abs() < 1e-4, "{} and {} at {i}", traced[at], measured[at]);
+            }
+        }
+        let blank = GrayImage::new(8, 8);
+        assert!(field(&GlyphForm::Coverage { image: blank, corner }, spread).is_none());
+    }
+
+    #[test]
+    fn the_atlas_widens_for_a_large_glyph() {
+        let mut packer = Packer { width: 20, cursor: UVec2::new(SOLID, 0), shelf: SOLID };
+        assert_eq!(packer.place(UVec2::new(10, 5)), UVec2::new(SOLID, 0));
+        // on a row of its own, below the first, a pixel apart
+        assert_eq!(packer.place(UVec2::new(30, 8)), UVec2::new(0, 6));
+        assert_eq!(packer.width, 30);
+        assert_eq!(packer.place(UVec2::new(10, 5)), UVec2::new(0, 15));
+
+        let mut fonts = FontRegistry::bundled();
+        let text = RichText::layout(&mut fonts, &[Span::new("W🦀", TextStyle::default())], 1.25, f32::INFINITY).unwrap();
+        let atlas = SdfAtlas::new(&text, SdfOptions { width: 16, ..Default::default() }).unwrap();
+        assert!(atlas.image.width() > 16);
+        assert_eq!(atlas.quads.len(), 2, "the emoji too");
+        assert!(atlas.quads.iter().flat_map(|quad| quad.uvs).all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()));
+    }
+    #[test]
+    fn options_are_checked() {
+        let mut fonts = FontRegistry::bundled();
+        let text = RichText::layout(&mut fonts, &[Span::new("a", TextStyle::default())], 1.25, f32::INFINITY).unwrap();
+        for bad in [0.0, -1.0, f32::NAN] {
+            let options = SdfOptions { size: bad, ..Default::default() };
+            assert!(matches!(SdfAtlas::new(&text, options), Err(SdfError::BadSize(_))));
+            let options = SdfOptions { spread: bad, ..Default::default() };
+            assert!(matches!(SdfAtlas::new(&text, options), Err(SdfError::BadSpread(_))));
+        }
+    }
+}
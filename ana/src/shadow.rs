@@ -0,0 +1,198 @@
+//! Shadow anamorphosis: a cut-out held between a point light and the target,
+//! whose shadow falls exactly where the artwork would have been painted, so
+//! it reads from the layout's eye.
+//!
+//! Seen from the light, the cut-out and its shadow line up, so the outline of
+//! the cut-out is the artwork's outline on the target pushed back along the
+//! light's rays onto the cut-out's plane. Between two planes that is another
+//! homography.
+
+use std::{error::Error, fmt};
+
+use glam::{Vec2, Vec3};
+
+use crate::mesh::TriangleMesh;
+use crate::projection::{Homography, PlaneRect};
+use crate::vector::{self, flatten, shapes, PathCommand};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowError {
+    /// The cut-out's plane doesn't lie between the light and the target.
+    PlaneNotBetween,
+    /// The light sees the target or the cut-out's plane edge-on.
+    Degenerate,
+}
+
+impl fmt::Display for ShadowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShadowError::PlaneNotBetween => write!(f, "the cut-out's plane is not between the light and the target"),
+            ShadowError::Degenerate => write!(f, "the light sees the target or the cut-out edge-on"),
+        }
+    }
+}
+
+impl Error for ShadowError {}
+
+/// The cut-out: filled outlines on a plane, in that plane's coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder {
+    pub plane: PlaneRect,
+    pub contours: Vec<Vec<Vec2>>,
+}
+
+impl Occluder {
+    /// Cuts an occluder on `plane` whose shadow from a point `light` fills
+    /// `outlines` on `target`. `to_target` takes outline coordinates to
+    /// target coordinates, e.g. through a layout's `picture_to_target`.
+    /// Contours stay within `tolerance` metres of the true outline.
+    pub fn cut(
+        light: Vec3,
+        plane: PlaneRect,
+        target: &PlaneRect,
+        outlines: &[Vec<PathCommand>],
+        to_target: impl Fn(Vec2) -> Option<Vec2>,
+        tolerance: f32,
+    ) -> Result<Self, ShadowError> {
+        let light_side = |rect: &PlaneRect| rect.normal().dot(light - rect.origin);
+        if light_side(target).abs() < 1e-6 || light_side(&plane).abs() < 1e-6 {
+            return Err(ShadowError::Degenerate);
+        }
+        // where the rays to the target's corners cross the plane, before they
+        // reach the target
+        let mut crossings = [Vec2::ZERO; 4];
+        for (crossing, corner) in crossings.iter_mut().zip(target.corners()) {
+            let (_, coords) = plane
+                .intersect_ray(light, corner - light)
+                .filter(|(t, _)| *t < 1.0)
+                .ok_or(ShadowError::PlaneNotBetween)?;
+            *crossing = coords;
+        }
+        let target_to_plane = Homography::from_correspondences(target.corner_coords(), crossings)
+            .ok_or(ShadowError::Degenerate)?;
+
+        let contours = outlines
+            .iter()
+            .flat_map(|outline| {
+                flatten(outline, |p| target_to_plane.apply(to_target(p)?), tolerance)
+            })
+            .collect();
+        Ok(Self { plane, contours })
+    }
+
+    /// The smallest and largest plane coordinates of the cut-out.
+    pub fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        let mut points = self.contours.iter().flatten();
+        let first = *points.next()?;
+        Some(points.fold((first, first), |(min, max), p| (min.min(*p), max.max(*p))))
+    }
+
+    /// The cut-out as a slab `thickness` metres thick, with its front face on
+    /// the plane and its back on the side the plane's normal points away
+    /// from. UVs run over the plane rectangle.
+    pub fn mesh(&self, thickness: f32) -> TriangleMesh {
+        let back = -self.plane.normal() * thickness;
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut add = |corners: [(Vec2, Vec3); 3]| {
+            for (coords, offset) in corners {
+                positions.push(self.plane.point_at(coords) + offset);
+                uvs.push(coords / self.plane.size);
+            }
+        };
+        for shape in shapes(&self.contours) {
+            // positive triangles face along the normal, so the back turns them
+            for [a, b, c] in shape.triangulate() {
+                add([(a, Vec3::ZERO), (b, Vec3::ZERO), (c, Vec3::ZERO)]);
+                add([(a, back), (c, back), (b, back)]);
+            }
+            // the filled side is on the left of every edge
+            for contour in std::iter::once(&shape.outer).chain(&shape.holes) {
+                for (a, b) in contour.iter().zip(contour.iter().cycle().skip(1)) {
+                    add([(*a, Vec3::ZERO), (*a, back), (*b, back)]);
+                    add([(*a, Vec3::ZERO), (*b, back), (*b, Vec3::ZERO)]);
+                }
+            }
+        }
+        let indices = (0..positions.len() as u32).collect();
+        TriangleMesh::new(positions, uvs, indices)
+    }
+
+    /// The outline at full scale for cutting, cropped to [`Occluder::bounds`]:
+    /// the SVG's top left corner is the smallest plane coordinate.
+    pub fn svg(&self) -> String {
+        let (min, max) = self.bounds().unwrap_or_default();
+        let cropped: Vec<Vec<Vec2>> = self
+            .contours
+            .iter()
+            .map(|contour| contour.iter().map(|p| *p - min).collect())
+            .collect();
+        vector::svg(&cropped, max - min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIGHT: Vec3 = Vec3::new(0.2, 3.0, 0.5);
+
+    fn target() -> PlaneRect {
+        PlaneRect::ground(Vec3::ZERO, Vec2::splat(4.0))
+    }
+
+    /// A triangle with a square hole, in target coordinates.
+    fn outlines() -> Vec<Vec<PathCommand>> {
+        let contour = |points: &[Vec2]| {
+            let mut path = vec![PathCommand::MoveTo(points[0])];
+            path.extend(points[1..].iter().map(|p| PathCommand::LineTo(*p)));
+            path.push(PathCommand::Close);
+            path
+        };
+        vec![
+            contour(&[Vec2::new(0.5, 3.5), Vec2::new(3.5, 3.5), Vec2::new(2.0, 0.5)]),
+            contour(&[
+                Vec2::new(1.7, 2.4),
+                Vec2::new(2.3, 2.4),
+                Vec2::new(2.3, 3.0),
+                Vec2::new(1.7, 3.0),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn outline_points_cast_onto_the_artwork() {
+        let target = target();
+        let plane = PlaneRect::ground(Vec3::new(0.0, 1.5, 0.0), Vec2::splat(3.0));
+        let outlines = outlines();
+        let occluder = Occluder::cut(LIGHT, plane, &target, &outlines, Some, 1e-3).unwrap();
+
+        // straight edges flatten to their corners, so each contour keeps its
+        // points in order
+        let expected: Vec<Vec<Vec2>> = outlines
+            .iter()
+            .map(|outline| flatten(outline, Some, 1e-3).remove(0))
+            .collect();
+        assert_eq!(occluder.contours.len(), expected.len());
+        for (contour, expected) in occluder.contours.iter().zip(&expected) {
+            assert_eq!(contour.len(), expected.len());
+            for (point, expected) in contour.iter().zip(expected) {
+                let on_plane = plane.point_at(*point);
+                let (_, shadow) = target.intersect_ray(LIGHT, on_plane - LIGHT).unwrap();
+                assert!(shadow.distance(*expected) < 1e-4, "{shadow} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn light_below_the_plane_casts_nothing() {
+        let target = target();
+        let plane = PlaneRect::ground(Vec3::new(0.0, 1.5, 0.0), Vec2::splat(3.0));
+        let light = Vec3::new(0.2, 1.0, 0.5);
+        let error = Occluder::cut(light, plane, &target, &outlines(), Some, 1e-3).unwrap_err();
+        assert_eq!(error, ShadowError::PlaneNotBetween);
+        // and edge on to the plane
+        let error = Occluder::cut(Vec3::new(0.2, 1.5, 0.5), plane, &target, &outlines(), Some, 1e-3).unwrap_err();
+        assert_eq!(error, ShadowError::Degenerate);
+    }
+}
@@ -0,0 +1,283 @@
+//! Grid-method stencils for painting floor art by hand.
+//!
+//! The artist chalks a grid of square cells on the floor, then copies the
+//! design one cell at a time. The overview sheet shows the whole design with
+//! the grid and its labels; the cell sheets show each cell that has anything in
+//! it blown up, with a finer grid to judge positions inside the cell by eye.
+
+use std::{error::Error, fmt};
+
+use glam::Vec2;
+use image::{Pixel, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut, draw_line_segment_mut, Blend};
+use imageproc::rect::Rect;
+
+use crate::poster::{column_name, sheet_name};
+use crate::fonts::FontRegistry;
+use crate::text::draw_text;
+use crate::warp::{self, Filter, WarpOptions};
+
+const GRID: Rgba<u8> = Rgba([220, 40, 40, 255]);
+const SUBGRID: Rgba<u8> = Rgba([220, 40, 40, 96]);
+const INK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const PAPER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+/// Overview cells are blown up to at least this many pixels so their labels
+/// fit.
+const MIN_OVERVIEW_CELL: f32 = 32.0;
+/// Fainter than this isn't worth painting.
+const VISIBLE_ALPHA: u8 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StencilError {
+    /// Cells must be a positive size on the floor and in the artwork.
+    BadCellSize,
+    /// Cell sheets must hold at least one enlarged cell of at least a pixel.
+    EmptySheet,
+}
+
+impl fmt::Display for StencilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StencilError::BadCellSize => write!(f, "grid cells must be larger than nothing"),
+            StencilError::EmptySheet => write!(f, "cell sheets must hold at least one cell"),
+        }
+    }
+}
+
+impl Error for StencilError {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StencilOptions {
+    /// Side of a grid cell on the floor, in centimetres.
+    pub cell_cm: f32,
+    /// Shown wherever the artwork is transparent. Mid grey by default, so both
+    /// light and dark artwork stand out.
+    pub background: Rgba<u8>,
+    /// Side of an enlarged cell on the cell sheets, in pixels.
+    pub enlarged_pixels: u32,
+    /// Each enlarged cell is split into this many parts each way.
+    pub subdivisions: u32,
+    /// Enlarged cells per cell sheet, across and down.
+    pub sheet_cells: (u32, u32),
+}
+
+impl Default for StencilOptions {
+    fn default() -> Self {
+        Self {
+            cell_cm: 20.0,
+            background: Rgba([128, 128, 128, 255]),
+            enlarged_pixels: 400,
+            subdivisions: 4,
+            sheet_cells: (3, 4),
+        }
+    }
+}
+
+/// A floor image split into grid cells. Cells are named like poster sheets:
+/// lettered columns, numbered rows, from the image's top left.
+pub struct Stencil<'a> {
+    artwork: &'a RgbaImage,
+    options: StencilOptions,
+    cell_pixels: f32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl<'a> Stencil<'a> {
+    pub fn new(artwork: &'a RgbaImage, pixels_per_metre: f32, options: StencilOptions) -> Result<Self, StencilError> {
+        let cell_pixels = options.cell_cm / 100.0 * pixels_per_metre;
+        if cell_pixels <= 0.0 || !cell_pixels.is_finite() {
+            return Err(StencilError::BadCellSize);
+        }
+        let (across, down) = options.sheet_cells;
+        if across == 0 || down == 0 || options.enlarged_pixels == 0 {
+            return Err(StencilError::EmptySheet);
+        }
+        Ok(Self {
+            artwork,
+            options,
+            cell_pixels,
+            columns: (artwork.width() as f32 / cell_pixels).ceil() as u32,
+            rows: (artwork.height() as f32 / cell_pixels).ceil() as u32,
+        })
+    }
+
+    /// The whole design on its background, with the grid drawn over it and
+    /// cell labels along the top and left.
+    pub fn overview(&self) -> RgbaImage {
+        let scale = (MIN_OVERVIEW_CELL / self.cell_pixels).max(1.0);
+        let cell_pixels = self.cell_pixels * scale;
+        let artwork = self.resampled(
+            Vec2::ZERO,
+            (self.artwork.width() as f32 * scale).round() as u32,
+            (self.artwork.height() as f32 * scale).round() as u32,
+            scale,
+        );
+
+        let gutter = (cell_pixels * 0.5).clamp(24.0, 64.0) as u32;
+        let font_size = gutter as f32 * 0.5;
+        let mut fonts = FontRegistry::bundled();
+        // whole cells, even where the artwork stops short
+        let width = gutter + (self.columns as f32 * cell_pixels).ceil() as u32 + 1;
+        let height = gutter + (self.rows as f32 * cell_pixels).ceil() as u32 + 1;
+        let mut image = RgbaImage::from_pixel(width, height, PAPER);
+        draw_filled_rect_mut(&mut image,
+            Rect::at(gutter as i32, gutter as i32).of_size(width - gutter, height - gutter),
+            self.options.background);
+        image::imageops::replace(&mut image, &artwork, gutter as i64, gutter as i64);
+        draw_text(&mut fonts, &mut image, &format!("{} cm", self.options.cell_cm), font_size * 0.6,
+            ((gutter / 8) as i32, (gutter / 4) as i32), GRID);
+
+        let origin = gutter as f32;
+        let end = Vec2::new(width as f32, height as f32);
+        for column in 0..=self.columns {
+            let x = origin + column as f32 * cell_pixels;
+            draw_line_segment_mut(&mut image, (x, origin), (x, end.y), GRID);
+            if column < self.columns {
+                draw_text(&mut fonts, &mut image, &column_name(column), font_size,
+                    ((x + cell_pixels / 2.0 - font_size / 2.0) as i32, (gutter / 4) as i32), INK);
+            }
+        }
+        for row in 0..=self.rows {
+            let y = origin + row as f32 * cell_pixels;
+            draw_line_segment_mut(&mut image, (origin, y), (end.x, y), GRID);
+            if row < self.rows {
+                draw_text(&mut fonts, &mut image, &(row + 1).to_string(), font_size,
+                    ((gutter / 8) as i32, (y + cell_pixels / 2.0 - font_size / 2.0) as i32), INK);
+            }
+        }
+        image
+    }
+
+    /// Part of the artwork from `corner` (in artwork pixels) enlarged by
+    /// `scale`, on the background.
+    fn resampled(&self, corner: Vec2, width: u32, height: u32, scale: f32) -> RgbaImage {
+        let options = WarpOptions {
+            filter: Filter::Bicubic,
+            supersample: 1,
+        };
+        let mut image = warp::warp(self.artwork, width, height, options, |p| Some(corner + p / scale));
+        for pixel in image.pixels_mut() {
+            let mut background = self.options.background;
+            background.blend(pixel);
+            *pixel = background;
+        }
+        image
+    }
+
+    /// Whether none of the cell's pixels are visible enough to paint.
+    fn is_empty(&self, column: u32, row: u32) -> bool {
+        let x0 = (column as f32 * self.cell_pixels) as u32;
+        let y0 = (row as f32 * self.cell_pixels) as u32;
+        let x1 = (((column + 1) as f32 * self.cell_pixels).ceil() as u32).min(self.artwork.width());
+        let y1 = (((row + 1) as f32 * self.cell_pixels).ceil() as u32).min(self.artwork.height());
+        (y0..y1).all(|y| (x0..x1).all(|x| self.artwork.get_pixel(x, y)[3] < VISIBLE_ALPHA))
+    }
+
+    /// One cell enlarged, with its subdivisions and an outline.
+    fn enlarged(&self, column: u32, row: u32) -> RgbaImage {
+        let size = self.options.enlarged_pixels;
+        let corner = Vec2::new(column as f32, row as f32) * self.cell_pixels;
+        let cell = self.resampled(corner, size, size, size as f32 / self.cell_pixels);
+
+        let n = self.options.subdivisions.max(1);
+        let mut blended = Blend(cell);
+        for i in 1..n {
+            let at = (i * size / n) as f32;
+            draw_line_segment_mut(&mut blended, (at, 0.0), (at, size as f32), SUBGRID);
+            draw_line_segment_mut(&mut blended, (0.0, at), (size as f32, at), SUBGRID);
+        }
+        let mut cell = blended.0;
+        draw_hollow_rect_mut(&mut cell, Rect::at(0, 0).of_size(size, size), GRID);
+        cell
+    }
+
+    /// Sheets of enlarged cells, each labelled with its name, in reading
+    /// order. Cells with nothing to paint are left out.
+    pub fn cell_sheets(&self) -> Vec<RgbaImage> {
+        let cells: Vec<(u32, u32)> = (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .filter(|&(column, row)| !self.is_empty(column, row))
+            .collect();
+
+        let size = self.options.enlarged_pixels;
+        let label_height = (size / 8).max(20);
+        let pad = label_height / 2;
+        let (across, down) = self.options.sheet_cells;
+        let slot = Vec2::new((size + pad) as f32, (size + label_height + pad) as f32);
+        let width = pad + across * (size + pad);
+        let height = pad + down * (size + label_height + pad);
+        let mut fonts = FontRegistry::bundled();
+
+        cells
+            .chunks((across * down) as usize)
+            .map(|page| {
+                let mut sheet = RgbaImage::from_pixel(width, height, PAPER);
+                for (i, &(column, row)) in page.iter().enumerate() {
+                    let at = Vec2::new((i as u32 % across) as f32, (i as u32 / across) as f32) * slot
+                        + pad as f32;
+                    draw_text(&mut fonts, &mut sheet, &sheet_name(column, row), label_height as f32 * 0.7,
+                        (at.x as i32, at.y as i32), INK);
+                    let top = at.y as i64 + label_height as i64;
+                    image::imageops::overlay(&mut sheet, &self.enlarged(column, row), at.x as i64, top);
+                }
+                sheet
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two metres by one at 100 pixels a metre, painted only in its top left
+    /// and bottom right corners.
+    fn artwork() -> RgbaImage {
+        RgbaImage::from_fn(200, 100, |x, y| {
+            if (x < 10 && y < 10) || (x >= 190 && y >= 90) {
+                INK
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    #[test]
+    fn cells_cover_the_artwork() {
+        let artwork = artwork();
+        let stencil = Stencil::new(&artwork, 100.0, StencilOptions { cell_cm: 30.0, ..Default::default() }).unwrap();
+        assert_eq!((stencil.columns, stencil.rows), (7, 4));
+        assert!(!stencil.is_empty(0, 0));
+        assert!(stencil.is_empty(1, 0));
+        assert!(!stencil.is_empty(6, 3));
+    }
+
+    #[test]
+    fn empty_cells_are_left_off_the_sheets() {
+        let artwork = artwork();
+        let options = StencilOptions { enlarged_pixels: 40, sheet_cells: (1, 1), ..Default::default() };
+        let stencil = Stencil::new(&artwork, 100.0, options).unwrap();
+        let sheets = stencil.cell_sheets();
+        assert_eq!(sheets.len(), 2);
+        // the enlarged cell sits below its label, with its outline in grid red
+        assert_eq!(sheets[0].get_pixel(10, 10 + 20), &GRID);
+    }
+
+    #[test]
+    fn sizes_must_be_positive() {
+        let artwork = artwork();
+        let stencil = |pixels_per_metre, options| Stencil::new(&artwork, pixels_per_metre, options).err();
+        for cell_cm in [0.0, -5.0, f32::NAN] {
+            let options = StencilOptions { cell_cm, ..Default::default() };
+            assert_eq!(stencil(100.0, options), Some(StencilError::BadCellSize));
+        }
+        assert_eq!(stencil(0.0, StencilOptions::default()), Some(StencilError::BadCellSize));
+        for sheet_cells in [(0, 4), (3, 0)] {
+            let options = StencilOptions { sheet_cells, ..Default::default() };
+            assert_eq!(stencil(100.0, options), Some(StencilError::EmptySheet));
+        }
+        let options = StencilOptions { enlarged_pixels: 0, ..Default::default() };
+        assert_eq!(stencil(100.0, options), Some(StencilError::EmptySheet));
+    }
+}
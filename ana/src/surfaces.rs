@@ -0,0 +1,217 @@
+//! Anamorphosis split across several planar surfaces.
+//!
+//! Every surface texel is traced back along its sight line to the eye; if no
+//! other surface is in the way, it takes whatever the picture shows at that
+//! point. Because neighbouring surfaces sample the picture along the very same
+//! rays, the image runs continuously across floor/wall corners and box edges.
+
+use glam::{Vec2, Vec3};
+use image::RgbaImage;
+
+use crate::projection::PlaneRect;
+use crate::warp::{self, WarpOptions};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Surface {
+    pub name: String,
+    pub rect: PlaneRect,
+}
+
+impl Surface {
+    pub fn new(name: impl Into<String>, rect: PlaneRect) -> Self {
+        Self {
+            name: name.into(),
+            rect,
+        }
+    }
+}
+
+/// The five faces of an axis-aligned box that can be seen from outside it
+/// (the bottom sits on the floor). Each face is oriented to be read from
+/// outside the box.
+pub fn box_faces(name: &str, center: Vec3, size: Vec3) -> Vec<Surface> {
+    let h = size / 2.0;
+    let face = |suffix: &str, top_left: Vec3, u_axis: Vec3, v_axis: Vec3, extent: Vec2| {
+        Surface::new(
+            format!("{name}/{suffix}"),
+            PlaneRect::new(center + top_left, u_axis, v_axis, extent),
+        )
+    };
+    vec![
+        face("top", Vec3::new(-h.x, h.y, -h.z), Vec3::X, Vec3::Z, Vec2::new(size.x, size.z)),
+        face("front", Vec3::new(-h.x, h.y, h.z), Vec3::X, Vec3::NEG_Y, Vec2::new(size.x, size.y)),
+        face("back", Vec3::new(h.x, h.y, -h.z), Vec3::NEG_X, Vec3::NEG_Y, Vec2::new(size.x, size.y)),
+        face("right", Vec3::new(h.x, h.y, h.z), Vec3::NEG_Z, Vec3::NEG_Y, Vec2::new(size.z, size.y)),
+        face("left", Vec3::new(-h.x, h.y, -h.z), Vec3::Z, Vec3::NEG_Y, Vec2::new(size.z, size.y)),
+    ]
+}
+
+/// Texture produced for one surface, covering its whole rectangle.
+#[derive(Clone, Debug)]
+pub struct SurfaceTexture {
+    pub name: String,
+    pub image: RgbaImage,
+}
+
+/// One eye and picture plane shared by several target surfaces.
+#[derive(Clone, Debug)]
+pub struct MultiSurfaceLayout {
+    pub eye: Vec3,
+    pub picture: PlaneRect,
+    pub surfaces: Vec<Surface>,
+}
+
+impl MultiSurfaceLayout {
+    pub fn new(eye: Vec3, picture: PlaneRect, surfaces: Vec<Surface>) -> Self {
+        Self {
+            eye,
+            picture,
+            surfaces,
+        }
+    }
+
+    /// Picture coordinates seen through `point` on surface `index`, if the eye
+    /// can actually see that point.
+    pub fn picture_coords(&self, index: usize, point: Vec3) -> Option<Vec2> {
+        let surface = &self.surfaces[index].rect;
+        let direction = point - self.eye;
+        // surfaces are one-sided: seen from behind they are hidden
+        if direction.dot(surface.normal()) <= 0.0 {
+            return None;
+        }
+
+        let occluded = self.surfaces.iter().enumerate().any(|(other, s)| {
+            other != index
+                && s.rect
+                    .intersect_ray(self.eye, direction)
+                    .is_some_and(|(t, coords)| t < 1.0 - 1e-4 && s.rect.contains(coords))
+        });
+        if occluded {
+            return None;
+        }
+
+        let (_, coords) = self.picture.intersect_ray(self.eye, direction)?;
+        Some(coords)
+    }
+
+    /// Produces one texture per surface, in the same order as `surfaces`.
+    /// `artwork` is stretched over the whole picture rectangle.
+    pub fn render(
+        &self,
+        artwork: &RgbaImage,
+        pixels_per_metre: f32,
+        options: WarpOptions,
+    ) -> Vec<SurfaceTexture> {
+        let artwork_scale =
+            Vec2::new(artwork.width() as f32, artwork.height() as f32) / self.picture.size;
+
+        self.surfaces
+            .iter()
+            .enumerate()
+            .map(|(index, surface)| {
+                let rect = &surface.rect;
+                let width = (rect.size.x * pixels_per_metre).round().max(1.0) as u32;
+                let height = (rect.size.y * pixels_per_metre).round().max(1.0) as u32;
+                let image = warp::warp(artwork, width, height, options, |p| {
+                    let coords = p / pixels_per_metre;
+                    if !rect.contains(coords) {
+                        return None;
+                    }
+                    self.picture_coords(index, rect.point_at(coords))
+                        .map(|c| c * artwork_scale)
+                });
+                SurfaceTexture {
+                    name: surface.name.clone(),
+                    image,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warp::Filter;
+
+    const EYE: Vec3 = Vec3::new(0.3, 3.0, 6.0);
+
+    /// The floor and a metre cube on it, seen from in front and above.
+    fn layout() -> MultiSurfaceLayout {
+        let mut surfaces = vec![Surface::new(
+            "floor",
+            PlaneRect::ground(Vec3::ZERO, Vec2::splat(5.0)),
+        )];
+        surfaces.extend(box_faces("cube", Vec3::new(0.0, 0.5, 0.0), Vec3::ONE));
+        let picture = PlaneRect::facing(EYE, Vec3::new(0.0, 0.5, 0.0), Vec2::new(3.0, 2.0));
+        MultiSurfaceLayout::new(EYE, picture, surfaces)
+    }
+
+    fn index(layout: &MultiSurfaceLayout, name: &str) -> usize {
+        layout.surfaces.iter().position(|s| s.name == name).unwrap()
+    }
+
+    #[test]
+    fn seams_show_the_same_picture_point_from_both_sides() {
+        let layout = layout();
+        let (floor, top, front) = (
+            index(&layout, "floor"),
+            index(&layout, "cube/top"),
+            index(&layout, "cube/front"),
+        );
+        for x in [-0.4, 0.0, 0.35] {
+            // the cube's top front edge, and where its front meets the floor
+            for (a, b, point) in [
+                (top, front, Vec3::new(x, 1.0, 0.5)),
+                (front, floor, Vec3::new(x, 0.0, 0.5)),
+            ] {
+                let from_a = layout.picture_coords(a, point).expect("seen");
+                let from_b = layout.picture_coords(b, point).expect("seen");
+                assert!(
+                    from_a.distance(from_b) < 1e-5,
+                    "{from_a} and {from_b} at {point}"
+                );
+                // and that is where the sight line crosses the picture
+                let world = layout.picture.point_at(from_a);
+                assert!((world - EYE).normalize().dot((point - EYE).normalize()) > 1.0 - 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn hidden_points_show_nothing() {
+        let layout = layout();
+        let floor = index(&layout, "floor");
+        // behind the cube, and under it
+        assert!(layout
+            .picture_coords(floor, Vec3::new(0.0, 0.0, -0.8))
+            .is_none());
+        assert!(layout
+            .picture_coords(floor, Vec3::new(0.0, 0.0, 0.0))
+            .is_none());
+        assert!(layout
+            .picture_coords(floor, Vec3::new(0.0, 0.0, 1.5))
+            .is_some());
+    }
+
+    #[test]
+    fn back_faces_are_skipped() {
+        let layout = layout();
+        let back = index(&layout, "cube/back");
+        let rect = layout.surfaces[back].rect;
+        assert!(layout
+            .picture_coords(back, rect.point_at(rect.size / 2.0))
+            .is_none());
+        let artwork = RgbaImage::from_pixel(30, 20, image::Rgba([255, 255, 255, 255]));
+        let options = WarpOptions {
+            filter: Filter::Nearest,
+            supersample: 1,
+        };
+        let textures = layout.render(&artwork, 20.0, options);
+        assert!(textures[back].image.pixels().all(|p| p[3] == 0));
+        assert!(textures[index(&layout, "cube/front")]
+            .image
+            .pixels()
+            .any(|p| p[3] > 0));
+    }
+}
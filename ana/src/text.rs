@@ -0,0 +1,74 @@
+//! Drawing text into `image` buffers: short labels, set as [`RichText`] in
+//! the registry's fallback chain, and the rectangles cosmic-text's own
+//! `Buffer::draw` hands out.
+
+use glam::Vec2;
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::fonts::FontRegistry;
+use crate::rich::{RichText, Span, TextStyle};
+
+/// Draws `s` in `color` onto `image` with its top left at `position`,
+/// blending it over what is already there. Lines wrap at the image's
+/// right edge.
+pub fn draw_text(
+    fonts: &mut FontRegistry,
+    image: &mut RgbaImage,
+    s: &str,
+    font_size: f32,
+    position: (i32, i32),
+    color: Rgba<u8>
+) {
+    let style = TextStyle { size: font_size, color, ..Default::default() };
+    let width = image.width() as f32 - position.0 as f32;
+    let text = RichText::layout(fonts, &[Span::new(s, style)], 1.25, width)
+        .expect("the registry's own chain is always there");
+    text.draw(image, Vec2::new(position.0 as f32, position.1 as f32));
+}
+
+/// Blends `color` over the `w` by `h` pixels of `image` from `x`, `y`, as
+/// cosmic-text's `Buffer::draw` asks: colour glyphs come a pixel at a time
+/// in their own colours, so they go over what is there rather than
+/// replacing it. Whatever falls outside the image is dropped.
+pub fn blend_rect(image: &mut RgbaImage, x: i32, y: i32, w: u32, h: u32, color: Rgba<u8>) {
+    for py in y.max(0)..(y + h as i32).min(image.height() as i32) {
+        for px in x.max(0)..(x + w as i32).min(image.width() as i32) {
+            image.get_pixel_mut(px as u32, py as u32).blend(&color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_are_drawn_from_their_top_left() {
+        let mut image = RgbaImage::new(120, 40);
+        draw_text(&mut FontRegistry::bundled(), &mut image, "A1", 20.0, (30, 10), Rgba([255, 0, 0, 255]));
+        let inked: Vec<(u32, u32, &Rgba<u8>)> = image.enumerate_pixels().filter(|(_, _, p)| p[3] > 0).collect();
+        assert!(!inked.is_empty());
+        // give or take the antialiasing at the foot of the A
+        assert!(inked.iter().all(|(x, y, p)| *x >= 29 && *y >= 10 && p[0] == 255 && p[1] == 0));
+    }
+
+    #[test]
+    fn rects_blend_over_the_image() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        blend_rect(&mut image, 1, 1, 2, 1, Rgba([255, 0, 0, 255]));
+        blend_rect(&mut image, 1, 2, 2, 1, Rgba([255, 0, 0, 0]));
+        assert_eq!(image.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(2, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(3, 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(image.get_pixel(1, 2), &Rgba([0, 0, 255, 255]), "clear pixels leave it be");
+    }
+
+    #[test]
+    fn rects_are_clipped_to_the_image() {
+        let mut image = RgbaImage::new(4, 4);
+        blend_rect(&mut image, -2, 3, 4, 5, Rgba([255, 255, 255, 255]));
+        blend_rect(&mut image, 10, 10, 2, 2, Rgba([255, 255, 255, 255]));
+        let painted: Vec<(u32, u32)> = image.enumerate_pixels().filter(|(_, _, p)| p[3] > 0).map(|(x, y, _)| (x, y)).collect();
+        assert_eq!(painted, [(0, 3), (1, 3)]);
+    }
+}
@@ -0,0 +1,477 @@
+//! Vector output: pushing outlines through a mapping and writing SVG.
+//!
+//! A homography (let alone a mirror reflection) doesn't take Bézier curves to
+//! Bézier curves, so outlines are flattened into polygons after mapping,
+//! splitting each segment until the mapped curve stays within a tolerance of
+//! its chords.
+
+use std::fmt::Write;
+
+use glam::Vec2;
+
+/// One step of an outline, as produced by font scalers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCommand {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    QuadTo(Vec2, Vec2),
+    CurveTo(Vec2, Vec2, Vec2),
+    Close,
+}
+
+/// Never split a segment more than this many times over.
+const MAX_DEPTH: u32 = 12;
+/// Curves are always split at least this many times, so an S bend whose
+/// midpoint happens to fall on the chord isn't mistaken for a straight line.
+const MIN_CURVE_DEPTH: u32 = 2;
+
+/// Maps `path` through `map` and flattens it into closed polygons, each within
+/// `tolerance` (in mapped units) of the true mapped outline. Contours with any
+/// point that fails to map are left out.
+pub fn flatten(
+    path: &[PathCommand],
+    map: impl Fn(Vec2) -> Option<Vec2>,
+    tolerance: f32,
+) -> Vec<Vec<Vec2>> {
+    let mut contours = Vec::new();
+    let mut contour: Option<Vec<Vec2>> = Some(Vec::new());
+    let mut start = Vec2::ZERO;
+    let mut current = Vec2::ZERO;
+
+    let mut finish = |contour: &mut Option<Vec<Vec2>>| {
+        if let Some(points) = contour.replace(Vec::new()) {
+            if points.len() >= 3 {
+                contours.push(points);
+            }
+        }
+    };
+
+    for command in path {
+        let (end, min_depth, curve): (Vec2, u32, Box<dyn Fn(f32) -> Vec2>) = match *command {
+            PathCommand::MoveTo(to) => {
+                finish(&mut contour);
+                if let Some(points) = contour.as_mut() {
+                    match map(to) {
+                        Some(p) => points.push(p),
+                        None => contour = None,
+                    }
+                }
+                start = to;
+                current = to;
+                continue;
+            }
+            PathCommand::Close => {
+                finish(&mut contour);
+                current = start;
+                continue;
+            }
+            PathCommand::LineTo(to) => (to, 0, Box::new(move |t| current.lerp(to, t))),
+            PathCommand::QuadTo(control, to) => (
+                to,
+                MIN_CURVE_DEPTH,
+                Box::new(move |t| {
+                    let s = 1.0 - t;
+                    current * s * s + control * 2.0 * s * t + to * t * t
+                }),
+            ),
+            PathCommand::CurveTo(c1, c2, to) => (
+                to,
+                MIN_CURVE_DEPTH,
+                Box::new(move |t| {
+                    let s = 1.0 - t;
+                    current * s * s * s + c1 * 3.0 * s * s * t + c2 * 3.0 * s * t * t + to * t * t * t
+                }),
+            ),
+        };
+        current = end;
+        let Some(points) = contour.as_mut() else {
+            continue;
+        };
+        let (Some(&from), Some(to)) = (points.last(), map(end)) else {
+            contour = None;
+            continue;
+        };
+        let mapped = |t: f32| map(curve(t));
+        if !subdivide(&mapped, (0.0, from), (1.0, to), 0, min_depth, tolerance, points) {
+            contour = None;
+        }
+    }
+    finish(&mut contour);
+    contours
+}
+
+/// Appends the mapped points of the curve after `from` up to and including
+/// `to`. Returns `false` if some point along it doesn't map.
+fn subdivide(
+    curve: &dyn Fn(f32) -> Option<Vec2>,
+    from: (f32, Vec2),
+    to: (f32, Vec2),
+    depth: u32,
+    min_depth: u32,
+    tolerance: f32,
+    points: &mut Vec<Vec2>,
+) -> bool {
+    let t = (from.0 + to.0) / 2.0;
+    let Some(middle) = curve(t) else {
+        return false;
+    };
+    let flat = depth >= min_depth && distance_to_segment(middle, from.1, to.1) <= tolerance;
+    if flat || depth >= MAX_DEPTH {
+        points.push(to.1);
+        return true;
+    }
+    subdivide(curve, from, (t, middle), depth + 1, min_depth, tolerance, points)
+        && subdivide(curve, (t, middle), to, depth + 1, min_depth, tolerance, points)
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+/// An SVG document `size` metres across, sized in millimetres so it comes out
+/// at full scale, filling `contours` (given in metres) in black with the
+/// non-zero rule.
+pub fn svg(contours: &[Vec<Vec2>], size: Vec2) -> String {
+    let size_mm = size * 1000.0;
+    let mut path = String::new();
+    for contour in contours {
+        for (i, p) in contour.iter().enumerate() {
+            let p = *p * 1000.0;
+            let command = if i == 0 { 'M' } else { 'L' };
+            write!(path, "{command}{:.3} {:.3} ", p.x, p.y).unwrap();
+        }
+        path.push_str("Z ");
+    }
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.3}mm\" height=\"{h:.3}mm\" viewBox=\"0 0 {w:.3} {h:.3}\">\n",
+            "  <path fill=\"black\" fill-rule=\"nonzero\" d=\"{path}\"/>\n",
+            "</svg>\n",
+        ),
+        w = size_mm.x,
+        h = size_mm.y,
+        path = path.trim_end(),
+    )
+}
+
+/// A filled region: its boundary and the holes in it. The boundary winds
+/// with a positive signed area and the holes with a negative one, so the
+/// filled side is always on the same side of every edge.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Shape {
+    pub outer: Vec<Vec2>,
+    pub holes: Vec<Vec<Vec2>>,
+}
+
+fn signed_area(contour: &[Vec2]) -> f32 {
+    contour
+        .iter()
+        .zip(contour.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum::<f32>()
+        / 2.0
+}
+
+/// Even-odd point in polygon test.
+pub(crate) fn inside(point: Vec2, contour: &[Vec2]) -> bool {
+    let mut inside = false;
+    for (a, b) in contour.iter().zip(contour.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Sorts flattened contours into shapes by how deeply each is nested inside
+/// the others: outlines at even depths are boundaries, at odd depths holes.
+/// That matches the non-zero fill of font outlines as long as contours don't
+/// overlap.
+pub fn shapes(contours: &[Vec<Vec2>]) -> Vec<Shape> {
+    let containers: Vec<Vec<usize>> = contours
+        .iter()
+        .enumerate()
+        .map(|(i, contour)| {
+            (0..contours.len())
+                .filter(|&j| j != i && inside(contour[0], &contours[j]))
+                .collect()
+        })
+        .collect();
+    let depth = |i: usize| containers[i].len();
+    let oriented = |contour: &Vec<Vec2>, positive: bool| {
+        let mut contour = contour.clone();
+        if (signed_area(&contour) > 0.0) != positive {
+            contour.reverse();
+        }
+        contour
+    };
+
+    let mut shapes = Vec::new();
+    let mut shape_of = vec![None; contours.len()];
+    for (i, contour) in contours.iter().enumerate() {
+        if depth(i) % 2 == 0 {
+            shape_of[i] = Some(shapes.len());
+            shapes.push(Shape {
+                outer: oriented(contour, true),
+                holes: Vec::new(),
+            });
+        }
+    }
+    for (i, contour) in contours.iter().enumerate() {
+        if depth(i) % 2 == 1 {
+            // the innermost boundary around it
+            let parent = containers[i].iter().copied().find(|&j| depth(j) + 1 == depth(i));
+            if let Some(shape) = parent.and_then(|j| shape_of[j]) {
+                shapes[shape].holes.push(oriented(contour, false));
+            }
+        }
+    }
+    shapes
+}
+
+impl Shape {
+    /// Splits the shape into triangles, all with a positive signed area.
+    pub fn triangulate(&self) -> Vec<[Vec2; 3]> {
+        let mut polygon = self.outer.clone();
+        let rightmost = |contour: &Vec<Vec2>| contour.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+        let mut holes: Vec<&Vec<Vec2>> = self.holes.iter().collect();
+        holes.sort_by(|a, b| rightmost(b).total_cmp(&rightmost(a)));
+        for hole in holes {
+            bridge(&mut polygon, hole);
+        }
+        ear_clip(&polygon)
+    }
+}
+
+/// Joins `hole` into `polygon` with a pair of edges running from the hole's
+/// rightmost point to a polygon vertex it can see, so the two can be
+/// triangulated as one.
+fn bridge(polygon: &mut Vec<Vec2>, hole: &[Vec2]) {
+    let Some((start, &m)) = hole.iter().enumerate().max_by(|a, b| a.1.x.total_cmp(&b.1.x)) else {
+        return;
+    };
+    // the nearest edge hit by a ray heading right from m
+    let mut hit: Option<(f32, usize)> = None;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        if (a.y > m.y) == (b.y > m.y) {
+            continue;
+        }
+        let x = a.x + (m.y - a.y) / (b.y - a.y) * (b.x - a.x);
+        if x >= m.x && hit.is_none_or(|(nearest, _)| x < nearest) {
+            let end = if a.x > b.x { i } else { (i + 1) % polygon.len() };
+            hit = Some((x, end));
+        }
+    }
+    let Some((x, mut visible)) = hit else {
+        return;
+    };
+    // a vertex inside the triangle between m, the hit and that edge's end
+    // would block the view; the one closest in angle to the ray can't be
+    let (hit_point, end) = (Vec2::new(x, m.y), polygon[visible]);
+    let mut best_angle = f32::MAX;
+    for (i, p) in polygon.iter().enumerate() {
+        // (when the ray hits a vertex the triangle is flat, and every point
+        // along its line would count as inside)
+        if i == visible || *p == end || p.x < m.x || !in_triangle(*p, m, hit_point, end) {
+            continue;
+        }
+        let angle = (*p - m).angle_between(Vec2::X).abs();
+        if angle < best_angle {
+            best_angle = angle;
+            visible = i;
+        }
+    }
+
+    let mut bridged = Vec::with_capacity(polygon.len() + hole.len() + 2);
+    bridged.extend_from_slice(&polygon[..=visible]);
+    bridged.extend(hole[start..].iter().chain(&hole[..=start]));
+    bridged.extend_from_slice(&polygon[visible..]);
+    *polygon = bridged;
+}
+
+/// Whether `p` is inside or on the triangle `a b c`, of either winding.
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let (d1, d2, d3) = ((b - a).perp_dot(p - a), (c - b).perp_dot(p - b), (a - c).perp_dot(p - c));
+    let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(negative && positive)
+}
+
+/// Triangulates a simple polygon with a positive signed area by cutting off
+/// ears: corners turning left with no other vertex in their triangle.
+fn ear_clip(polygon: &[Vec2]) -> Vec<[Vec2; 3]> {
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    remaining.dedup_by(|a, b| polygon[*a] == polygon[*b]);
+    if remaining.len() > 1 && polygon[remaining[0]] == polygon[*remaining.last().unwrap()] {
+        remaining.pop();
+    }
+    let mut triangles = Vec::with_capacity(polygon.len());
+    let mut i = 0;
+    let mut since_last_cut = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        if since_last_cut > n {
+            // only left with slivers from rounding: let them go
+            break;
+        }
+        let (a, b, c) = (
+            polygon[remaining[(i + n - 1) % n]],
+            polygon[remaining[i]],
+            polygon[remaining[(i + 1) % n]],
+        );
+        let turn = (b - a).perp_dot(c - b);
+        let is_ear = turn > 0.0
+            && !remaining.iter().any(|&j| {
+                let p = polygon[j];
+                p != a && p != b && p != c && in_triangle(p, a, b, c)
+            });
+        if turn == 0.0 || is_ear {
+            if is_ear {
+                triangles.push([a, b, c]);
+            }
+            remaining.remove(i);
+            since_last_cut = 0;
+            // the corner before may have become an ear
+            i = if i == 0 { n - 2 } else { i - 1 };
+        } else {
+            i = (i + 1) % n;
+            since_last_cut += 1;
+        }
+    }
+    if let [a, b, c] = remaining[..] {
+        let (a, b, c) = (polygon[a], polygon[b], polygon[c]);
+        if (b - a).perp_dot(c - b) > 0.0 {
+            triangles.push([a, b, c]);
+        }
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Furthest any point of `curve` strays from the polygon through `points`.
+    fn furthest(curve: impl Fn(f32) -> Vec2, points: &[Vec2]) -> f32 {
+        (0..=1000)
+            .map(|i| curve(i as f32 / 1000.0))
+            .map(|p| {
+                points
+                    .windows(2)
+                    .map(|edge| distance_to_segment(p, edge[0], edge[1]))
+                    .fold(f32::MAX, f32::min)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn curves_stay_within_tolerance() {
+        let (a, c1, c2, b) = (Vec2::ZERO, Vec2::new(0.0, 100.0), Vec2::new(100.0, -50.0), Vec2::new(100.0, 50.0));
+        let cubic = |t: f32| {
+            let s = 1.0 - t;
+            a * s * s * s + c1 * 3.0 * s * s * t + c2 * 3.0 * s * t * t + b * t * t * t
+        };
+        let path = [PathCommand::MoveTo(a), PathCommand::CurveTo(c1, c2, b), PathCommand::Close];
+        let mut previous = 0;
+        for tolerance in [4.0, 1.0, 0.25, 0.05] {
+            let contours = flatten(&path, Some, tolerance);
+            assert_eq!(contours.len(), 1);
+            let points = &contours[0];
+            assert_eq!((points[0], *points.last().unwrap()), (a, b));
+            assert!(furthest(cubic, points) <= tolerance * 1.1, "{tolerance}: {}", furthest(cubic, points));
+            // tighter tolerances take more points
+            assert!(points.len() > previous);
+            previous = points.len();
+        }
+    }
+
+    #[test]
+    fn quadratics_follow_the_mapping() {
+        let path = [
+            PathCommand::MoveTo(Vec2::ZERO),
+            PathCommand::QuadTo(Vec2::new(1.0, 2.0), Vec2::new(2.0, 0.0)),
+            PathCommand::Close,
+        ];
+        let scale = |p: Vec2| Some(p * Vec2::new(100.0, 50.0));
+        let points = &flatten(&path, scale, 0.1)[0];
+        let quad = |t: f32| {
+            let s = 1.0 - t;
+            (Vec2::new(1.0, 2.0) * 2.0 * s * t + Vec2::new(2.0, 0.0) * t * t) * Vec2::new(100.0, 50.0)
+        };
+        assert!(furthest(quad, points) <= 0.11);
+    }
+
+    #[test]
+    fn straight_lines_are_not_split() {
+        let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let path: Vec<PathCommand> = [PathCommand::MoveTo(square[0])]
+            .into_iter()
+            .chain(square[1..].iter().map(|p| PathCommand::LineTo(*p)))
+            .chain([PathCommand::Close])
+            .collect();
+        assert_eq!(flatten(&path, Some, 0.01), vec![square.to_vec()]);
+    }
+
+    #[test]
+    fn an_o_triangulates_to_its_ring() {
+        let ring = |radius: f32, sides: u32| -> Vec<Vec2> {
+            (0..sides)
+                .map(|i| Vec2::new(3.0, 2.0) + Vec2::from_angle(i as f32 / sides as f32 * std::f32::consts::TAU) * radius)
+                .collect()
+        };
+        // wound the same way, as a font might, with the counter off centre
+        let outer = ring(2.0, 40);
+        let counter: Vec<Vec2> = ring(0.8, 24).iter().map(|p| *p + Vec2::new(0.3, -0.2)).collect();
+        let shapes = shapes(&[outer.clone(), counter.clone()]);
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].holes.len(), 1);
+
+        let triangles = shapes[0].triangulate();
+        let areas: Vec<f32> = triangles.iter().map(|t| signed_area(t)).collect();
+        assert!(areas.iter().all(|area| *area > 0.0));
+        let expected = signed_area(&outer) - signed_area(&counter);
+        let total: f32 = areas.iter().sum();
+        assert!((total - expected).abs() < 1e-3, "{total} != {expected}");
+        for [a, b, c] in triangles {
+            let centroid = (a + b + c) / 3.0;
+            assert!(inside(centroid, &outer) && !inside(centroid, &counter), "{centroid}");
+        }
+    }
+
+    #[test]
+    fn contours_that_fail_to_map_are_left_out() {
+        let triangle = |offset: Vec2| {
+            [
+                PathCommand::MoveTo(offset),
+                PathCommand::LineTo(offset + Vec2::X),
+                PathCommand::LineTo(offset + Vec2::Y),
+                PathCommand::Close,
+            ]
+        };
+        let path: Vec<PathCommand> = triangle(Vec2::ZERO).into_iter().chain(triangle(Vec2::new(5.0, 0.0))).collect();
+        let contours = flatten(&path, |p| (p.x < 3.0).then_some(p), 0.1);
+        assert_eq!(contours, vec![vec![Vec2::ZERO, Vec2::X, Vec2::Y]]);
+    }
+
+    #[test]
+    fn svg_is_drawn_at_full_size_in_millimetres() {
+        let contours = vec![vec![Vec2::ZERO, Vec2::new(0.5, 0.0), Vec2::new(0.25, 0.125)], vec![Vec2::ONE, Vec2::new(1.0, 2.0), Vec2::new(0.0, 2.0)]];
+        let document = svg(&contours, Vec2::new(1.0, 2.0));
+        assert!(document.starts_with("<?xml"));
+        assert!(document.contains(r#"width="1000.000mm" height="2000.000mm" viewBox="0 0 1000.000 2000.000""#));
+        assert!(document.contains(
+            r#"d="M0.000 0.000 L500.000 0.000 L250.000 125.000 Z M1000.000 1000.000 L1000.000 2000.000 L0.000 2000.000 Z""#
+        ));
+        assert!(document.contains(r#"fill-rule="nonzero""#));
+    }
+}
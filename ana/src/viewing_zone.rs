@@ -0,0 +1,327 @@
+//! How far a visitor can wander from the eye point before the text breaks up.
+//!
+//! From any other position the floor text is seen through a different set of
+//! sight lines. We compare what that viewer sees with what the intended viewer
+//! sees, after allowing for the text looking bigger or smaller and sitting
+//! elsewhere in the field of view. What's left is distortion; its worst case
+//! over a set of sample points (typically glyph corners) is the score.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use glam::{Vec2, Vec3};
+use image::{Rgba, RgbaImage};
+
+use crate::projection::{AnamorphicLayout, PlaneRect};
+use crate::vector::inside;
+
+/// Projects directions onto the tangent plane of a viewer gazing along their
+/// mean, with the viewer's head kept upright.
+fn view_plane(directions: &[Vec3]) -> Vec<Vec2> {
+    let gaze = directions.iter().sum::<Vec3>().normalize();
+    let up = if gaze.cross(Vec3::Y).length_squared() < 1e-6 {
+        Vec3::NEG_Z
+    } else {
+        Vec3::Y
+    };
+    let right = gaze.cross(up).normalize();
+    let up = right.cross(gaze);
+    directions
+        .iter()
+        .map(|d| {
+            let p = *d / d.dot(gaze);
+            Vec2::new(p.dot(right), p.dot(up))
+        })
+        .collect()
+}
+
+/// Worst angular error, in degrees, between where the `samples` (picture
+/// coordinates) appear from `eye` and where they should appear, allowing for
+/// uniform scale and a shift of gaze. `None` if the samples can't be seen in
+/// the right orientation at all from `eye`.
+pub fn distortion(layout: &AnamorphicLayout, samples: &[Vec2], eye: Vec3) -> Option<f32> {
+    let mut ideal = Vec::with_capacity(samples.len());
+    let mut seen = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let on_target = layout.target.point_at(layout.picture_to_target.apply(*sample)?);
+        ideal.push((layout.picture.point_at(*sample) - layout.eye).normalize());
+        seen.push((on_target - eye).normalize());
+    }
+    if samples.is_empty() || seen.iter().any(|d| d.dot(seen[0]) <= 0.0) {
+        return None;
+    }
+
+    let ideal = view_plane(&ideal);
+    let seen = view_plane(&seen);
+    let ideal_mean = ideal.iter().sum::<Vec2>() / ideal.len() as f32;
+    let seen_mean = seen.iter().sum::<Vec2>() / seen.len() as f32;
+
+    // least squares scale between the centred point sets
+    let (dot, norm) = ideal.iter().zip(&seen).fold((0.0, 0.0), |(dot, norm), (i, s)| {
+        let (i, s) = (*i - ideal_mean, *s - seen_mean);
+        (dot + i.dot(s), norm + i.length_squared())
+    });
+    if norm <= 0.0 || dot <= 0.0 {
+        return None;
+    }
+    let scale = dot / norm;
+
+    let worst = ideal
+        .iter()
+        .zip(&seen)
+        .map(|(i, s)| ((*i - ideal_mean) * scale - (*s - seen_mean)).length())
+        .fold(0.0, f32::max);
+    Some(worst.atan().to_degrees())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewingZoneError {
+    /// Candidate eyes must be a positive distance apart.
+    BadSpacing(f32),
+    /// The readable threshold must be a positive number of degrees.
+    BadThreshold(f32),
+}
+
+impl fmt::Display for ViewingZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewingZoneError::BadSpacing(spacing) => write!(f, "eyes must be a positive distance apart, not {spacing}"),
+            ViewingZoneError::BadThreshold(threshold) => write!(f, "threshold must be a positive angle, not {threshold}"),
+        }
+    }
+}
+
+impl Error for ViewingZoneError {}
+
+/// Distortion scores for a grid of eye positions over a floor region.
+#[derive(Clone, Debug)]
+pub struct ViewingZone {
+    /// The floor area the candidate eyes stand over.
+    pub region: PlaneRect,
+    pub eye_height: f32,
+    /// Distance between neighbouring candidates, in metres.
+    pub spacing: f32,
+    pub columns: usize,
+    pub rows: usize,
+    /// Row-major scores in degrees, `None` where the text can't be read.
+    pub scores: Vec<Option<f32>>,
+    /// Scores up to this many degrees count as readable.
+    pub threshold: f32,
+}
+
+impl ViewingZone {
+    pub fn analyse(
+        layout: &AnamorphicLayout,
+        samples: &[Vec2],
+        region: PlaneRect,
+        eye_height: f32,
+        spacing: f32,
+        threshold: f32,
+    ) -> Result<Self, ViewingZoneError> {
+        if spacing <= 0.0 || spacing.is_nan() {
+            return Err(ViewingZoneError::BadSpacing(spacing));
+        }
+        if threshold <= 0.0 || threshold.is_nan() {
+            return Err(ViewingZoneError::BadThreshold(threshold));
+        }
+        let columns = (region.size.x / spacing).floor() as usize + 1;
+        let rows = (region.size.y / spacing).floor() as usize + 1;
+        let mut scores = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let eye = region.point_at(Vec2::new(column as f32, row as f32) * spacing)
+                    + Vec3::Y * eye_height;
+                scores.push(distortion(layout, samples, eye));
+            }
+        }
+        Ok(Self {
+            region,
+            eye_height,
+            spacing,
+            columns,
+            rows,
+            scores,
+            threshold,
+        })
+    }
+
+    fn score(&self, column: usize, row: usize) -> Option<f32> {
+        self.scores[row * self.columns + column]
+    }
+
+    /// Colours each candidate from green (no distortion) through yellow (at
+    /// the threshold) to red (twice the threshold and beyond); unreadable
+    /// positions are grey. The readable zone is outlined in white.
+    pub fn heatmap(&self, pixels_per_metre: f32) -> RgbaImage {
+        let width = (self.region.size.x * pixels_per_metre).round().max(1.0) as u32;
+        let height = (self.region.size.y * pixels_per_metre).round().max(1.0) as u32;
+        let mut image = RgbaImage::from_fn(width, height, |x, y| {
+            let coords = (Vec2::new(x as f32, y as f32) + 0.5) / pixels_per_metre;
+            let column = ((coords.x / self.spacing).round() as usize).min(self.columns - 1);
+            let row = ((coords.y / self.spacing).round() as usize).min(self.rows - 1);
+            match self.score(column, row) {
+                None => Rgba([96, 96, 96, 255]),
+                Some(score) => {
+                    let t = (score / self.threshold / 2.0).clamp(0.0, 1.0);
+                    let red = (t * 2.0).min(1.0);
+                    let green = (2.0 - t * 2.0).min(1.0);
+                    Rgba([(red * 255.0) as u8, (green * 255.0) as u8, 0, 255])
+                }
+            }
+        });
+
+        let zone = self.zone();
+        for (i, start) in zone.iter().enumerate() {
+            let end = zone[(i + 1) % zone.len()];
+            imageproc::drawing::draw_line_segment_mut(
+                &mut image,
+                (start.x * pixels_per_metre, start.y * pixels_per_metre),
+                (end.x * pixels_per_metre, end.y * pixels_per_metre),
+                Rgba([255, 255, 255, 255]),
+            );
+        }
+        image
+    }
+
+    /// The boundary of the readable zone in region coordinates, traced with
+    /// marching squares. Where there are several, this is the one around the
+    /// best scoring position. Empty if nowhere is readable.
+    pub fn zone(&self) -> Vec<Vec2> {
+        // pad with an unreadable border so every contour closes
+        let value = |column: i64, row: i64| -> f32 {
+            if column < 0 || row < 0 || column >= self.columns as i64 || row >= self.rows as i64 {
+                return 1.0;
+            }
+            match self.score(column as usize, row as usize) {
+                Some(score) => score - self.threshold,
+                None => 1.0,
+            }
+        };
+        let position = |column: f32, row: f32| Vec2::new(column, row) * self.spacing;
+
+        // where the contour crosses each cell edge; edges are keyed by their
+        // lower corner and direction
+        let crossing = |(column, row, vertical): (i64, i64, bool)| {
+            let (c2, r2) = if vertical { (column, row + 1) } else { (column + 1, row) };
+            let (a, b) = (value(column, row), value(c2, r2));
+            let t = if (a - b).abs() < 1e-9 { 0.5 } else { a / (a - b) };
+            position(
+                column as f32 + (c2 - column) as f32 * t,
+                row as f32 + (r2 - row) as f32 * t,
+            )
+        };
+
+        let mut next: HashMap<(i64, i64, bool), (i64, i64, bool)> = HashMap::new();
+        for row in -1..self.rows as i64 {
+            for column in -1..self.columns as i64 {
+                let inside = |c, r| value(c, r) <= 0.0;
+                let top = (column, row, false);
+                let bottom = (column, row + 1, false);
+                let left = (column, row, true);
+                let right = (column + 1, row, true);
+                let case = (inside(column, row) as u8)
+                    | (inside(column + 1, row) as u8) << 1
+                    | (inside(column + 1, row + 1) as u8) << 2
+                    | (inside(column, row + 1) as u8) << 3;
+                let centre_inside = (value(column, row)
+                    + value(column + 1, row)
+                    + value(column + 1, row + 1)
+                    + value(column, row + 1))
+                    <= 0.0;
+                // segments run with the readable side on the same hand
+                let segments: &[_] = match case {
+                    1 => &[(top, left)],
+                    2 => &[(right, top)],
+                    3 => &[(right, left)],
+                    4 => &[(bottom, right)],
+                    5 if centre_inside => &[(top, right), (bottom, left)],
+                    5 => &[(top, left), (bottom, right)],
+                    6 => &[(bottom, top)],
+                    7 => &[(bottom, left)],
+                    8 => &[(left, bottom)],
+                    9 => &[(top, bottom)],
+                    10 if centre_inside => &[(left, top), (right, bottom)],
+                    10 => &[(right, top), (left, bottom)],
+                    11 => &[(right, bottom)],
+                    12 => &[(left, right)],
+                    13 => &[(top, right)],
+                    14 => &[(left, top)],
+                    _ => &[],
+                };
+                for &(from, to) in segments {
+                    next.insert(from, to);
+                }
+            }
+        }
+
+        // chain the segments into closed loops
+        let mut loops: Vec<Vec<Vec2>> = Vec::new();
+        while let Some(&start) = next.keys().next() {
+            let mut polygon = Vec::new();
+            let mut edge = start;
+            while let Some(to) = next.remove(&edge) {
+                polygon.push(crossing(edge));
+                edge = to;
+            }
+            loops.push(polygon);
+        }
+
+        let best = (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .filter_map(|(column, row)| self.score(column, row).map(|s| (column, row, s)))
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((column, row, _)) = best else {
+            return Vec::new();
+        };
+        let best = position(column as f32, row as f32);
+        loops
+            .into_iter()
+            .filter(|polygon| polygon.len() >= 3)
+            .find(|polygon| inside(best, polygon))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> (AnamorphicLayout, Vec<Vec2>) {
+        let eye = Vec3::new(0.0, 1.6, 3.0);
+        let picture = PlaneRect::facing(eye, eye.lerp(Vec3::ZERO, 0.5), Vec2::new(0.6, 0.3));
+        let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(6.0));
+        let samples = vec![Vec2::ZERO, Vec2::new(0.6, 0.0), Vec2::new(0.6, 0.3), Vec2::new(0.0, 0.3), Vec2::new(0.3, 0.15)];
+        (AnamorphicLayout::solve(eye, picture, floor).unwrap(), samples)
+    }
+
+    #[test]
+    fn the_intended_eye_sees_no_distortion() {
+        let (layout, samples) = layout();
+        assert!(distortion(&layout, &samples, layout.eye).unwrap() < 1e-2);
+        let aside = distortion(&layout, &samples, layout.eye + Vec3::X * 1.5).unwrap();
+        assert!(aside > 1.0, "{aside}");
+    }
+
+    #[test]
+    fn spacing_must_be_positive() {
+        let (layout, samples) = layout();
+        let region = PlaneRect::ground(Vec3::new(0.0, 0.0, 3.0), Vec2::splat(2.0));
+        for spacing in [0.0, -0.1, f32::NAN] {
+            let error = ViewingZone::analyse(&layout, &samples, region, 1.6, spacing, 2.0).unwrap_err();
+            assert!(matches!(error, ViewingZoneError::BadSpacing(_)));
+        }
+        let zone = ViewingZone::analyse(&layout, &samples, region, 1.6, 0.5, 2.0).unwrap();
+        assert_eq!((zone.columns, zone.rows), (5, 5));
+        // the eye stands in the middle of the region, so the zone goes round it
+        assert!(inside(Vec2::splat(1.0), &zone.zone()));
+    }
+
+    #[test]
+    fn threshold_must_be_positive() {
+        let (layout, samples) = layout();
+        let region = PlaneRect::ground(Vec3::new(0.0, 0.0, 3.0), Vec2::splat(2.0));
+        for threshold in [0.0, -1.0, f32::NAN] {
+            let error = ViewingZone::analyse(&layout, &samples, region, 1.6, 0.5, threshold).unwrap_err();
+            assert!(matches!(error, ViewingZoneError::BadThreshold(_)));
+        }
+    }
+}
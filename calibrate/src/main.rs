@@ -0,0 +1,363 @@
+//! Works out where a photo of the site was taken from.
+//!
+//! Usage: `calibrate <photo> [focal length in pixels]`. Click a floor point
+//! whose position you have measured in the photo, type that position as `x,z`
+//! in metres and press Enter. Once four or more points are in, press Enter
+//! again with nothing typed to solve. Backspace deletes what's typed, or
+//! undoes the last point. The solved camera is printed and written to
+//! `calibration.txt`; copy that into `integrate2/assets` to lay the text out
+//! for the photographer's spot. Without a focal length it is estimated from
+//! the floor's vanishing points.
+//!
+//! With the positions already measured up in a CSV, listed as `x,z` one per
+//! line: `calibrate <photo> --points <floor points.csv> [focal length in pixels]`.
+//! Click each of them in the photo in the same order, then press Enter.
+//!
+//! Or, with a printed checkerboard on the floor:
+//! `calibrate <photo> --board <columns>x<rows> <square in metres> [focal length in pixels]`,
+//! counting the board's inner corners. Its corners are found and solved
+//! straight away, with the top left inner corner as seen in the photo at the
+//! floor's origin, columns along +X and rows along +Z.
+//!
+//! Once solved, the marked points are shown in red, where the camera puts
+//! them in green, and a one metre floor grid in yellow.
+
+use std::{env, error::Error, fs};
+
+use ana::calibration::{Calibration, Focal};
+use ana::checkerboard::{find_corners, Checkerboard};
+use bevy::{prelude::*, render::texture::ImageSampler, window::PrimaryWindow};
+
+const SOLVE_KEY: KeyCode = KeyCode::Return;
+const UNDO_KEY: KeyCode = KeyCode::Back;
+const TITLE: &str = "calibrate";
+
+#[derive(Resource)]
+struct Site {
+    photo: image::DynamicImage,
+    /// Measured positions, as world `(x, z)`.
+    floor: Vec<Vec2>,
+    /// Whether the positions are typed in as the points are marked, rather
+    /// than listed up front.
+    typed: bool,
+    focal: Focal,
+    /// Marked points, in photo pixels.
+    marked: Vec<Vec2>,
+    /// A point marked in the photo whose position is being typed.
+    pending: Option<Vec2>,
+    typing: String,
+    calibration: Option<Calibration>,
+}
+
+impl Site {
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.photo.width() as f32, self.photo.height() as f32)
+    }
+
+    /// World position of a photo pixel, with the photo centred on the origin.
+    fn to_world(&self, pixel: Vec2) -> Vec2 {
+        let centred = pixel - self.size() / 2.0;
+        Vec2::new(centred.x, -centred.y)
+    }
+
+    fn to_pixel(&self, world: Vec2) -> Vec2 {
+        Vec2::new(world.x, -world.y) + self.size() / 2.0
+    }
+
+    /// What to do next, also shown in the window title.
+    fn prompt(&self) -> String {
+        if self.typed {
+            match self.pending {
+                Some(_) => format!("type point {}'s floor position as x,z in metres: {}", self.marked.len() + 1, self.typing),
+                None if self.marked.len() < 4 => format!("click floor point {}", self.marked.len() + 1),
+                None => format!("click floor point {}, or press Enter to solve", self.marked.len() + 1),
+            }
+        } else {
+            match self.floor.get(self.marked.len()) {
+                Some(next) => format!("click floor point {} of {}: {:?}", self.marked.len() + 1, self.floor.len(), next),
+                None => "all points marked, press Enter to solve".to_string(),
+            }
+        }
+    }
+}
+
+/// A floor position typed as `x,z`.
+fn parse_position(text: &str) -> Option<Vec2> {
+    let (x, z) = text.split_once(',')?;
+    Some(Vec2::new(x.trim().parse().ok()?, z.trim().parse().ok()?))
+}
+
+fn read_floor_points(path: &str) -> Result<Vec<Vec2>, Box<dyn Error>> {
+    let mut points = vec![];
+    for line in fs::read_to_string(path)?.lines() {
+        // skipping headers and blank lines
+        if let Some(point) = parse_position(line) {
+            points.push(point);
+        }
+    }
+    Ok(points)
+}
+
+fn read_board(size: &str, square: &str) -> Result<Checkerboard, Box<dyn Error>> {
+    let Some((columns, rows)) = size.split_once('x') else {
+        return Err(format!("board size should look like 9x6, not {size}").into());
+    };
+    let board = Checkerboard {
+        columns: columns.parse()?,
+        rows: rows.parse()?,
+        square: square.parse()?,
+    };
+    if board.columns < 2 || board.rows < 2 {
+        return Err(format!("a board needs at least 2x2 inner corners, not {size}").into());
+    }
+    Ok(board)
+}
+
+/// Solves the camera, reports how well it fits and saves it.
+fn calibrate(site: &Site) -> Option<Calibration> {
+    let floor = &site.floor[..site.marked.len()];
+    let calibration = match Calibration::solve(&site.marked, floor, site.size(), site.focal) {
+        Ok(calibration) => calibration,
+        Err(e) => {
+            eprintln!("cannot calibrate: {e}");
+            return None;
+        }
+    };
+    println!("eye at {:?}, focal length {:.0} px ({:.1} degrees vertical field of view)",
+        calibration.eye(), calibration.focal, calibration.fov_y(site.size().y).to_degrees());
+    let errors = calibration.reprojection_errors(&site.marked, floor);
+    let (worst, max) = errors.iter().copied().enumerate()
+        .fold((0, 0.0), |a, b| if b.1 > a.1 { b } else { a });
+    println!("reprojection error {:.2} px rms, {:.2} px at worst (point {}, {:?})",
+        calibration.rms_error, max, worst + 1, floor[worst]);
+    match calibration.save("calibration.txt") {
+        Ok(()) => println!("written to calibration.txt"),
+        Err(e) => eprintln!("could not write calibration.txt: {e}"),
+    }
+    Some(calibration)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let usage = "usage: calibrate <photo> [--points <floor points.csv> | --board <columns>x<rows> <square in metres>] [focal length in pixels]";
+    let mut args = env::args().skip(1).peekable();
+    let Some(photo) = args.next() else {
+        return Err(usage.into());
+    };
+    let photo = image::open(photo)?;
+    let mut site = Site {
+        photo,
+        floor: vec![],
+        typed: true,
+        focal: Focal::FromFloor,
+        marked: vec![],
+        pending: None,
+        typing: String::new(),
+        calibration: None,
+    };
+    match args.peek().map(String::as_str) {
+        Some("--board") => {
+            args.next();
+            let (Some(size), Some(square)) = (args.next(), args.next()) else {
+                return Err(usage.into());
+            };
+            let board = read_board(&size, &square)?;
+            let Some(grid) = find_corners(&site.photo.to_rgba8(), &board) else {
+                return Err(format!("no {}x{} checkerboard found", board.columns, board.rows).into());
+            };
+            println!("found a {}x{} checkerboard", grid.columns, grid.rows);
+            site.floor = grid.floor_points(board.square, Vec2::ZERO);
+            site.marked = grid.corners;
+            site.typed = false;
+        }
+        Some("--points") => {
+            args.next();
+            let Some(points) = args.next() else {
+                return Err(usage.into());
+            };
+            site.floor = read_floor_points(&points)?;
+            if site.floor.len() < 4 {
+                return Err("need at least four floor points".into());
+            }
+            site.typed = false;
+        }
+        _ => {}
+    }
+    if let Some(focal) = args.next() {
+        site.focal = Focal::Known(focal.parse()?);
+    }
+    if site.marked.is_empty() {
+        println!("{}", site.prompt());
+    } else {
+        site.calibration = calibrate(&site);
+    }
+
+    App::new()
+        .insert_resource(site)
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: TITLE.to_string(),
+                ..default()
+            }),
+            ..default()
+        }))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (mark_points, type_position, solve, draw_points, show_prompt).chain())
+        .run();
+
+    Ok(())
+}
+
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    site: Res<Site>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let mut photo = Image::from_dynamic(site.photo.clone(), true);
+    photo.sampler_descriptor = ImageSampler::linear();
+
+    // one world unit per photo pixel, zoomed out until the photo fits
+    let window = windows.single();
+    let scale = (site.size() / Vec2::new(window.width(), window.height())).max_element();
+    commands.spawn(Camera2dBundle {
+        projection: OrthographicProjection {
+            scale: scale.max(1.0),
+            ..default()
+        },
+        ..default()
+    });
+    commands.spawn(SpriteBundle {
+        texture: images.add(photo),
+        ..default()
+    });
+}
+
+fn mark_points(
+    mouse: Res<Input<MouseButton>>,
+    mut site: ResMut<Site>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    let more = if site.typed { site.pending.is_none() } else { site.marked.len() < site.floor.len() };
+    if !mouse.just_pressed(MouseButton::Left) || !more {
+        return;
+    }
+    let (camera, transform) = cameras.single();
+    let Some(world) = windows
+        .single()
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(transform, cursor))
+    else {
+        return;
+    };
+    let pixel = site.to_pixel(world);
+    if site.typed {
+        site.pending = Some(pixel);
+    } else {
+        site.marked.push(pixel);
+    }
+    println!("{}", site.prompt());
+}
+
+/// Typing, correcting and entering the position of the pending point, and
+/// undoing points.
+fn type_position(mut characters: EventReader<ReceivedCharacter>, mut keys: ResMut<Input<KeyCode>>, mut site: ResMut<Site>) {
+    for character in characters.iter() {
+        if site.pending.is_some() && (character.char.is_ascii_digit() || ".,- ".contains(character.char)) {
+            site.typing.push(character.char);
+        }
+    }
+    if keys.just_pressed(UNDO_KEY) {
+        if site.typing.pop().is_none() {
+            if site.pending.take().is_none() && site.marked.pop().is_some() {
+                if site.typed {
+                    site.floor.pop();
+                }
+                site.calibration = None;
+            }
+            println!("{}", site.prompt());
+        }
+    } else if keys.just_pressed(SOLVE_KEY) {
+        let Some(pixel) = site.pending else {
+            return;
+        };
+        let Some(position) = parse_position(&site.typing) else {
+            println!("{:?} is not a position like 1.5,-2", site.typing);
+            return;
+        };
+        site.marked.push(pixel);
+        site.floor.push(position);
+        site.pending = None;
+        site.typing.clear();
+        // the same Enter doesn't solve as well
+        keys.clear_just_pressed(SOLVE_KEY);
+        println!("{}", site.prompt());
+    }
+}
+
+/// Solves with the points marked so far, on Enter.
+fn solve(keys: Res<Input<KeyCode>>, mut site: ResMut<Site>) {
+    if !keys.just_pressed(SOLVE_KEY) || site.pending.is_some() {
+        return;
+    }
+    site.calibration = calibrate(&site);
+}
+
+fn show_prompt(site: Res<Site>, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if site.is_changed() {
+        windows.single_mut().title = format!("{TITLE}: {}", site.prompt());
+    }
+}
+
+fn draw_points(mut gizmos: Gizmos, site: Res<Site>) {
+    for pixel in &site.marked {
+        gizmos.circle_2d(site.to_world(*pixel), 6.0, Color::RED);
+    }
+    let Some(calibration) = &site.calibration else {
+        return;
+    };
+    let on_floor = |p: Vec2| calibration.project(Vec3::new(p.x, 0.0, p.y)).map(|pixel| site.to_world(pixel));
+    for floor in &site.floor {
+        if let Some(world) = on_floor(*floor) {
+            gizmos.circle_2d(world, 3.0, Color::GREEN);
+        }
+    }
+
+    // a one metre grid a metre beyond the measured points
+    let min = site.floor.iter().fold(Vec2::MAX, |a, b| a.min(*b)).floor() - 1.0;
+    let max = site.floor.iter().fold(Vec2::MIN, |a, b| a.max(*b)).ceil() + 1.0;
+    let mut line = |from: Vec2, to: Vec2| {
+        if let (Some(from), Some(to)) = (on_floor(from), on_floor(to)) {
+            gizmos.line_2d(from, to, Color::YELLOW);
+        }
+    };
+    for x in min.x as i32..=max.x as i32 {
+        line(Vec2::new(x as f32, min.y), Vec2::new(x as f32, max.y));
+    }
+    for z in min.y as i32..=max.y as i32 {
+        line(Vec2::new(min.x, z as f32), Vec2::new(max.x, z as f32));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_typed_as_x_comma_z() {
+        assert_eq!(parse_position("1.5,-2"), Some(Vec2::new(1.5, -2.0)));
+        assert_eq!(parse_position(" 3 , 0.25 "), Some(Vec2::new(3.0, 0.25)));
+        assert_eq!(parse_position("x,z"), None);
+        assert_eq!(parse_position("1.5"), None);
+        assert_eq!(parse_position(""), None);
+    }
+
+    #[test]
+    fn boards_need_two_corners_each_way() {
+        let board = read_board("9x6", "0.025").unwrap();
+        assert_eq!((board.columns, board.rows), (9, 6));
+        for size in ["1x6", "9x1", "0x0", "9", "ax6"] {
+            assert!(read_board(size, "0.025").is_err(), "{size}");
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Generates floor rings for cylinder and cone mirror anamorphoses.
+//!
+//! Usage: `catoptric [message]`. Writes `cylinder.png` and `cone.png`, each
+//! with a red circle marking where the mirror stands.
+
+use std::{env, error::Error};
+
+use ana::catoptric::{CatoptricLayout, Mirror};
+use ana::fonts::FontRegistry;
+use ana::projection::PlaneRect;
+use ana::rich::{RichText, Span, TextStyle};
+use ana::warp::{Filter, WarpOptions};
+use glam::{Vec2, Vec3};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::draw_hollow_circle_mut;
+
+const PIXELS_PER_METRE: f32 = 1000.0;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let message = env::args().nth(1)
+        .unwrap_or_else(|| "hello from the mirror".to_string());
+    let style = TextStyle { size: 28.0, ..Default::default() };
+    let text = RichText::layout(&mut FontRegistry::bundled(), &[Span::new(message, style)],
+        40.0 / 28.0, 240.0)?;
+    // faintly tinted, so the whole picture shows up on the floor
+    let mut text_image = RgbaImage::from_pixel(240, 80, Rgba([255, 255, 255, 10]));
+    text.draw(&mut text_image, Vec2::ZERO);
+
+    let warp_options = WarpOptions {
+        filter: Filter::Bicubic,
+        supersample: 2,
+    };
+
+    // a 10cm radius cylinder, looked at from half a metre away and above
+    let cylinder = Mirror::Cylinder {
+        base: Vec3::ZERO,
+        radius: 0.1,
+        height: 0.25,
+    };
+    let eye = Vec3::new(0.0, 0.5, 0.5);
+    let layout = CatoptricLayout::new(eye, cylinder, cylinder.picture(eye, 0.7),
+        PlaneRect::ground(Vec3::ZERO, Vec2::splat(1.2)));
+    let mut ring = layout.render(&text_image, PIXELS_PER_METRE, warp_options);
+    mark_mirror(&mut ring, &layout);
+    ring.save("cylinder.png")?;
+
+    // a cone, looked down on from directly above its apex
+    let cone = Mirror::Cone {
+        base: Vec3::ZERO,
+        radius: 0.1,
+        height: 0.15,
+    };
+    let eye = Vec3::new(0.0, 0.6, 0.0);
+    let layout = CatoptricLayout::new(eye, cone, cone.picture(eye, 0.9),
+        PlaneRect::ground(Vec3::ZERO, Vec2::splat(1.0)));
+    let mut ring = layout.render(&text_image, PIXELS_PER_METRE, warp_options);
+    mark_mirror(&mut ring, &layout);
+    ring.save("cone.png")?;
+
+    Ok(())
+}
+
+/// Outlines the mirror's footprint, so it can be placed on the print.
+fn mark_mirror(image: &mut RgbaImage, layout: &CatoptricLayout) {
+    let center = layout.floor.coords_of(layout.mirror.base()) * PIXELS_PER_METRE;
+    let radius = layout.mirror.radius() * PIXELS_PER_METRE;
+    draw_hollow_circle_mut(image,
+        (center.x.round() as i32, center.y.round() as i32),
+        radius.round() as i32,
+        Rgba([255, 0, 0, 255]));
+}
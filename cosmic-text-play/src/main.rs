@@ -1,28 +1,31 @@
-use cosmic_text::fontdb::{Source, Database};
+use ana::text::blend_rect;
+use cosmic_text::fontdb::{Database, ID};
 use cosmic_text::{Attrs, Color, FontSystem, SwashCache, Buffer, Metrics, Shaping};
-use imageproc::drawing::draw_filled_rect_mut;
-use imageproc::rect::Rect;
 
 fn main() {
 
-    // A FontSystem provides access to detected system fonts, create one per application
-    let mut font_system = FontSystem::new();
-
+    // Only the fonts bundled with ana, not the system's, so the output is the
+    // same on every machine
     let mut db = Database::new();
     let locale = "en-US";
 
-    // let mut font_system = FontSystem::new_with_fonts(
-    //     vec![Source::File("assets/fonts/FiraSans-Bold.ttf".into())].into_iter()
-    // );
+    db.load_fonts_dir("../ana/assets/fonts/");
+
+    // and a font of your own, such as a colour emoji font, given with
+    // --font, which the text is then set in
+    let before: Vec<ID> = db.faces().map(|face| face.id).collect();
+    if let Some(path) = std::env::args().skip_while(|arg| arg != "--font").nth(1) {
+        db.load_font_file(&path).unwrap();
+    }
+    let family = db.faces()
+        .find(|face| !before.contains(&face.id))
+        .map_or("Noto Emoji".to_string(), |face| face.families[0].0.clone());
 
-    db.load_fonts_dir("assets/fonts/");
-        
-    // let mut font_system = FontSystem::new_with_locale_and_db(locale.into(), db);
+    // A FontSystem provides access to the loaded fonts, create one per application
+    let mut font_system = FontSystem::new_with_locale_and_db(locale.into(), db);
 
     println!("{:?}", font_system.db());
 
-    font_system.db_mut().load_font_file("assets/fonts/Noto_Emoji/NotoEmoji-VariableFont_wght.ttf").unwrap();
-
     // A SwashCache stores rasterized glyphs, create one per application
     let mut swash_cache = SwashCache::new();
 
@@ -44,7 +47,7 @@ fn main() {
     // Attributes indicate what font to choose
     let mut attrs = Attrs::new();
 
-    attrs = attrs.family(cosmic_text::Family::Name("Noto Emoji"));
+    attrs = attrs.family(cosmic_text::Family::Name(&family));
 
     // Add some text!
     buffer.set_text("Hello, Rust! 🦀\n", attrs, Shaping::Advanced);
@@ -62,16 +65,14 @@ fn main() {
     // Create a default text color
     let text_color = Color::rgb(0xFF, 0xFF, 0xFF);
 
-    let mut imgbuf: image::ImageBuffer<_, Vec<_>> = image::ImageBuffer::new(buf_width as u32, buf_height as u32);
+    let mut imgbuf = image::RgbaImage::new(buf_width as u32, buf_height as u32);
 
     // Draw the buffer (for performance, instead use SwashCache directly)
     buffer.draw(&mut swash_cache, text_color, |x, y, w, h, color| {
         println!("{x},{y},{w},{h},{color:?}");
-        // Fill in your code here for drawing rectangles
-        let v = [color.r(), color.g(), color.b(), color.a()];
-        let rgba = image::Rgba(v);
-        println!("{v:?} -> {rgba:?}");
-        draw_filled_rect_mut(&mut imgbuf, Rect::at(x, y).of_size(w, h), rgba);
+        // colour glyphs, like emoji, come a pixel at a time in their own
+        // colours, so blend each one in rather than painting over
+        blend_rect(&mut imgbuf, x, y, w, h, image::Rgba([color.r(), color.g(), color.b(), color.a()]));
     });
 
     imgbuf.save("image.png").unwrap();
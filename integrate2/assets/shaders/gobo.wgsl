@@ -0,0 +1,64 @@
+// Bevy's PBR lighting, plus the light a projector throws through its frame.
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+#import bevy_pbr::mesh_view_bindings view
+#import bevy_pbr::mesh_bindings mesh
+#import bevy_pbr::pbr_functions as pbr_functions
+#import bevy_pbr::utils PI
+#import bevy_core_pipeline::tonemapping tone_mapping
+
+struct GoboMaterial {
+    base_color: vec4<f32>,
+    // world to the projector's clip space
+    gobo_view_projection: mat4x4<f32>,
+    gobo_position: vec3<f32>,
+    // luminous intensity of a white pixel, per channel
+    gobo_intensity: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> material: GoboMaterial;
+@group(1) @binding(1) var gobo_texture: texture_2d<f32>;
+@group(1) @binding(2) var gobo_sampler: sampler;
+@group(1) @binding(3) var base_color_texture: texture_2d<f32>;
+@group(1) @binding(4) var base_color_sampler: sampler;
+
+@fragment
+fn fragment(
+    in: MeshVertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> @location(0) vec4<f32> {
+    var base_color = material.base_color;
+#ifdef VERTEX_UVS
+    base_color = base_color * textureSample(base_color_texture, base_color_sampler, in.uv);
+#endif
+
+    var pbr_input = pbr_functions::pbr_input_new();
+    pbr_input.material.base_color = base_color;
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = pbr_functions::prepare_world_normal(in.world_normal, true, is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+    var output_color = pbr_functions::pbr(pbr_input);
+
+    // where this point is in the frame, sampled before branching so the
+    // derivatives stay valid
+    let clip = material.gobo_view_projection * vec4(in.world_position.xyz, 1.0);
+    let ndc = clip.xy / max(clip.w, 1e-6);
+    let uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let frame = textureSample(gobo_texture, gobo_sampler, uv).rgb;
+    if clip.w > 0.0 && all(uv >= vec2(0.0)) && all(uv <= vec2(1.0)) {
+        // a Lambertian surface lit like one of Bevy's point lights
+        let to_light = material.gobo_position - in.world_position.xyz;
+        let n_dot_l = max(dot(pbr_input.N, normalize(to_light)), 0.0);
+        let illuminance = frame * material.gobo_intensity.rgb * n_dot_l / dot(to_light, to_light);
+        output_color = vec4(output_color.rgb + base_color.rgb / PI * illuminance, output_color.a);
+    }
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
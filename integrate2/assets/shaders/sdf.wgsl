@@ -0,0 +1,32 @@
+// Text from a signed distance field atlas: 0.5 on the outline, more inside
+// and less outside, coloured by the mesh's vertex colours.
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+#import bevy_pbr::mesh_view_bindings view
+#import bevy_core_pipeline::tonemapping tone_mapping
+
+@group(1) @binding(0) var atlas_texture: texture_2d<f32>;
+@group(1) @binding(1) var atlas_sampler: sampler;
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    var distance = 0.0;
+#ifdef VERTEX_UVS
+    distance = textureSample(atlas_texture, atlas_sampler, in.uv).r;
+#endif
+    // about a screen pixel either side of the outline, however far the
+    // atlas is stretched or squeezed
+    let width = max(fwidth(distance) * 0.7, 1e-4);
+    let coverage = smoothstep(0.5 - width, 0.5 + width, distance);
+
+    var color = vec4(1.0);
+#ifdef VERTEX_COLORS
+    color = in.color;
+#endif
+    var output_color = vec4(color.rgb, color.a * coverage);
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
@@ -0,0 +1,102 @@
+//! Baking the text into the textures of meshes standing in the scene, so they
+//! carry on the anamorphosis from the eye.
+
+use ana::mesh::{bake, TriangleMesh};
+use ana::projection::PlaneRect;
+use ana::warp::WarpOptions;
+use bevy::{prelude::*, render::{mesh::{Indices, VertexAttributeValues}, render_resource::{Extent3d, PrimitiveTopology, TextureDimension, TextureFormat}}};
+use image::RgbaImage;
+
+/// The artwork and the spot it has to be seen from.
+#[derive(Resource)]
+pub struct Anamorphosis {
+    pub eye: Vec3,
+    pub picture: PlaneRect,
+    /// The surface the artwork is laid out on.
+    pub target: PlaneRect,
+    pub artwork: RgbaImage,
+}
+
+/// Marks an entity whose material texture gets baked from the eye, once its
+/// mesh is available (meshes loaded from glTF can take a few frames).
+#[derive(Component)]
+pub struct BakeFromEye {
+    pub texture_size: u32,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn bake_from_eye(
+    mut commands: Commands,
+    anamorphosis: Res<Anamorphosis>,
+    meshes: Res<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<(Entity, &Handle<Mesh>, &Handle<StandardMaterial>, &GlobalTransform, &BakeFromEye)>,
+) {
+    for (entity, mesh, material, transform, bake_from_eye) in &query {
+        let Some(mesh) = meshes.get(mesh) else {
+            continue;
+        };
+        commands.entity(entity).remove::<BakeFromEye>();
+
+        let Some(triangles) = triangle_mesh(mesh) else {
+            warn!("can only bake triangle meshes with positions, uvs and indices");
+            continue;
+        };
+        let texture = bake(
+            &triangles.transformed(transform.compute_matrix()),
+            anamorphosis.eye,
+            &anamorphosis.picture,
+            &anamorphosis.artwork,
+            bake_from_eye.texture_size,
+            bake_from_eye.texture_size,
+            WarpOptions { supersample: 2, ..default() },
+        );
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color_texture = Some(images.add(to_bevy_image(texture)));
+        }
+    }
+}
+
+fn triangle_mesh(mesh: &Mesh) -> Option<TriangleMesh> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+        return None;
+    };
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        return None;
+    };
+    let indices = match mesh.indices()? {
+        Indices::U16(indices) => indices.iter().map(|&i| i as u32).collect(),
+        Indices::U32(indices) => indices.clone(),
+    };
+    Some(TriangleMesh::new(
+        positions.iter().map(|p| Vec3::from_array(*p)).collect(),
+        uvs.iter().map(|uv| Vec2::from_array(*uv)).collect(),
+        indices,
+    ))
+}
+
+pub fn to_bevy_image(image: RgbaImage) -> Image {
+    let size = Extent3d {
+        width: image.width(),
+        height: image.height(),
+        ..default()
+    };
+    Image::new(size, TextureDimension::D2, image.into_raw(), TextureFormat::Rgba8UnormSrgb)
+}
+
+/// The inverse of `triangle_mesh`, with flat normals.
+pub fn to_bevy_mesh(triangles: &TriangleMesh) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION,
+        triangles.positions.iter().map(|p| p.to_array()).collect::<Vec<_>>());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0,
+        triangles.uvs.iter().map(|uv| uv.to_array()).collect::<Vec<_>>());
+    mesh.set_indices(Some(Indices::U32(triangles.indices.clone())));
+    mesh.duplicate_vertices();
+    mesh.compute_flat_normals();
+    mesh
+}
@@ -0,0 +1,105 @@
+//! Showing where the anamorphosis is meant to be seen from, and jumping the
+//! camera there to check that it resolves.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::bake::Anamorphosis;
+
+/// Toggles the camera between the eye and wherever it was before.
+const SNAP_KEY: KeyCode = KeyCode::E;
+
+/// Stands at the eye, looking at the middle of the picture.
+#[derive(Component)]
+pub struct EyeMarker;
+
+/// Where the orbit camera was before it was sent to the eye.
+#[derive(Component)]
+pub struct ReturnPose {
+    focus: Vec3,
+    alpha: f32,
+    beta: f32,
+    radius: f32,
+}
+
+pub fn spawn_eye_marker(mut commands: Commands, anamorphosis: Res<Anamorphosis>) {
+    commands.spawn((
+        SpatialBundle::from_transform(
+            Transform::from_translation(anamorphosis.eye)
+                .looking_at(anamorphosis.picture.center(), Vec3::Y),
+        ),
+        EyeMarker,
+    ));
+}
+
+/// Draws the eye, the picture plane and the sight lines through its corners
+/// down to where they land on the target.
+pub fn draw_eye(
+    mut gizmos: Gizmos,
+    anamorphosis: Res<Anamorphosis>,
+    query: Query<&GlobalTransform, With<EyeMarker>>,
+) {
+    for transform in &query {
+        let eye = transform.translation();
+        gizmos
+            .sphere(eye, Quat::IDENTITY, 0.05, Color::YELLOW)
+            .circle_segments(16);
+        gizmos.ray(eye, transform.forward() * 0.3, Color::YELLOW);
+
+        let picture = anamorphosis.picture.corners();
+        gizmos.linestrip(picture.into_iter().chain([picture[0]]), Color::YELLOW);
+        for corner in picture {
+            let end = anamorphosis
+                .target
+                .intersect_ray(eye, corner - eye)
+                .map(|(t, _)| eye + (corner - eye) * t)
+                .unwrap_or(corner);
+            gizmos.line(eye, end, Color::ORANGE);
+        }
+    }
+}
+
+/// Animates the orbit camera into the eye pose, and back out again on the
+/// next press.
+pub fn snap_to_eye(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    anamorphosis: Res<Anamorphosis>,
+    mut query: Query<(Entity, &mut PanOrbitCamera, Option<&ReturnPose>)>,
+) {
+    if !input.just_pressed(SNAP_KEY) {
+        return;
+    }
+    for (entity, mut camera, return_pose) in &mut query {
+        if let Some(pose) = return_pose {
+            camera.target_focus = pose.focus;
+            camera.target_alpha = pose.alpha;
+            camera.target_beta = pose.beta;
+            camera.target_radius = pose.radius;
+            commands.entity(entity).remove::<ReturnPose>();
+            info!("back to the orbit view");
+            continue;
+        }
+
+        commands.entity(entity).insert(ReturnPose {
+            focus: camera.target_focus,
+            alpha: camera.target_alpha,
+            beta: camera.target_beta,
+            radius: camera.target_radius,
+        });
+        // the orbit camera sits at `focus + rotation * (0, 0, radius)` with
+        // rotation `Ry(alpha) * Rx(-beta)`, so orbiting the picture centre
+        // puts it exactly at the eye, looking where the eye looks
+        let focus = anamorphosis.picture.center();
+        let offset = anamorphosis.eye - focus;
+        let alpha = offset.x.atan2(offset.z);
+        camera.target_focus = focus;
+        // take the short way round from wherever the camera has got to
+        camera.target_alpha = alpha + TAU * ((camera.target_alpha - alpha) / TAU).round();
+        camera.target_beta = (offset.y / offset.length()).asin();
+        camera.target_radius = offset.length();
+        info!("viewing from the eye at {}", anamorphosis.eye);
+    }
+}
@@ -0,0 +1,124 @@
+//! The ceiling projector as a spotlight with a gobo: its frame is thrown
+//! onto every lit surface in the scene, not just the floor, so the text also
+//! lands on the cut-out. The sculpture is unlit, to show its baked paint
+//! as-is, so it keeps that paint and the projector leaves it alone.
+//!
+//! Bevy 0.11 has no light cookies, so lit materials are swapped for a
+//! [`GoboMaterial`], which does the usual PBR lighting and then adds the
+//! projector's. The projector's light casts no shadows, so what it would
+//! have thrown on the sculpture carries on to the floor behind.
+
+use ana::projector::Projector;
+use bevy::{prelude::*, reflect::{TypePath, TypeUuid}, render::render_resource::{AsBindGroup, ShaderRef}};
+
+use crate::projector::{ceiling_projector, FloorText};
+use crate::FloorImages;
+
+/// Swaps the lit materials for ones lit by the projector too.
+const TOGGLE_KEY: KeyCode = KeyCode::G;
+/// Dim next to a real projector, to match the rest of the scene's lighting
+/// with no exposure control.
+const LUMENS: f32 = 60.0;
+
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "6f0bd3a4-7c8e-4f3a-9a55-3e2b8d61c9f1"]
+pub struct GoboMaterial {
+    #[uniform(0)]
+    pub base_color: Color,
+    #[uniform(0)]
+    pub gobo_view_projection: Mat4,
+    #[uniform(0)]
+    pub gobo_position: Vec3,
+    /// Luminous intensity of a white pixel of the frame, in candela.
+    #[uniform(0)]
+    pub gobo_intensity: Vec4,
+    #[texture(1)]
+    #[sampler(2)]
+    pub gobo: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    pub base_color_texture: Option<Handle<Image>>,
+}
+
+impl GoboMaterial {
+    /// `material` lit by `projector` showing `frame`, with `lumens` of
+    /// white light spread over the whole frame.
+    pub fn new(material: &StandardMaterial, projector: &Projector, frame: Handle<Image>, lumens: f32) -> Self {
+        Self {
+            base_color: material.base_color,
+            gobo_view_projection: projector.view_projection(),
+            gobo_position: projector.position,
+            gobo_intensity: Vec4::splat(lumens / projector.solid_angle()),
+            gobo: frame,
+            base_color_texture: material.base_color_texture.clone(),
+        }
+    }
+}
+
+impl Material for GoboMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/gobo.wgsl".into()
+    }
+}
+
+/// The frame the projector shows.
+#[derive(Resource)]
+pub struct Gobo {
+    projector: Projector,
+    frame: Handle<Image>,
+}
+
+/// Remembers the material a surface had before the projector lit it.
+#[derive(Component)]
+pub struct GoboLit(Handle<StandardMaterial>);
+
+pub fn setup_gobo(mut commands: Commands, floor_images: Res<FloorImages>) {
+    commands.insert_resource(Gobo {
+        projector: ceiling_projector(),
+        frame: floor_images.frame.clone(),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn toggle_gobo(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    gobo: Res<Gobo>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut gobo_materials: ResMut<Assets<GoboMaterial>>,
+    unlit: Query<(Entity, &Handle<StandardMaterial>), Without<FloorText>>,
+    lit: Query<(Entity, &GoboLit)>,
+    mut floor_text: Query<&mut Visibility, With<FloorText>>,
+) {
+    if !input.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    let on = lit.is_empty();
+    if on {
+        for (entity, handle) in &unlit {
+            // unlit and see-through surfaces aren't lit by anything
+            let Some(material) = standard_materials.get(handle) else {
+                continue;
+            };
+            if material.unlit || material.alpha_mode != AlphaMode::Opaque {
+                continue;
+            }
+            let gobo_material = GoboMaterial::new(material, &gobo.projector, gobo.frame.clone(), LUMENS);
+            commands.entity(entity)
+                .remove::<Handle<StandardMaterial>>()
+                .insert((gobo_materials.add(gobo_material), GoboLit(handle.clone())));
+        }
+        info!("projector lighting the scene");
+    } else {
+        for (entity, GoboLit(handle)) in &lit {
+            commands.entity(entity)
+                .remove::<(Handle<GoboMaterial>, GoboLit)>()
+                .insert(handle.clone());
+        }
+        info!("projector off");
+    }
+    // the projector replaces the paint
+    for mut visibility in &mut floor_text {
+        *visibility = if on { Visibility::Hidden } else { Visibility::Inherited };
+    }
+}
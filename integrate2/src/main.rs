@@ -1,30 +1,214 @@
-use std::{fs::OpenOptions, error::Error, io::{Write, Cursor}};
+use std::{error::Error, io::ErrorKind};
 
-use std::f32::consts::PI;
+mod bake;
+mod eye;
+mod gobo;
+mod projector;
+mod sdf_text;
+mod shadow;
 
-use bevy::prelude::*;
+use ana::calibration::Calibration;
+use ana::effects::Effect;
+use ana::fonts::FontRegistry;
+use ana::projection::{AnamorphicLayout, PlaneRect};
+use ana::rich::{Baseline, FitOptions, GlyphPlacement, PathAlign, RichText, Span, TextStyle};
+use ana::road_marking::{RoadMarking, RoadMarkingOptions};
+use ana::sdf::{SdfAtlas, SdfOptions};
+use ana::vector::{flatten, svg};
+use ana::viewing_zone::ViewingZone;
+use ana::warp::{Filter, WarpOptions};
+use bake::{to_bevy_image, Anamorphosis, BakeFromEye, bake_from_eye};
+use eye::{draw_eye, snap_to_eye, spawn_eye_marker};
+use gobo::{setup_gobo, toggle_gobo, GoboMaterial};
+use projector::{ceiling_projector, draw_projector, spawn_projector, toggle_projection, FloorText};
+use sdf_text::{spawn_sdf_text, toggle_sdf_text, SdfFloorText, SdfMaterial};
+use shadow::{recut_occluder, spawn_occluder, ShadowCutOut};
+use bevy::{pbr::PointLightShadowMap, prelude::*, transform::TransformSystem};
 use bevy_panorbit_camera::{PanOrbitCameraPlugin, PanOrbitCamera};
+use image::{Rgba, RgbaImage};
+
+const FLOOR_SIZE: f32 = 12.0;
+const FLOOR_PIXELS_PER_METRE: f32 = 100.0;
+/// Size of the text image, which is stretched over the picture plane.
+const TEXT_PIXELS: Vec2 = Vec2::new(200.0, 120.0);
+/// Height of the band along the bottom of the text image kept for the
+/// caption, which arches along it.
+const CAPTION_PIXELS: f32 = 20.0;
+/// How far, in metres, the flattened outlines may stray from the true ones.
+const VECTOR_TOLERANCE: f32 = 0.0005;
+/// Glyph corners may drift this far, in degrees, before the text is
+/// considered unreadable.
+const READABLE_DEGREES: f32 = 2.0;
+/// Where the point light starts, and the occluder is first cut for.
+const LIGHT_START: Vec3 = Vec3::new(0.0, 4.0, 14.0);
+/// Written by the `calibrate` tool from a photo of the site.
+const CALIBRATION: &str = "assets/calibration.txt";
+/// Also writes the floor art, its cut files and the viewing zone to `assets/`
+/// for making it for real.
+const EXPORT_FLAG: &str = "--export";
+/// Adds the font file named after it, such as a colour emoji font, to be
+/// tried before all but the first of the bundled fonts.
+const FONT_FLAG: &str = "--font";
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let message = "hello bevy world! how are you doing?";
-    let png_image_bytes = 
-        layout_text_as_png_image(message, 
-        28.0, 40.0, 
-        200.0, 120.0);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let export = args.iter().any(|arg| arg == EXPORT_FLAG);
+    let plain = TextStyle::default();
+    let message = [
+        Span::new("hello ", plain.clone()),
+        Span::new("bevy", TextStyle { color: Rgba([255, 200, 60, 255]), underline: true, ..plain.clone() }),
+        Span::new(" world! how are ", plain.clone()),
+        Span::new("you", TextStyle { italic: true, ..plain.clone() }),
+        Span::new(" doing? 🦀", plain.clone()),
+    ];
+    // as large as fills the picture above the caption, however long the
+    // message
+    let mut fonts = FontRegistry::bundled();
+    if let Some(path) = args.iter().skip_while(|arg| *arg != FONT_FLAG).nth(1) {
+        add_font(&mut fonts, path)?;
+    }
+    let fit = FitOptions { line_height: 1.4, ..default() };
+    let bounds = TEXT_PIXELS - Vec2::new(0.0, CAPTION_PIXELS);
+    let (mut text, report) = RichText::fit(&mut fonts, &message, bounds, fit)?;
+    println!("text set at {:.1} px in {} lines", report.size, report.lines);
+    // the caption arches gently over the bottom of the band, running
+    // clockwise so it stands on the outside of the arc
+    let caption = [Span::new("ana · anamorphic", TextStyle { size: 11.0, weight: 400, ..plain.clone() })];
+    let radius = 200.0;
+    let centre = Vec2::new(TEXT_PIXELS.x / 2.0, TEXT_PIXELS.y - 11.0 + radius);
+    let top = -std::f32::consts::FRAC_PI_2;
+    let arch = Baseline::arc(centre, radius, top - 0.3, top + 0.3, 0.1)
+        .expect("the arc has length");
+    let caption = RichText::on_baseline(&mut fonts, &caption, &arch, 1.2, PathAlign::Centre)?;
+
+    // the text should read upright from the eye, on a picture plane halfway
+    // along the sight line down to the floor. A site calibration from the
+    // `calibrate` tool puts the eye where the photo was taken.
+    let eye = match Calibration::load(CALIBRATION) {
+        Ok(calibration) => {
+            println!("eye from {CALIBRATION}");
+            calibration.eye()
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec3::new(0.0, 1.7, 5.0),
+        Err(e) => return Err(e.into()),
+    };
+    let floor = PlaneRect::ground(Vec3::ZERO, Vec2::splat(FLOOR_SIZE));
+    let picture = PlaneRect::facing(
+        eye, eye.lerp(Vec3::new(0.0, 0.0, 1.0), 0.5), Vec2::new(1.0, 0.6));
+    let layout = AnamorphicLayout::solve(eye, picture, floor)?;
+
+    // letters further along the floor look thinner, so set them heavier,
+    // as heavy from the eye as the nearest
+    let text_scale = picture.size / TEXT_PIXELS;
+    let distance = |g: &GlyphPlacement| layout.distance(g.middle() * text_scale);
+    let nearest = text.placements().iter().filter_map(distance).fold(f32::INFINITY, f32::min);
+    text.vary(|g| match distance(g) {
+        Some(d) => g.at_distance(d, nearest),
+        None => g.variation,
+    });
+    // faintly tinted, so the whole picture shows up on the floor
+    let mut text_image = RgbaImage::from_pixel(
+        TEXT_PIXELS.x as u32, TEXT_PIXELS.y as u32, Rgba([255, 255, 255, 10]));
+    // outlined, to stand out from the green of the floor
+    let effects = [Effect::Outline { color: Rgba([30, 40, 30, 255]), width: 1.0 }];
+    text.draw_with_effects(&mut text_image, Vec2::ZERO, &effects);
+    caption.draw_with_effects(&mut text_image, Vec2::ZERO, &effects);
+    // the far end of the text is squeezed hard, so supersample it
+    let warp_options = WarpOptions {
+        filter: Filter::Lanczos3,
+        supersample: 3,
+    };
+    let floor_image = layout.render(&text_image, FLOOR_PIXELS_PER_METRE, warp_options);
+    let outlines = text.outlines();
+    if export {
+        floor_image.save("assets/image.png")?;
+        // the same text as outlines, for cutting out of vinyl at full size
+        let contours: Vec<Vec<Vec2>> = outlines
+            .iter()
+            .flat_map(|outline| flatten(outline,
+                |p| layout.picture_to_target.apply(p * text_scale),
+                VECTOR_TOLERANCE))
+            .collect();
+        std::fs::write("assets/image.svg", svg(&contours, floor.size))?;
+    }
+
+    // or drawn from a distance field atlas, which stays sharp where the
+    // painted texture smears
+    let sdf_text = SdfFloorText::new(&SdfAtlas::new(&text, SdfOptions::default())?, |p| {
+        layout.picture_to_target.apply(p * text_scale).map(|coords| floor.point_at(coords))
+    });
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open("assets/image.png")?;
+    // or drawn out along the road, to read at a glance from anywhere over a
+    // stretch of it rather than exactly from the eye
+    if export {
+        let road = RoadMarking::solve(&text, RoadMarkingOptions::default())?;
+        road.render(&text_image, FLOOR_PIXELS_PER_METRE, warp_options)
+            .save("assets/road_marking.png")?;
+        std::fs::write("assets/road_marking.svg",
+            svg(&road.contours(&text, VECTOR_TOLERANCE), road.size()))?;
+    }
 
-    file.write_all(png_image_bytes.as_slice())?;
+    // or cast as a shadow by a cut-out hanging in front of the light
+    let shadow = ShadowCutOut::new(outlines, text_scale, &layout, LIGHT_START, export)?;
+    if export {
+        shadow.save_svg()?;
+    }
+
+    // or thrown from a projector: the frame it shows, and what it lights up
+    // on the floor, which has to match the painted version
+    let projector = ceiling_projector();
+    let frame = projector.render(&layout, &text_image, warp_options);
+    let projected = projector.illuminate(&frame, &floor, FLOOR_PIXELS_PER_METRE, WarpOptions::default());
+    if export {
+        frame.save("assets/projector_frame.png")?;
+        projected.save("assets/projected.png")?;
+    }
+
+    // where else on the floor a visitor of the same height can still read it
+    if export {
+        let glyph_corners: Vec<Vec2> = text.boxes()
+            .into_iter()
+            .flat_map(|(min, max)| [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)])
+            .map(|corner| corner * text_scale)
+            .collect();
+        let zone = ViewingZone::analyse(
+            &layout, &glyph_corners, floor, eye.y, 0.1, READABLE_DEGREES)?;
+        zone.heatmap(50.0).save("assets/viewing_zone.png")?;
+        let polygon: Vec<String> = zone.zone().iter()
+            .map(|coords| floor.point_at(*coords))
+            .map(|p| format!("{},{}", p.x, p.z))
+            .collect();
+        std::fs::write("assets/viewing_zone.csv", format!("x,z\n{}\n", polygon.join("\n")))?;
+        println!("exported to assets/");
+    }
 
     App::new()
         .insert_resource(Msaa::default())
+        .insert_resource(Anamorphosis {
+            eye,
+            picture,
+            target: floor,
+            artwork: text_image,
+        })
+        .insert_resource(shadow)
+        .insert_resource(sdf_text)
+        .insert_resource(FloorRenders {
+            painted: floor_image,
+            frame,
+            projected,
+        })
+        .insert_resource(PointLightShadowMap { size: 4096 })
         .add_plugins(DefaultPlugins)
         .add_plugins(PanOrbitCameraPlugin)
-        .add_systems(Startup, setup)
-        .add_systems(Update, (debug, light_movement))
+        .add_plugins(MaterialPlugin::<GoboMaterial>::default())
+        .add_plugins(MaterialPlugin::<SdfMaterial>::default())
+        .add_systems(PreStartup, add_floor_images)
+        .add_systems(Startup, (setup, spawn_eye_marker, spawn_projector, spawn_occluder,
+            setup_gobo, spawn_sdf_text))
+        .add_systems(Update, (debug, light_movement, draw_eye, snap_to_eye,
+            draw_projector, toggle_projection, recut_occluder, toggle_gobo, toggle_sdf_text))
+        .add_systems(PostUpdate,
+            bake_from_eye.after(TransformSystem::TransformPropagate))
         .run();
 
     Ok(())
@@ -33,28 +217,64 @@ fn main() -> Result<(), Box<dyn Error>> {
 #[derive(Component)]
 struct Light;
 
+/// The text painted on the floor, and the projector's frame and the light it
+/// throws there, drawn before the app starts.
+/// Loads the fonts in the file at `path` and puts them in the fallback chain
+/// straight after the first font, so their emoji are used before Noto
+/// Emoji's but the text stays in Fira Sans.
+fn add_font(fonts: &mut FontRegistry, path: &str) -> Result<(), Box<dyn Error>> {
+    let added = fonts.add_font(std::fs::read(path)?);
+    if added.is_empty() {
+        return Err(format!("no fonts in {path}").into());
+    }
+    let mut chain: Vec<String> = fonts.fallback().into_iter().map(str::to_string).collect();
+    chain.splice(1..1, added.iter().cloned());
+    fonts.set_fallback(&chain.iter().map(String::as_str).collect::<Vec<_>>())?;
+    println!("{path} adds {}", added.join(", "));
+    Ok(())
+}
+
+#[derive(Resource)]
+struct FloorRenders {
+    painted: RgbaImage,
+    frame: RgbaImage,
+    projected: RgbaImage,
+}
+
+/// `FloorRenders` as images.
+#[derive(Resource)]
+pub struct FloorImages {
+    pub painted: Handle<Image>,
+    pub frame: Handle<Image>,
+    pub projected: Handle<Image>,
+}
+
+fn add_floor_images(mut commands: Commands, mut images: ResMut<Assets<Image>>, renders: Res<FloorRenders>) {
+    let mut add = |image: &RgbaImage| images.add(to_bevy_image(image.clone()));
+    commands.insert_resource(FloorImages {
+        painted: add(&renders.painted),
+        frame: add(&renders.frame),
+        projected: add(&renders.projected),
+    });
+    commands.remove_resource::<FloorRenders>();
+}
+
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>
+    floor_images: Res<FloorImages>,
 ) {
     // opaque plane, uses `alpha_mode: Opaque` by default
     commands.spawn(PbrBundle {
-        mesh: meshes.add(shape::Plane::from_size(12.0).into()),
+        mesh: meshes.add(shape::Plane::from_size(FLOOR_SIZE).into()),
         material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
         transform: Transform::from_xyz(0.0, 0.0, 0.0),
         ..default()
     });
 
-    // load a texture and retrieve its aspect ratio
-    let texture_handle = asset_server.load("image.png");
-
-    // create a new quad mesh. this is what we will apply the texture to
-    let quad_handle = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(
-        8.0,
-        2.0,
-    ))));
+    // the pre-distorted text, covering the whole floor
+    let texture_handle = floor_images.painted.clone();
 
     // this material renders the texture normally
     let material_handle = materials.add(StandardMaterial {
@@ -64,17 +284,31 @@ fn setup(
         ..default()
     });
 
-    // textured quad - normal
-    commands.spawn(PbrBundle {
-        mesh: quad_handle.clone(),
+    // textured plane, lifted just clear of the floor
+    commands.spawn((PbrBundle {
+        mesh: meshes.add(shape::Plane::from_size(FLOOR_SIZE).into()),
         material: material_handle,
-        transform: Transform::from_xyz(0.0, 1.0, 7.0)
-            .with_rotation(Quat::from_rotation_x(-PI / 8.0)),
+        transform: Transform::from_xyz(0.0, 0.001, 0.0),
         ..default()
-    });
+    }, FloorText));
+
+    // a sculpture standing in the text, which carries on across its surface
+    commands.spawn((PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::UVSphere {
+            radius: 0.4,
+            sectors: 64,
+            stacks: 32,
+        })),
+        material: materials.add(StandardMaterial {
+            unlit: true,
+            ..default()
+        }),
+        transform: Transform::from_xyz(0.2, 0.4, 0.6),
+        ..default()
+    }, BakeFromEye { texture_size: 1024 }));
 
     // light
-    let light_position = Transform::from_xyz(0.0, 4.0, 14.0);
+    let light_position = Transform::from_translation(LIGHT_START);
     commands.spawn((
         PointLightBundle {
             point_light: PointLight {
@@ -82,7 +316,7 @@ fn setup(
                 shadows_enabled: true,
                 ..default()
             },
-            transform: light_position.clone(),
+            transform: light_position,
             ..default()
         },
         Light)
@@ -132,51 +366,9 @@ fn debug(
     mut gizmos: Gizmos, query: Query<&Transform, With<Light>>
 ) {
     for transform in &query {
-        let light_position = transform.translation.clone();
+        let light_position = transform.translation;
         gizmos
             .sphere(light_position, Quat::IDENTITY, 0.5, Color::WHITE)
             .circle_segments(64);
     }    
 }
-
-fn layout_text_as_png_image(
-    s: &str, 
-    font_size: f32, line_height: f32,
-    width: f32, height: f32
-) -> Vec<u8> {
-    use cosmic_text::{Attrs, Color, FontSystem, SwashCache, Buffer, Metrics, Shaping};
-    use imageproc::drawing::draw_filled_rect_mut;
-    use imageproc::rect::Rect;
-
-    let mut font_system = FontSystem::new();
-    let mut swash_cache = SwashCache::new();
-
-    let metrics = Metrics::new(font_size, line_height);
-
-    let mut buffer = Buffer::new(&mut font_system, metrics);
-    let mut buffer = buffer.borrow_with(&mut font_system);
-    buffer.set_size(width, height);
-
-    let attrs = Attrs::new();
-    buffer.set_text(s, attrs, Shaping::Advanced);
-    
-    buffer.shape_until_scroll();
-
-    let text_color = Color::rgb(0xFF, 0xFF, 0xFF);
-
-    let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
-    let bg = image::Rgba([255, 255, 255, 10]);
-    draw_filled_rect_mut(&mut imgbuf, 
-        Rect::at(0, 0).of_size(width as u32, height as u32), bg);
-    buffer.draw(&mut swash_cache, text_color, |x, y, w, h, color| {
-        let rgba = image::Rgba([color.r(), color.g(), color.b(), color.a()]);
-        draw_filled_rect_mut(&mut imgbuf, 
-            Rect::at(x, y).of_size(w, h), rgba);
-    });
-
-    let mut bytes: Vec<u8> = Vec::new();
-    imgbuf.write_to(
-        &mut Cursor::new(&mut bytes), 
-        image::ImageOutputFormat::Png).unwrap();
-    bytes
-}
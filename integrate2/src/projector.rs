@@ -0,0 +1,110 @@
+//! A ceiling projector throwing the text onto the floor instead of paint, to
+//! check the projector frame in the scene.
+
+use ana::projector::Projector;
+use bevy::prelude::*;
+
+use crate::FloorImages;
+
+/// A short-throw 1080p projector on a 3m ceiling, above the middle of the
+/// text and turned so the long side of its picture runs along the text.
+pub fn ceiling_projector() -> Projector {
+    let position = Vec3::new(0.0, 3.0, 0.4);
+    Projector::looking_at(
+        position,
+        position - Vec3::Y,
+        Vec3::X,
+        UVec2::new(1920, 1080),
+        0.5,
+        Vec2::ZERO,
+    )
+}
+
+/// Swaps the floor between the painted text and the projector's light.
+const TOGGLE_KEY: KeyCode = KeyCode::P;
+
+/// The projector and the two versions of the floor texture.
+#[derive(Resource)]
+pub struct ProjectorPreview {
+    projector: Projector,
+    painted: Handle<Image>,
+    projected: Handle<Image>,
+}
+
+/// The floor plane carrying the text texture.
+#[derive(Component)]
+pub struct FloorText;
+
+/// Marks the projector's body.
+#[derive(Component)]
+pub struct ProjectorMarker;
+
+pub fn spawn_projector(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    floor_images: Res<FloorImages>,
+) {
+    let projector = ceiling_projector();
+    commands.spawn((PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box::new(0.3, 0.1, 0.25))),
+        material: materials.add(Color::DARK_GRAY.into()),
+        transform: Transform::from_translation(projector.position)
+            .with_rotation(projector.rotation),
+        ..default()
+    }, ProjectorMarker));
+    commands.insert_resource(ProjectorPreview {
+        projector,
+        painted: floor_images.painted.clone(),
+        projected: floor_images.projected.clone(),
+    });
+}
+
+/// Outlines the cone of light down to the floor.
+pub fn draw_projector(
+    mut gizmos: Gizmos,
+    preview: Res<ProjectorPreview>,
+    query: Query<&GlobalTransform, With<ProjectorMarker>>,
+) {
+    let projector = &preview.projector;
+    for transform in &query {
+        let lens = transform.translation();
+        // scale each corner ray so it ends on the floor
+        let corners = projector.frame_corners(1.0).map(|corner| {
+            let direction = corner - lens;
+            if direction.y < 0.0 {
+                lens + direction * (lens.y / -direction.y)
+            } else {
+                corner
+            }
+        });
+        for corner in corners {
+            gizmos.line(lens, corner, Color::CYAN);
+        }
+        gizmos.linestrip(corners.into_iter().chain([corners[0]]), Color::CYAN);
+    }
+}
+
+pub fn toggle_projection(
+    input: Res<Input<KeyCode>>,
+    preview: Res<ProjectorPreview>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<&Handle<StandardMaterial>, With<FloorText>>,
+) {
+    if !input.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    for material in &query {
+        let Some(material) = materials.get_mut(material) else {
+            continue;
+        };
+        let projecting = material.base_color_texture.as_ref() == Some(&preview.projected);
+        material.base_color_texture = Some(if projecting {
+            info!("floor text painted");
+            preview.painted.clone()
+        } else {
+            info!("floor text projected");
+            preview.projected.clone()
+        });
+    }
+}
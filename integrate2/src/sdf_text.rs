@@ -0,0 +1,132 @@
+//! The floor text drawn from a signed distance field atlas instead of the
+//! painted bitmap, so its edges stay sharp from a grazing angle however close
+//! the camera gets.
+//!
+//! Each glyph's quad is cut into a grid whose corners go through the
+//! anamorphosis onto the floor. Within a cell the atlas is interpolated
+//! linearly, which is close enough at this size.
+
+use ana::sdf::SdfAtlas;
+use bevy::{prelude::*, reflect::{TypePath, TypeUuid}, render::{mesh::Indices, render_resource::{AsBindGroup, Extent3d, PrimitiveTopology, ShaderRef, TextureDimension, TextureFormat}}};
+
+use crate::projector::FloorText;
+
+/// Swaps the painted text for the distance field one.
+const TOGGLE_KEY: KeyCode = KeyCode::T;
+/// Grid cells along each side of a glyph's quad.
+const CELLS: u32 = 4;
+/// Just clear of the painted text.
+const LIFT: f32 = 0.002;
+
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "2c8f5e0b-93d4-4a1e-b7f6-5d0a6c3e9b27"]
+pub struct SdfMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub atlas: Handle<Image>,
+}
+
+impl Material for SdfMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/sdf.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+/// The distance field text, ready to spawn.
+#[derive(Resource)]
+pub struct SdfFloorText {
+    mesh: Mesh,
+    atlas: Image,
+}
+
+impl SdfFloorText {
+    /// `atlas`'s quads laid on the world by `to_world`, which takes the
+    /// text's pixels.
+    pub fn new(atlas: &SdfAtlas, to_world: impl Fn(Vec2) -> Option<Vec3>) -> Self {
+        let (mut positions, mut uvs, mut colors, mut indices) = (vec![], vec![], vec![], vec![]);
+        for quad in &atlas.quads {
+            let [a, b, _, d] = quad.corners;
+            let [ua, ub, _, ud] = quad.uvs;
+            let grid: Option<Vec<Vec3>> = (0..=CELLS)
+                .flat_map(|j| (0..=CELLS).map(move |i| Vec2::new(i as f32, j as f32) / CELLS as f32))
+                .map(|t| to_world(a + (b - a) * t.x + (d - a) * t.y))
+                .collect();
+            // quads the eye sees through the floor are left out
+            let Some(grid) = grid else {
+                continue;
+            };
+            let first = positions.len() as u32;
+            let color = Color::rgba_u8(quad.color[0], quad.color[1], quad.color[2], quad.color[3]).as_linear_rgba_f32();
+            for (k, position) in grid.iter().enumerate() {
+                let t = UVec2::new(k as u32 % (CELLS + 1), k as u32 / (CELLS + 1)).as_vec2() / CELLS as f32;
+                positions.push((*position + Vec3::Y * LIFT).to_array());
+                uvs.push((ua + (ub - ua) * t.x + (ud - ua) * t.y).to_array());
+                colors.push(color);
+            }
+            for j in 0..CELLS {
+                for i in 0..CELLS {
+                    let corner = first + j * (CELLS + 1) + i;
+                    let below = corner + CELLS + 1;
+                    indices.extend([corner, below, corner + 1, corner + 1, below, below + 1]);
+                }
+            }
+        }
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; positions.len()]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+
+        let size = Extent3d {
+            width: atlas.image.width(),
+            height: atlas.image.height(),
+            ..default()
+        };
+        // distances, not colours, so not sRGB
+        let atlas = Image::new(size, TextureDimension::D2, atlas.image.clone().into_raw(), TextureFormat::R8Unorm);
+        Self { mesh, atlas }
+    }
+}
+
+/// Marks the distance field text.
+#[derive(Component)]
+pub struct SdfText;
+
+pub fn spawn_sdf_text(
+    mut commands: Commands,
+    text: Res<SdfFloorText>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<SdfMaterial>>,
+) {
+    commands.spawn((MaterialMeshBundle {
+        mesh: meshes.add(text.mesh.clone()),
+        material: materials.add(SdfMaterial { atlas: images.add(text.atlas.clone()) }),
+        visibility: Visibility::Hidden,
+        ..default()
+    }, SdfText));
+}
+
+#[allow(clippy::type_complexity)]
+pub fn toggle_sdf_text(
+    input: Res<Input<KeyCode>>,
+    mut sdf_text: Query<&mut Visibility, (With<SdfText>, Without<FloorText>)>,
+    mut floor_text: Query<&mut Visibility, (With<FloorText>, Without<SdfText>)>,
+) {
+    if !input.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    let on = sdf_text.iter().any(|visibility| *visibility == Visibility::Hidden);
+    for mut visibility in &mut sdf_text {
+        *visibility = if on { Visibility::Inherited } else { Visibility::Hidden };
+    }
+    for mut visibility in &mut floor_text {
+        *visibility = if on { Visibility::Hidden } else { Visibility::Inherited };
+    }
+    info!("{} text", if on { "distance field" } else { "painted" });
+}
@@ -0,0 +1,120 @@
+//! A cut-out hung between the light and the floor, whose shadow falls on
+//! the painted text so it reads from the eye without any paint.
+
+use std::{fs, io};
+
+use ana::projection::{AnamorphicLayout, Homography, PlaneRect};
+use ana::shadow::{Occluder, ShadowError};
+use ana::vector::PathCommand;
+use bevy::prelude::*;
+
+use crate::bake::to_bevy_mesh;
+use crate::{Light, VECTOR_TOLERANCE};
+
+/// Re-cuts the occluder for wherever the light is now.
+const RECUT_KEY: KeyCode = KeyCode::O;
+/// How high the cut-out hangs.
+const HEIGHT: f32 = 2.0;
+/// Thin acrylic: with the light this low, the edges of a thicker sheet would
+/// cast a shadow of their own and fatten the letters.
+const THICKNESS: f32 = 0.003;
+const SVG: &str = "assets/occluder.svg";
+
+/// The text to cast, and the cut-out currently casting it.
+#[derive(Resource)]
+pub struct ShadowCutOut {
+    outlines: Vec<Vec<PathCommand>>,
+    /// Takes outline coordinates to picture coordinates.
+    text_scale: Vec2,
+    picture_to_target: Homography,
+    target: PlaneRect,
+    occluder: Occluder,
+    /// Whether re-cuts also rewrite the outline, as with `--export`.
+    export: bool,
+}
+
+/// Marks the cut-out.
+#[derive(Component)]
+pub struct OccluderMarker;
+
+impl ShadowCutOut {
+    pub fn new(
+        outlines: Vec<Vec<PathCommand>>,
+        text_scale: Vec2,
+        layout: &AnamorphicLayout,
+        light: Vec3,
+        export: bool,
+    ) -> Result<Self, ShadowError> {
+        let occluder = Occluder { plane: layout.target, contours: vec![] };
+        let mut cut_out = Self {
+            outlines,
+            text_scale,
+            picture_to_target: layout.picture_to_target,
+            target: layout.target,
+            occluder,
+            export,
+        };
+        cut_out.cut(light)?;
+        Ok(cut_out)
+    }
+
+    fn cut(&mut self, light: Vec3) -> Result<(), ShadowError> {
+        let plane = PlaneRect::ground(Vec3::Y * HEIGHT, self.target.size);
+        self.occluder = Occluder::cut(light, plane, &self.target, &self.outlines,
+            |p| self.picture_to_target.apply(p * self.text_scale), VECTOR_TOLERANCE)?;
+        Ok(())
+    }
+
+    /// Writes the outline for the laser cutter.
+    pub fn save_svg(&self) -> io::Result<()> {
+        fs::write(SVG, self.occluder.svg())
+    }
+}
+
+pub fn spawn_occluder(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cut_out: Res<ShadowCutOut>,
+) {
+    commands.spawn((PbrBundle {
+        mesh: meshes.add(to_bevy_mesh(&cut_out.occluder.mesh(THICKNESS))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgb(0.75, 0.6, 0.4),
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        }),
+        ..default()
+    }, OccluderMarker));
+}
+
+pub fn recut_occluder(
+    input: Res<Input<KeyCode>>,
+    mut cut_out: ResMut<ShadowCutOut>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    lights: Query<&Transform, With<Light>>,
+    query: Query<&Handle<Mesh>, With<OccluderMarker>>,
+) {
+    if !input.just_pressed(RECUT_KEY) {
+        return;
+    }
+    let Ok(light) = lights.get_single() else {
+        return;
+    };
+    if let Err(e) = cut_out.cut(light.translation) {
+        warn!("cannot cut the occluder: {e}");
+        return;
+    }
+    info!("occluder cut for the light at {}", light.translation);
+    if cut_out.export {
+        if let Err(e) = cut_out.save_svg() {
+            warn!("could not write {SVG}: {e}");
+        }
+    }
+    for mesh in &query {
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            *mesh = to_bevy_mesh(&cut_out.occluder.mesh(THICKNESS));
+        }
+    }
+}
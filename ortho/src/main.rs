@@ -1,7 +1,22 @@
 //! Shows how to create a 3D orthographic view (for isometric-look games or CAD applications).
+//! The text is split across the floor and the cube faces so it reads from a single eye position.
 
-use bevy::{prelude::*, render::camera::ScalingMode};
+use ana::fonts::FontRegistry;
+use ana::projection::PlaneRect;
+use ana::rich::{RichText, Span, TextStyle};
+use ana::surfaces::{box_faces, MultiSurfaceLayout, Surface, SurfaceTexture};
+use ana::warp::{Filter, WarpOptions};
+use bevy::{prelude::*, render::{camera::ScalingMode, render_resource::{Extent3d, TextureDimension, TextureFormat}}};
 use bevy_panorbit_camera::{PanOrbitCameraPlugin,PanOrbitCamera};
+use image::{Rgba, RgbaImage};
+
+const FLOOR_SIZE: f32 = 5.0;
+const CUBE_CENTERS: [Vec3; 4] = [
+    Vec3::new(1.5, 0.5, 1.5),
+    Vec3::new(1.5, 0.5, -1.5),
+    Vec3::new(-1.5, 0.5, 1.5),
+    Vec3::new(-1.5, 0.5, -1.5),
+];
 
 fn main() {
     App::new()
@@ -16,6 +31,7 @@ fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
     commands.spawn((
         Camera3dBundle {
@@ -30,49 +46,91 @@ fn setup(
         },
         PanOrbitCamera::default(),
     ));
-    
+
 
     // plane
     commands.spawn(PbrBundle {
-        mesh: meshes.add(shape::Plane::from_size(5.0).into()),
+        mesh: meshes.add(shape::Plane::from_size(FLOOR_SIZE).into()),
         material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
         ..default()
     });
     // cubes
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
-        material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-        transform: Transform::from_xyz(1.5, 0.5, 1.5),
-        ..default()
-    });
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
-        material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-        transform: Transform::from_xyz(1.5, 0.5, -1.5),
-        ..default()
-    });
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
-        material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-        transform: Transform::from_xyz(-1.5, 0.5, 1.5),
-        ..default()
-    });
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
-        material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-        transform: Transform::from_xyz(-1.5, 0.5, -1.5),
-        ..default()
-    });
+    for center in CUBE_CENTERS {
+        commands.spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
+            material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
+            transform: Transform::from_translation(center),
+            ..default()
+        });
+    }
     // light
     let light_position = Transform::from_xyz(3.0, 8.0, 5.0);
     commands.spawn(PointLightBundle {
-        transform: light_position.clone(),
+        transform: light_position,
         ..default()
     });
     commands.spawn(PbrBundle {
         mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
         material: materials.add(Color::WHITE.into()),
-        transform: light_position.clone(),
+        transform: light_position,
         ..default()
     });
+
+    // text spread over the floor and the near cubes, reading from the eye
+    let eye = Vec3::new(0.0, 1.6, 4.0);
+    let mut surfaces = vec![
+        Surface::new("floor", PlaneRect::ground(Vec3::ZERO, Vec2::splat(FLOOR_SIZE))),
+    ];
+    for (i, center) in CUBE_CENTERS.iter().enumerate() {
+        surfaces.extend(box_faces(&format!("cube{i}"), *center, Vec3::ONE));
+    }
+    let picture = PlaneRect::facing(eye, Vec3::new(0.0, 0.4, 0.0), Vec2::new(3.6, 1.2));
+    let layout = MultiSurfaceLayout::new(eye, picture, surfaces);
+
+    let style = TextStyle { size: 28.0, ..default() };
+    let text = RichText::layout(&mut FontRegistry::bundled(), &[Span::new("hello from every surface", style)],
+        40.0 / 28.0, 300.0).expect("the bundled fonts set any text");
+    // faintly tinted, so the whole picture shows up on the surfaces
+    let mut text_image = RgbaImage::from_pixel(300, 100, Rgba([255, 255, 255, 10]));
+    text.draw(&mut text_image, Vec2::ZERO);
+    let warp_options = WarpOptions {
+        filter: Filter::Bicubic,
+        supersample: 2,
+    };
+    let textures = layout.render(&text_image, 200.0, warp_options);
+
+    for (surface, texture) in layout.surfaces.iter().zip(textures) {
+        if texture.image.pixels().all(|p| p[3] == 0) {
+            continue;
+        }
+        let rect = surface.rect;
+        commands.spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::new(rect.size))),
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(images.add(to_bevy_image(texture))),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            transform: quad_transform(&rect),
+            ..default()
+        });
+    }
+}
+
+/// Places a `shape::Quad` over `rect`, so the quad's UVs line up with the
+/// rectangle's coordinates. It is lifted just clear of the surface it covers.
+fn quad_transform(rect: &PlaneRect) -> Transform {
+    let rotation = Quat::from_mat3(&Mat3::from_cols(rect.u_axis, -rect.v_axis, -rect.normal()));
+    Transform::from_translation(rect.center() - rect.normal() * 0.001)
+        .with_rotation(rotation)
+}
+
+fn to_bevy_image(texture: SurfaceTexture) -> Image {
+    let size = Extent3d {
+        width: texture.image.width(),
+        height: texture.image.height(),
+        ..default()
+    };
+    Image::new(size, TextureDimension::D2, texture.image.into_raw(), TextureFormat::Rgba8UnormSrgb)
 }
@@ -0,0 +1,45 @@
+//! Splits a warped floor image into sheets for printing at full size.
+//!
+//! Usage: `poster <floor.png> <width in metres> [dpi] [a4|a3]`. The floor image
+//! is cropped to its visible part first, and the sheets are written as
+//! `sheet-A1.png`, `sheet-B1.png`, ... with their positions on the floor
+//! printed in the margin.
+
+use std::{env, error::Error};
+
+use ana::poster::{crop_to_content, tile_poster, Paper, PosterOptions};
+use glam::Vec2;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (Some(path), Some(width)) = (args.next(), args.next()) else {
+        return Err("usage: poster <floor.png> <width in metres> [dpi] [a4|a3]".into());
+    };
+    let floor = image::open(path)?.into_rgba8();
+    let width: f32 = width.parse()?;
+    let dpi: f32 = args.next().map(|dpi| dpi.parse()).transpose()?.unwrap_or(150.0);
+    let paper = match args.next().as_deref() {
+        None | Some("a4") => Paper::A4,
+        Some("a3") => Paper::A3,
+        Some(other) => return Err(format!("unknown paper size {other}").into()),
+    };
+
+    let metres_per_pixel = width / floor.width() as f32;
+    let Some((artwork, offset)) = crop_to_content(&floor) else {
+        return Err("the floor image is empty".into());
+    };
+    let size = Vec2::new(artwork.width() as f32, artwork.height() as f32) * metres_per_pixel;
+    let options = PosterOptions {
+        paper,
+        dpi,
+        origin: offset * metres_per_pixel,
+        ..Default::default()
+    };
+
+    let sheets = tile_poster(&artwork, size, &options)?;
+    for sheet in &sheets {
+        sheet.save(format!("sheet-{}.png", sheet.name()), dpi)?;
+    }
+    println!("{:.3} x {:.3} m on {} sheets", size.x, size.y, sheets.len());
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Chalk-grid stencils for painting a warped floor image by hand.
+//!
+//! Usage: `stencil <floor.png> <width in metres> [cell size in cm]`. The floor
+//! image is cropped to its visible part, then `grid.png` shows it with the
+//! labelled grid and `cells-1.png`, `cells-2.png`, ... show each cell with
+//! anything in it at a larger scale.
+
+use std::{env, error::Error};
+
+use ana::poster::crop_to_content;
+use ana::stencil::{Stencil, StencilOptions};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (Some(path), Some(width)) = (args.next(), args.next()) else {
+        return Err("usage: stencil <floor.png> <width in metres> [cell size in cm]".into());
+    };
+    let floor = image::open(path)?.into_rgba8();
+    let pixels_per_metre = floor.width() as f32 / width.parse::<f32>()?;
+    let cell_cm: f32 = args.next().map(|cm| cm.parse()).transpose()?.unwrap_or(20.0);
+
+    let Some((artwork, offset)) = crop_to_content(&floor) else {
+        return Err("the floor image is empty".into());
+    };
+    let stencil = Stencil::new(&artwork, pixels_per_metre, StencilOptions {
+        cell_cm,
+        ..Default::default()
+    })?;
+
+    stencil.overview().save("grid.png")?;
+    let sheets = stencil.cell_sheets();
+    for (i, sheet) in sheets.iter().enumerate() {
+        sheet.save(format!("cells-{}.png", i + 1))?;
+    }
+
+    let origin = offset / pixels_per_metre;
+    println!("{} x {} cells of {} cm, on {} sheets; the grid's top left corner is {:.3} m, {:.3} m from the floor image's",
+        stencil.columns, stencil.rows, cell_cm, sheets.len(), origin.x, origin.y);
+    Ok(())
+}